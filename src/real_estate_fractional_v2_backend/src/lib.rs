@@ -1,14 +1,40 @@
 use candid::{CandidType, Deserialize, Principal};
-use ic_cdk::api::caller;
+use ic_cdk::init;
+use ic_cdk::post_upgrade;
+use ic_cdk::pre_upgrade;
 use ic_cdk::query;
 use ic_cdk::update;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::cell::RefCell;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::Duration;
+use ic_cdk_timers::TimerId;
+use sha2::{Digest, Sha256};
+
+// Upper bound on items returned by any paged query
+const MAX_PAGE_SIZE: u64 = 100;
+// Largest supported share precision; 10^18 still fits comfortably in a u64
+const MAX_DECIMALS: u8 = 18;
+// Fixed-point scale of the per-share income accumulator
+const INCOME_SCALE: u128 = 1_000_000_000_000_000_000;
+// Longest transfer memo accepted, matching the ICRC-1 limit
+const MAX_MEMO_LEN: usize = 32;
+// Longest proposal comment accepted, in bytes
+const MAX_COMMENT_LEN: usize = 2000;
+// Method invoked on subscriber canisters with the recorded `Event`
+const NOTIFY_METHOD: &str = "on_rwa_event";
+// Price unit assumed for listings that don't name one
+const DEFAULT_CURRENCY: &str = "ICP";
+const DEFAULT_PRICE_DECIMALS: u8 = 8;
+// Longest currency code accepted on a listing
+const MAX_CURRENCY_LEN: usize = 10;
+// How long a proposed role change waits for the target to accept it
+const ROLE_HANDOVER_WINDOW_SECS: u64 = 7 * 24 * 60 * 60;
 
 // Types
 pub type PropertyId = u64;
 pub type UserId = String; // For now, use Principal as String
+type BalanceHistory = HashMap<(PropertyId, Principal), Vec<(u64, u64)>>; // (timestamp, balance) per holder
+type IncomeSegments = HashMap<(PropertyId, Principal), Vec<(u64, u64)>>; // (income mark index, amount) per holder
 
 #[derive(CandidType, Deserialize, Clone, PartialEq, Eq, Hash, Debug)]
 pub enum Role {
@@ -17,28 +43,173 @@ pub enum Role {
     User,
 }
 
+/// A role change waiting for its target principal to accept it.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct PendingRole {
+    pub role: Role,
+    pub proposed_by: Principal,
+    pub expires_at: u64,
+}
+
 #[derive(CandidType, Deserialize, Clone)]
 pub struct PropertyMetadata {
     pub location: String,
     pub description: String,
+    pub tags: Vec<String>, // stored trimmed, lowercased and deduplicated
+    pub country: String,
+    pub city: String,
+    pub lat: Option<f64>,
+    pub lng: Option<f64>,
+}
+
+/// A tranche of a property's shares with its own weight in income distribution.
+/// A property's class totals sum to its `total_shares`.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ShareClass {
+    pub name: String,
+    pub total: u64,
+    pub income_weight_bps: u16, // class's cut of each deposit; weights sum to 10000
 }
 
 #[derive(CandidType, Deserialize, Clone)]
 pub struct Property {
     pub id: PropertyId,
     pub name: String,
+    // All share amounts are integers in base units: one display share is 10^decimals units
     pub total_shares: u64,
     pub shares_available: u64,
+    pub decimals: u8,
     pub metadata: PropertyMetadata,
     pub status: PropertyStatus,
+    pub max_ownership_bps: Option<u16>, // per-holder cap as a share of total_shares
+    pub frozen: bool, // blocks share movement and income claims, e.g. during legal disputes
+    pub transfers_paused: bool, // set by shareholder vote; blocks secondary transfers only
+    pub fully_subscribed_at: Option<u64>, // when shares_available first reached zero (ns)
+    pub share_classes: Vec<ShareClass>, // empty for a single class of common shares
 }
 
 #[derive(CandidType, Deserialize, Clone)]
 pub struct Listing {
+    pub listing_id: u64,
+    pub property_id: PropertyId,
+    pub seller: Principal,
+    pub amount: u64,
+    pub price_per_share: u64,
+    // Display context for price_per_share: one `currency` unit is 10^price_decimals.
+    // Settlement always uses the internal BALANCES ledger.
+    pub currency: String,
+    pub price_decimals: u8,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct PropertyPage {
+    pub properties: Vec<Property>,
+    pub total: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ListingPage {
+    pub listings: Vec<Listing>,
+    pub total: u64,
+}
+
+/// Witness over the certified property tree, in the IC hash tree format.
+/// Clients rebuild the root hash from it and compare it to the certificate.
+#[derive(CandidType, Deserialize, Clone)]
+pub enum HashTree {
+    Empty,
+    Fork(Box<HashTree>, Box<HashTree>),
+    Labeled(Vec<u8>, Box<HashTree>),
+    Leaf(Vec<u8>),
+    Pruned(Vec<u8>),
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct CertifiedProperty {
+    pub property: Property,
+    pub holders: Vec<(Principal, u64)>, // sorted by principal
+    pub certificate: Vec<u8>,
+    pub witness: HashTree,
+}
+
+/// ICRC-1 metadata value, so each property can be read as a token by wallets.
+#[derive(CandidType, Deserialize, Clone)]
+pub enum MetadataValue {
+    Nat(candid::Nat),
+    Int(candid::Int),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct RecurringIncome {
+    pub property_id: PropertyId,
+    pub amount: u64,
+    pub interval_secs: u64,
+    pub scheduled_by: Principal,
+}
+
+/// An issuer offer to repurchase shares at a fixed price, escrowed from the treasury.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Buyback {
+    pub property_id: PropertyId,
+    pub price_per_share: u64,
+    pub max_shares: u64,
+    pub remaining: u64, // shares still accepted; remaining * price_per_share is held in escrow
+    pub opened_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Statistics {
+    pub total_properties: u64,
+    pub total_shares_issued: u64,
+    pub total_holders: u64, // distinct principals holding shares in any property
+    pub total_income_deposited: u64,
+    pub total_unclaimed_income: u64,
+    pub open_proposals: u64,
+    pub marketplace_listings: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct CycleStatus {
+    pub balance: u64,
+    pub threshold: u64,
+    pub healthy: bool, // balance is at or above the threshold
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct TransferRecord {
+    pub tx_index: u64,
+    pub property_id: PropertyId,
+    pub from: Principal,
+    pub to: Principal,
+    pub amount: u64,
+    pub timestamp: u64,
+    pub memo: Option<Vec<u8>>,
+    pub fee: u64, // shares withheld as transfer tax; `to` received amount - fee
+    pub class_index: Option<u32>, // class moved on a share-class property
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Trade {
     pub property_id: PropertyId,
+    pub buyer: Principal,
     pub seller: Principal,
     pub amount: u64,
     pub price_per_share: u64,
+    pub timestamp: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct TradePage {
+    pub trades: Vec<Trade>,
+    pub total: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct OrderBook {
+    pub property_id: PropertyId,
+    pub levels: Vec<(u64, u64)>, // (price_per_share, total_amount), cheapest first
 }
 
 // Ensure PropertyStatus is defined at the top level
@@ -55,6 +226,37 @@ pub enum ProposalStatus {
     Approved,
     Rejected,
     Executed,
+    Cancelled, // withdrawn by the proposer or an admin while open
+}
+
+/// State change applied to the proposal's property when it is executed.
+#[derive(CandidType, Deserialize, Clone)]
+pub enum ProposalAction {
+    UpdateStatus(PropertyStatus),
+    UpdateMetadata(PropertyMetadata),
+    DistributeReserve(u64), // amount moved from the reserve to holders as rental income
+    SetTransfersPaused(bool),
+    Rename(String),
+}
+
+/// Payload-free counterpart of `ProposalAction`, used to key per-action
+/// approval thresholds. `Signal` covers proposals with no action.
+#[derive(CandidType, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ProposalKind {
+    Signal,
+    UpdateStatus,
+    UpdateMetadata,
+    DistributeReserve,
+    SetTransfersPaused,
+    Rename,
+}
+
+/// What happens to the integer-division dust of an income deposit.
+#[derive(CandidType, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RoundingMode {
+    Floor,            // holders are rounded down; dust is carried into the next deposit
+    HalfUp,           // holders are rounded to nearest, funded by the deposit's own dust; the rest goes to the reserve
+    ReserveRemainder, // holders are rounded down; dust goes to the property reserve
 }
 
 #[derive(CandidType, Deserialize, Clone)]
@@ -63,10 +265,19 @@ pub struct Proposal {
     pub property_id: PropertyId,
     pub proposer: Principal,
     pub description: String,
+    pub action: Option<ProposalAction>,
     pub status: ProposalStatus,
     pub yes_votes: u64,
     pub no_votes: u64,
     pub votes: HashMap<Principal, bool>, // true = yes, false = no
+    pub vote_weights: HashMap<Principal, u64>, // weight counted for each voter's current vote
+    pub voting_deadline_ns: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ProposalPage {
+    pub proposals: Vec<Proposal>,
+    pub total: u64,
 }
 
 #[derive(CandidType, Deserialize, Clone)]
@@ -76,6 +287,22 @@ pub struct OwnershipRecord {
     pub shares: u64,
 }
 
+#[derive(CandidType, Deserialize, Clone)]
+pub struct PortfolioPosition {
+    pub property_id: PropertyId,
+    pub property_name: String,
+    pub shares: u64,
+    pub unclaimed_income: u64,
+    pub estimated_value: u64, // shares' pro-rata slice of the latest valuation; 0 if never valued
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Portfolio {
+    pub positions: Vec<PortfolioPosition>,
+    pub total_unclaimed_income: u64,
+    pub total_estimated_value: u64,
+}
+
 #[derive(CandidType, Deserialize, Clone)]
 pub struct RentalIncomeRecord {
     pub property_id: PropertyId,
@@ -84,417 +311,3812 @@ pub struct RentalIncomeRecord {
 }
 
 #[derive(CandidType, Deserialize, Clone)]
+pub struct ValuationRecord {
+    pub timestamp: u64,
+    pub valuation: u64,
+    pub price_per_share: u64, // valuation / total_shares
+}
+
+/// A legal document kept off-chain; `sha256` lets clients verify the file at `uri`.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct DocRef {
+    pub name: String,
+    pub uri: String,
+    pub sha256: [u8; 32],
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ListingFill {
+    pub listing_id: u64,
+    pub seller: Principal,
+    pub amount: u64,
+    pub price_per_share: u64,
+    pub cost: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct MarketFill {
+    pub filled: u64,
+    pub total_cost: u64,
+    pub fills: Vec<ListingFill>,
+}
+
+/// Why a marketplace purchase would be refused, so `can_buy_shares` clients
+/// can branch on the cause. Displays as the message `buy_shares` returns.
+#[derive(CandidType, Deserialize, Clone, PartialEq, Debug)]
+pub enum RwaError {
+    Paused,
+    NotKycVerified,
+    ListingUnavailable, // no such listing, or it or its seller is short of shares
+    OwnListing,
+    Blacklisted,
+    NotWhitelisted,
+    PropertyFrozen,
+    TransfersPaused,
+    SharesLocked { until: u64 },
+    OwnershipCapExceeded,
+    TradeValueOverflow,
+    InsufficientFunds,
+}
+
+impl std::fmt::Display for RwaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RwaError::Paused => write!(f, "Canister is paused"),
+            RwaError::NotKycVerified => write!(f, "Recipient is not KYC verified"),
+            RwaError::ListingUnavailable => write!(f, "Listing not found or insufficient shares"),
+            RwaError::OwnListing => write!(f, "Cannot buy your own listing"),
+            RwaError::Blacklisted => write!(f, "Principal is blacklisted"),
+            RwaError::NotWhitelisted => write!(f, "Recipient is not whitelisted for this property"),
+            RwaError::PropertyFrozen => write!(f, "Property is frozen"),
+            RwaError::TransfersPaused => write!(f, "Transfers are paused for this property"),
+            RwaError::SharesLocked { until } => write!(f, "Shares are locked until {}", until),
+            RwaError::OwnershipCapExceeded => write!(f, "Exceeds ownership cap"),
+            RwaError::TradeValueOverflow => write!(f, "Trade value overflow"),
+            RwaError::InsufficientFunds => write!(f, "Insufficient funds"),
+        }
+    }
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct PriceBounds {
+    pub min_price_per_share: u64,
+    pub max_price_per_share: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct IncomeSummary {
+    pub total_deposited: u64,
+    pub total_unclaimed: u64,
+    pub total_claimed: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct DistributionPreview {
+    pub allocations: Vec<(Principal, u64)>,
+    pub remainder: u64, // rounding dust and unissued shares' portion, routed to the reserve
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ProposalStats {
+    pub yes_votes: u64,
+    pub no_votes: u64,
+    pub participating_shares: u64,
+    pub total_shares: u64,
+    pub turnout_bps: u64, // participating_shares as a share of total_shares
+    pub voter_count: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct VoteRecord {
+    pub voter: Principal,
+    pub choice: bool, // true = yes, false = no
+    pub weight: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct Comment {
+    pub author: Principal,
+    pub timestamp: u64,
+    pub text: String,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ClaimRecord {
+    pub property_id: PropertyId,
+    pub amount: u64,
+    pub timestamp: u64, // nanoseconds, from ic_cdk::api::time()
+}
+
+/// Kind of an audit log entry (the `EventKind` of the audit log request).
+#[derive(CandidType, Deserialize, Clone, PartialEq)]
 pub enum EventType {
     PropertyRegistered,
+    PropertyArchived,
     PropertyMetadataUpdated,
+    PropertyRenamed,
     PropertyStatusUpdated,
+    PropertyFrozenSet,
+    TransfersPausedSet,
     SharesIssued,
+    PropertyFullySubscribed,
+    OfferingCancelled,
     SharesTransferred,
+    SharesApproved,
+    SharesForceTransferred,
+    ShareClassesSet,
     SharesListedForSale,
     SharesBought,
+    FundsDeposited,
+    FundsWithdrawn,
+    MarketFeeSet,
+    TransferFeeSet,
+    PriceBoundsSet,
+    ValuationRecorded,
+    DocumentAdded,
+    SaleProceedsSet,
+    SharesRedeemed,
+    BuybackOpened,
+    BuybackClosed,
+    SharesSoldToBuyback,
+    SharesBurned,
+    ListingCancelled,
     RentalIncomeDeposited,
     RentalIncomeClaimed,
+    RecurringIncomeScheduled,
+    RecurringIncomeCancelled,
+    ReserveDeposited,
+    ReserveSpent,
     ProposalSubmitted,
     ProposalVoted,
+    ProposalCommented,
     ProposalExecuted,
+    ProposalRejected,
+    ProposalCancelled,
+    VoteDelegated,
+    DelegationRevoked,
     RoleSet,
+    RoleProposed,
+    AdminAdded,
+    AdminRemoved,
     KycStatusSet,
+    BlacklistSet,
+    WhitelistUpdated,
+    AdminBootstrapped,
+    StrictKycSet,
+    PausedSet,
+    QuorumSet,
+    ApprovalThresholdSet,
+    MinProposalStakeSet,
+    CycleThresholdSet,
+    MaxListingsSet,
+    RoundingModeSet,
+    ClaimWindowSet,
+    IncomeExpired,
+    ExpiredIncomeSwept,
+    NotifierRegistered,
+    NotifierRemoved,
 }
 
+/// One entry in the audit log (the `AuditEvent` of the audit log request). The
+/// type and field names predate the log and are kept: stored snapshots and
+/// existing clients decode them by name.
 #[derive(CandidType, Deserialize, Clone)]
 pub struct Event {
+    pub id: u64,
     pub event_type: EventType,
     pub timestamp: u64,
     pub actor: Principal,
     pub details: String,
+    pub property_id: Option<PropertyId>, // set for events about a single property
+}
+
+/// One entry in a property's activity feed.
+#[derive(CandidType, Deserialize, Clone)]
+pub struct ActivityItem {
+    pub timestamp: u64,
+    pub event_type: EventType,
+    pub actor: Principal,
+    pub details: String,
 }
 
 thread_local! {
     static PROPERTIES: RefCell<HashMap<PropertyId, Property>> = RefCell::new(HashMap::new());
+    static ARCHIVED_PROPERTIES: RefCell<HashMap<PropertyId, Property>> = RefCell::new(HashMap::new()); // removed before any issuance
     static OWNERSHIP: RefCell<HashMap<(PropertyId, Principal), u64>> = RefCell::new(HashMap::new());
-    static NEXT_PROPERTY_ID: RefCell<PropertyId> = RefCell::new(1);
+    // Reverse indexes over OWNERSHIP, holding only non-zero balances. Rebuilt on upgrade.
+    static HOLDERS_BY_PROPERTY: RefCell<HashMap<PropertyId, HashSet<Principal>>> = RefCell::new(HashMap::new());
+    static PROPERTIES_BY_HOLDER: RefCell<HashMap<Principal, HashSet<PropertyId>>> = RefCell::new(HashMap::new());
+    // Present only for private offerings; an empty set admits nobody
+    static BUYER_WHITELISTS: RefCell<HashMap<PropertyId, HashSet<Principal>>> = RefCell::new(HashMap::new());
+    // Lowercased property name -> id over live PROPERTIES. Rebuilt on upgrade.
+    static NAME_INDEX: RefCell<HashMap<String, PropertyId>> = RefCell::new(HashMap::new());
+    static NEXT_PROPERTY_ID: RefCell<PropertyId> = const { RefCell::new(1) };
+    static BALANCE_HISTORY: RefCell<BalanceHistory> = RefCell::new(HashMap::new()); // append-only
+    static ALLOWANCES: RefCell<HashMap<(PropertyId, Principal, Principal), u64>> = RefCell::new(HashMap::new()); // (property, owner, spender)
+    static LOCKUPS: RefCell<HashMap<(PropertyId, Principal), u64>> = RefCell::new(HashMap::new()); // unlock time (ns)
+    static VALUATIONS: RefCell<HashMap<PropertyId, Vec<(u64, u64)>>> = RefCell::new(HashMap::new()); // (timestamp, valuation)
+    static DOCUMENTS: RefCell<HashMap<PropertyId, Vec<DocRef>>> = RefCell::new(HashMap::new());
+    static RESERVES: RefCell<HashMap<PropertyId, u64>> = RefCell::new(HashMap::new()); // maintenance funds, not distributable income
+    static ROUNDING_MODE: RefCell<RoundingMode> = const { RefCell::new(RoundingMode::ReserveRemainder) };
+    static INCOME_CARRY: RefCell<HashMap<PropertyId, u64>> = RefCell::new(HashMap::new()); // dust held back under RoundingMode::Floor
+    static CLAIM_WINDOWS: RefCell<HashMap<PropertyId, u64>> = RefCell::new(HashMap::new()); // seconds income stays claimable
+    static INCOME_MARKS: RefCell<HashMap<PropertyId, Vec<IncomeMark>>> = RefCell::new(HashMap::new()); // one per deposit under a claim window
+    static INCOME_SEGMENTS: RefCell<IncomeSegments> = RefCell::new(HashMap::new()); // settled income tagged with the deposit it came from
+    static SALE_PROCEEDS: RefCell<HashMap<PropertyId, u64>> = RefCell::new(HashMap::new()); // total paid out on sale
+    static REDEEMED_PROCEEDS: RefCell<HashMap<PropertyId, u64>> = RefCell::new(HashMap::new()); // sale proceeds already paid to redeemers
     static RENTAL_INCOME: RefCell<HashMap<PropertyId, u64>> = RefCell::new(HashMap::new()); // total deposited
-    static UNCLAIMED_INCOME: RefCell<HashMap<(PropertyId, Principal), u64>> = RefCell::new(HashMap::new()); // per user
-    static MARKETPLACE: RefCell<Vec<Listing>> = RefCell::new(Vec::new());
-    static ADMINS: RefCell<Vec<Principal>> = RefCell::new(vec![Principal::anonymous()]);
+    static DISTRIBUTED_INCOME: RefCell<HashMap<PropertyId, u64>> = RefCell::new(HashMap::new()); // total allocated to holders
+    static UNCLAIMED_INCOME: RefCell<HashMap<(PropertyId, Principal), u64>> = RefCell::new(HashMap::new()); // per user, settled
+    static INCOME_PER_SHARE: RefCell<HashMap<PropertyId, u128>> = RefCell::new(HashMap::new()); // cumulative income per share, scaled by INCOME_SCALE
+    static INCOME_CHECKPOINTS: RefCell<HashMap<(PropertyId, Principal), u128>> = RefCell::new(HashMap::new()); // accumulator value at last settlement
+    static INCOME_REMAINDERS: RefCell<HashMap<(PropertyId, Principal), u128>> = RefCell::new(HashMap::new()); // settled accrual below one unit, scaled by INCOME_SCALE
+    static DISTRIBUTED_FRACTION: RefCell<HashMap<PropertyId, u128>> = RefCell::new(HashMap::new()); // scaled accrual not yet counted in DISTRIBUTED_INCOME
+    // Per-class holdings of properties with share classes; OWNERSHIP keeps each holder's total across classes
+    static CLASS_OWNERSHIP: RefCell<HashMap<(PropertyId, u32, Principal), u64>> = RefCell::new(HashMap::new());
+    // Shares of each class held by anyone; derived from CLASS_OWNERSHIP, rebuilt on upgrade
+    static CLASS_ISSUED: RefCell<HashMap<(PropertyId, u32), u64>> = RefCell::new(HashMap::new());
+    static INCOME_PER_CLASS_SHARE: RefCell<HashMap<(PropertyId, u32), u128>> = RefCell::new(HashMap::new());
+    static CLASS_INCOME_CHECKPOINTS: RefCell<HashMap<(PropertyId, u32, Principal), u128>> = RefCell::new(HashMap::new());
+    static CLAIMED_INCOME: RefCell<HashMap<(PropertyId, Principal), u64>> = RefCell::new(HashMap::new()); // total claimed per user
+    static CLAIM_HISTORY: RefCell<HashMap<Principal, Vec<ClaimRecord>>> = RefCell::new(HashMap::new());
+    // Leaf digests of the certified property tree; rebuilt on upgrade, not persisted
+    static CERTIFIED_PROPERTIES: RefCell<BTreeMap<PropertyId, [u8; 32]>> = const { RefCell::new(BTreeMap::new()) };
+    static RECURRING_INCOME: RefCell<HashMap<PropertyId, RecurringIncome>> = RefCell::new(HashMap::new());
+    // Timers do not survive upgrades; re-armed from RECURRING_INCOME in post_upgrade
+    static RECURRING_TIMERS: RefCell<HashMap<PropertyId, TimerId>> = RefCell::new(HashMap::new());
+    static BALANCES: RefCell<HashMap<Principal, u64>> = RefCell::new(HashMap::new()); // internal funds ledger
+    static FEE_BPS: RefCell<u16> = const { RefCell::new(0) }; // marketplace fee on trade value
+    static TRANSFER_FEE_BPS: RefCell<u16> = const { RefCell::new(0) }; // shares withheld from direct transfers
+    static TREASURY: RefCell<u64> = const { RefCell::new(0) };
+    static BUYBACKS: RefCell<HashMap<PropertyId, Buyback>> = RefCell::new(HashMap::new());
+    static NOTIFIERS: RefCell<HashMap<Principal, Vec<EventType>>> = RefCell::new(HashMap::new()); // subscriber canister -> event types
+    static MARKETPLACE: RefCell<Vec<Listing>> = const { RefCell::new(Vec::new()) };
+    static PRICE_BOUNDS: RefCell<HashMap<PropertyId, PriceBounds>> = RefCell::new(HashMap::new()); // allowed listing price band
+    static NEXT_LISTING_ID: RefCell<u64> = const { RefCell::new(1) };
+    static TRADES: RefCell<Vec<Trade>> = const { RefCell::new(Vec::new()) }; // settled fills, oldest first
+    static TRANSFERS: RefCell<Vec<TransferRecord>> = const { RefCell::new(Vec::new()) }; // direct transfers; tx_index is the position
+    static ADMINS: RefCell<Vec<Principal>> = RefCell::new(vec![Principal::anonymous()]); // kept in sync with Role::Admin entries in ROLES
     static ROLES: RefCell<HashMap<Principal, Role>> = RefCell::new(HashMap::new());
-    static KYC: RefCell<HashMap<Principal, bool>> = RefCell::new(HashMap::new());
-    static BOOTSTRAPPED: RefCell<bool> = RefCell::new(false);
+    static PENDING_ROLES: RefCell<HashMap<Principal, PendingRole>> = RefCell::new(HashMap::new());
+    static KYC: RefCell<HashMap<Principal, u64>> = RefCell::new(HashMap::new()); // verified until (ns)
+    static BLACKLIST: RefCell<HashSet<Principal>> = RefCell::new(HashSet::new());
+    static BOOTSTRAPPED: RefCell<bool> = const { RefCell::new(false) };
+    static DEPLOYER: RefCell<Option<Principal>> = const { RefCell::new(None) };
+    static STRICT_KYC: RefCell<bool> = const { RefCell::new(true) };
+    static PAUSED: RefCell<bool> = const { RefCell::new(false) }; // emergency stop for every update but set_paused
+    static DELEGATIONS: RefCell<HashMap<(PropertyId, Principal), Principal>> = RefCell::new(HashMap::new()); // delegator -> delegate
     static PROPOSALS: RefCell<HashMap<u64, Proposal>> = RefCell::new(HashMap::new());
-    static NEXT_PROPOSAL_ID: RefCell<u64> = RefCell::new(1);
-    static EVENTS: RefCell<Vec<Event>> = RefCell::new(Vec::new());
+    // Holdings of each proposal's property at submission, kept out of `Proposal` so responses stay small
+    static PROPOSAL_SNAPSHOTS: RefCell<HashMap<u64, HashMap<Principal, u64>>> = RefCell::new(HashMap::new());
+    static NEXT_PROPOSAL_ID: RefCell<u64> = const { RefCell::new(1) };
+    static COMMENTS: RefCell<HashMap<u64, Vec<Comment>>> = RefCell::new(HashMap::new()); // by proposal id, oldest first
+    static QUORUM_BPS: RefCell<u16> = const { RefCell::new(1000) }; // share of total_shares that must vote
+    static MIN_PROPOSAL_BPS: RefCell<u16> = const { RefCell::new(100) }; // stake needed to submit, share of total_shares
+    static MAX_LISTINGS_PER_SELLER: RefCell<u32> = const { RefCell::new(20) }; // open listings per seller across all properties
+    static APPROVAL_THRESHOLDS: RefCell<HashMap<ProposalKind, u16>> = RefCell::new(HashMap::new()); // overrides of default_approval_threshold_bps
+    static LOW_CYCLE_THRESHOLD: RefCell<u64> = const { RefCell::new(0) }; // warn below this many cycles
+    static EVENTS: RefCell<Vec<Event>> = const { RefCell::new(Vec::new()) };
 }
 
-fn get_role(principal: &Principal) -> Role {
-    ROLES.with(|roles| roles.borrow().get(principal).cloned().unwrap_or(Role::User))
+/// Stable-memory snapshot, tagged with its layout version. Candid can't
+/// default a missing non-`opt` field, so any change to `StableState` other
+/// than adding an `opt` field needs a new variant and a migration in
+/// `restore_snapshot`.
+#[derive(CandidType, Deserialize)]
+enum VersionedState {
+    V1(StableState),
 }
 
-fn is_kyc_verified(principal: &Principal) -> bool {
-    KYC.with(|kyc| kyc.borrow().get(principal).cloned().unwrap_or(false))
+/// Snapshot of all canister state, written to stable memory across upgrades.
+/// Every field added after the original V1 layout is an `opt`, so a snapshot
+/// written before it existed still decodes; the state restores empty, or keeps
+/// its initial value for settings.
+#[derive(CandidType, Deserialize)]
+struct StableState {
+    properties: HashMap<PropertyId, Property>,
+    archived_properties: Option<HashMap<PropertyId, Property>>,
+    ownership: HashMap<(PropertyId, Principal), u64>,
+    next_property_id: PropertyId,
+    balance_history: Option<BalanceHistory>,
+    class_ownership: Option<HashMap<(PropertyId, u32, Principal), u64>>,
+    income_per_class_share: Option<HashMap<(PropertyId, u32), u128>>,
+    class_income_checkpoints: Option<HashMap<(PropertyId, u32, Principal), u128>>,
+    allowances: Option<HashMap<(PropertyId, Principal, Principal), u64>>,
+    lockups: Option<HashMap<(PropertyId, Principal), u64>>,
+    valuations: Option<HashMap<PropertyId, Vec<(u64, u64)>>>,
+    documents: Option<HashMap<PropertyId, Vec<DocRef>>>,
+    reserves: Option<HashMap<PropertyId, u64>>,
+    rounding_mode: Option<RoundingMode>,
+    income_carry: Option<HashMap<PropertyId, u64>>,
+    claim_windows: Option<HashMap<PropertyId, u64>>,
+    income_marks: Option<HashMap<PropertyId, Vec<IncomeMark>>>,
+    income_segments: Option<IncomeSegments>,
+    buyer_whitelists: Option<HashMap<PropertyId, HashSet<Principal>>>,
+    sale_proceeds: Option<HashMap<PropertyId, u64>>,
+    redeemed_proceeds: Option<HashMap<PropertyId, u64>>,
+    rental_income: HashMap<PropertyId, u64>,
+    distributed_income: Option<HashMap<PropertyId, u64>>,
+    unclaimed_income: HashMap<(PropertyId, Principal), u64>,
+    income_per_share: Option<HashMap<PropertyId, u128>>,
+    income_checkpoints: Option<HashMap<(PropertyId, Principal), u128>>,
+    income_remainders: Option<HashMap<(PropertyId, Principal), u128>>,
+    distributed_fraction: Option<HashMap<PropertyId, u128>>,
+    claimed_income: Option<HashMap<(PropertyId, Principal), u64>>,
+    claim_history: Option<HashMap<Principal, Vec<ClaimRecord>>>,
+    recurring_income: Option<HashMap<PropertyId, RecurringIncome>>,
+    balances: Option<HashMap<Principal, u64>>,
+    fee_bps: Option<u16>,
+    transfer_fee_bps: Option<u16>,
+    treasury: Option<u64>,
+    buybacks: Option<HashMap<PropertyId, Buyback>>,
+    notifiers: Option<HashMap<Principal, Vec<EventType>>>,
+    marketplace: Vec<Listing>,
+    price_bounds: Option<HashMap<PropertyId, PriceBounds>>,
+    next_listing_id: Option<u64>,
+    trades: Option<Vec<Trade>>,
+    transfers: Option<Vec<TransferRecord>>,
+    admins: Vec<Principal>,
+    roles: HashMap<Principal, Role>,
+    pending_roles: Option<HashMap<Principal, PendingRole>>,
+    kyc: HashMap<Principal, u64>,
+    blacklist: Option<HashSet<Principal>>,
+    bootstrapped: bool,
+    deployer: Option<Principal>,
+    strict_kyc: Option<bool>,
+    paused: Option<bool>,
+    delegations: Option<HashMap<(PropertyId, Principal), Principal>>,
+    proposals: HashMap<u64, Proposal>,
+    proposal_snapshots: Option<HashMap<u64, HashMap<Principal, u64>>>,
+    next_proposal_id: u64,
+    comments: Option<HashMap<u64, Vec<Comment>>>,
+    quorum_bps: Option<u16>,
+    min_proposal_bps: Option<u16>,
+    max_listings_per_seller: Option<u32>,
+    approval_thresholds: Option<HashMap<ProposalKind, u16>>,
+    low_cycle_threshold: Option<u64>,
+    events: Vec<Event>,
 }
 
-fn now() -> u64 {
-    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+impl StableState {
+    fn capture() -> Self {
+        StableState {
+            properties: PROPERTIES.with(|s| s.borrow().clone()),
+            archived_properties: Some(ARCHIVED_PROPERTIES.with(|s| s.borrow().clone())),
+            ownership: OWNERSHIP.with(|s| s.borrow().clone()),
+            next_property_id: NEXT_PROPERTY_ID.with(|s| *s.borrow()),
+            balance_history: Some(BALANCE_HISTORY.with(|s| s.borrow().clone())),
+            class_ownership: Some(CLASS_OWNERSHIP.with(|s| s.borrow().clone())),
+            income_per_class_share: Some(INCOME_PER_CLASS_SHARE.with(|s| s.borrow().clone())),
+            class_income_checkpoints: Some(CLASS_INCOME_CHECKPOINTS.with(|s| s.borrow().clone())),
+            allowances: Some(ALLOWANCES.with(|s| s.borrow().clone())),
+            lockups: Some(LOCKUPS.with(|s| s.borrow().clone())),
+            valuations: Some(VALUATIONS.with(|s| s.borrow().clone())),
+            documents: Some(DOCUMENTS.with(|s| s.borrow().clone())),
+            reserves: Some(RESERVES.with(|s| s.borrow().clone())),
+            rounding_mode: Some(ROUNDING_MODE.with(|s| *s.borrow())),
+            income_carry: Some(INCOME_CARRY.with(|s| s.borrow().clone())),
+            claim_windows: Some(CLAIM_WINDOWS.with(|s| s.borrow().clone())),
+            income_marks: Some(INCOME_MARKS.with(|s| s.borrow().clone())),
+            income_segments: Some(INCOME_SEGMENTS.with(|s| s.borrow().clone())),
+            buyer_whitelists: Some(BUYER_WHITELISTS.with(|s| s.borrow().clone())),
+            sale_proceeds: Some(SALE_PROCEEDS.with(|s| s.borrow().clone())),
+            redeemed_proceeds: Some(REDEEMED_PROCEEDS.with(|s| s.borrow().clone())),
+            rental_income: RENTAL_INCOME.with(|s| s.borrow().clone()),
+            distributed_income: Some(DISTRIBUTED_INCOME.with(|s| s.borrow().clone())),
+            unclaimed_income: UNCLAIMED_INCOME.with(|s| s.borrow().clone()),
+            income_per_share: Some(INCOME_PER_SHARE.with(|s| s.borrow().clone())),
+            income_checkpoints: Some(INCOME_CHECKPOINTS.with(|s| s.borrow().clone())),
+            income_remainders: Some(INCOME_REMAINDERS.with(|s| s.borrow().clone())),
+            distributed_fraction: Some(DISTRIBUTED_FRACTION.with(|s| s.borrow().clone())),
+            claimed_income: Some(CLAIMED_INCOME.with(|s| s.borrow().clone())),
+            claim_history: Some(CLAIM_HISTORY.with(|s| s.borrow().clone())),
+            recurring_income: Some(RECURRING_INCOME.with(|s| s.borrow().clone())),
+            balances: Some(BALANCES.with(|s| s.borrow().clone())),
+            fee_bps: Some(FEE_BPS.with(|s| *s.borrow())),
+            transfer_fee_bps: Some(TRANSFER_FEE_BPS.with(|s| *s.borrow())),
+            treasury: Some(TREASURY.with(|s| *s.borrow())),
+            buybacks: Some(BUYBACKS.with(|s| s.borrow().clone())),
+            notifiers: Some(NOTIFIERS.with(|s| s.borrow().clone())),
+            marketplace: MARKETPLACE.with(|s| s.borrow().clone()),
+            price_bounds: Some(PRICE_BOUNDS.with(|s| s.borrow().clone())),
+            next_listing_id: Some(NEXT_LISTING_ID.with(|s| *s.borrow())),
+            trades: Some(TRADES.with(|s| s.borrow().clone())),
+            transfers: Some(TRANSFERS.with(|s| s.borrow().clone())),
+            admins: ADMINS.with(|s| s.borrow().clone()),
+            roles: ROLES.with(|s| s.borrow().clone()),
+            pending_roles: Some(PENDING_ROLES.with(|s| s.borrow().clone())),
+            kyc: KYC.with(|s| s.borrow().clone()),
+            blacklist: Some(BLACKLIST.with(|s| s.borrow().clone())),
+            bootstrapped: BOOTSTRAPPED.with(|s| *s.borrow()),
+            deployer: DEPLOYER.with(|s| *s.borrow()),
+            strict_kyc: Some(STRICT_KYC.with(|s| *s.borrow())),
+            paused: Some(PAUSED.with(|s| *s.borrow())),
+            delegations: Some(DELEGATIONS.with(|s| s.borrow().clone())),
+            proposals: PROPOSALS.with(|s| s.borrow().clone()),
+            proposal_snapshots: Some(PROPOSAL_SNAPSHOTS.with(|s| s.borrow().clone())),
+            next_proposal_id: NEXT_PROPOSAL_ID.with(|s| *s.borrow()),
+            comments: Some(COMMENTS.with(|s| s.borrow().clone())),
+            quorum_bps: Some(QUORUM_BPS.with(|s| *s.borrow())),
+            min_proposal_bps: Some(MIN_PROPOSAL_BPS.with(|s| *s.borrow())),
+            max_listings_per_seller: Some(MAX_LISTINGS_PER_SELLER.with(|s| *s.borrow())),
+            approval_thresholds: Some(APPROVAL_THRESHOLDS.with(|s| s.borrow().clone())),
+            low_cycle_threshold: Some(LOW_CYCLE_THRESHOLD.with(|s| *s.borrow())),
+            events: EVENTS.with(|s| s.borrow().clone()),
+        }
+    }
+
+    fn restore(self) {
+        PROPERTIES.with(|s| *s.borrow_mut() = self.properties);
+        ARCHIVED_PROPERTIES.with(|s| *s.borrow_mut() = self.archived_properties.unwrap_or_default());
+        OWNERSHIP.with(|s| *s.borrow_mut() = self.ownership);
+        CLASS_OWNERSHIP.with(|s| *s.borrow_mut() = self.class_ownership.unwrap_or_default());
+        rebuild_ownership_indexes();
+        rebuild_name_index();
+        NEXT_PROPERTY_ID.with(|s| *s.borrow_mut() = self.next_property_id);
+        BALANCE_HISTORY.with(|s| *s.borrow_mut() = self.balance_history.unwrap_or_default());
+        INCOME_PER_CLASS_SHARE.with(|s| *s.borrow_mut() = self.income_per_class_share.unwrap_or_default());
+        CLASS_INCOME_CHECKPOINTS.with(|s| *s.borrow_mut() = self.class_income_checkpoints.unwrap_or_default());
+        ALLOWANCES.with(|s| *s.borrow_mut() = self.allowances.unwrap_or_default());
+        LOCKUPS.with(|s| *s.borrow_mut() = self.lockups.unwrap_or_default());
+        VALUATIONS.with(|s| *s.borrow_mut() = self.valuations.unwrap_or_default());
+        DOCUMENTS.with(|s| *s.borrow_mut() = self.documents.unwrap_or_default());
+        RESERVES.with(|s| *s.borrow_mut() = self.reserves.unwrap_or_default());
+        if let Some(value) = self.rounding_mode {
+            ROUNDING_MODE.with(|s| *s.borrow_mut() = value);
+        }
+        INCOME_CARRY.with(|s| *s.borrow_mut() = self.income_carry.unwrap_or_default());
+        CLAIM_WINDOWS.with(|s| *s.borrow_mut() = self.claim_windows.unwrap_or_default());
+        INCOME_MARKS.with(|s| *s.borrow_mut() = self.income_marks.unwrap_or_default());
+        INCOME_SEGMENTS.with(|s| *s.borrow_mut() = self.income_segments.unwrap_or_default());
+        BUYER_WHITELISTS.with(|s| *s.borrow_mut() = self.buyer_whitelists.unwrap_or_default());
+        SALE_PROCEEDS.with(|s| *s.borrow_mut() = self.sale_proceeds.unwrap_or_default());
+        REDEEMED_PROCEEDS.with(|s| *s.borrow_mut() = self.redeemed_proceeds.unwrap_or_default());
+        RENTAL_INCOME.with(|s| *s.borrow_mut() = self.rental_income);
+        DISTRIBUTED_INCOME.with(|s| *s.borrow_mut() = self.distributed_income.unwrap_or_default());
+        UNCLAIMED_INCOME.with(|s| *s.borrow_mut() = self.unclaimed_income);
+        INCOME_PER_SHARE.with(|s| *s.borrow_mut() = self.income_per_share.unwrap_or_default());
+        INCOME_CHECKPOINTS.with(|s| *s.borrow_mut() = self.income_checkpoints.unwrap_or_default());
+        INCOME_REMAINDERS.with(|s| *s.borrow_mut() = self.income_remainders.unwrap_or_default());
+        DISTRIBUTED_FRACTION.with(|s| *s.borrow_mut() = self.distributed_fraction.unwrap_or_default());
+        CLAIMED_INCOME.with(|s| *s.borrow_mut() = self.claimed_income.unwrap_or_default());
+        CLAIM_HISTORY.with(|s| *s.borrow_mut() = self.claim_history.unwrap_or_default());
+        RECURRING_INCOME.with(|s| *s.borrow_mut() = self.recurring_income.unwrap_or_default());
+        BALANCES.with(|s| *s.borrow_mut() = self.balances.unwrap_or_default());
+        if let Some(value) = self.fee_bps {
+            FEE_BPS.with(|s| *s.borrow_mut() = value);
+        }
+        if let Some(value) = self.transfer_fee_bps {
+            TRANSFER_FEE_BPS.with(|s| *s.borrow_mut() = value);
+        }
+        if let Some(value) = self.treasury {
+            TREASURY.with(|s| *s.borrow_mut() = value);
+        }
+        BUYBACKS.with(|s| *s.borrow_mut() = self.buybacks.unwrap_or_default());
+        NOTIFIERS.with(|s| *s.borrow_mut() = self.notifiers.unwrap_or_default());
+        MARKETPLACE.with(|s| *s.borrow_mut() = self.marketplace);
+        PRICE_BOUNDS.with(|s| *s.borrow_mut() = self.price_bounds.unwrap_or_default());
+        if let Some(value) = self.next_listing_id {
+            NEXT_LISTING_ID.with(|s| *s.borrow_mut() = value);
+        }
+        TRADES.with(|s| *s.borrow_mut() = self.trades.unwrap_or_default());
+        TRANSFERS.with(|s| *s.borrow_mut() = self.transfers.unwrap_or_default());
+        ADMINS.with(|s| *s.borrow_mut() = self.admins);
+        ROLES.with(|s| *s.borrow_mut() = self.roles);
+        PENDING_ROLES.with(|s| *s.borrow_mut() = self.pending_roles.unwrap_or_default());
+        KYC.with(|s| *s.borrow_mut() = self.kyc);
+        BLACKLIST.with(|s| *s.borrow_mut() = self.blacklist.unwrap_or_default());
+        BOOTSTRAPPED.with(|s| *s.borrow_mut() = self.bootstrapped);
+        DEPLOYER.with(|s| *s.borrow_mut() = self.deployer);
+        if let Some(value) = self.strict_kyc {
+            STRICT_KYC.with(|s| *s.borrow_mut() = value);
+        }
+        if let Some(value) = self.paused {
+            PAUSED.with(|s| *s.borrow_mut() = value);
+        }
+        DELEGATIONS.with(|s| *s.borrow_mut() = self.delegations.unwrap_or_default());
+        PROPOSALS.with(|s| *s.borrow_mut() = self.proposals);
+        PROPOSAL_SNAPSHOTS.with(|s| *s.borrow_mut() = self.proposal_snapshots.unwrap_or_default());
+        NEXT_PROPOSAL_ID.with(|s| *s.borrow_mut() = self.next_proposal_id);
+        COMMENTS.with(|s| *s.borrow_mut() = self.comments.unwrap_or_default());
+        if let Some(value) = self.quorum_bps {
+            QUORUM_BPS.with(|s| *s.borrow_mut() = value);
+        }
+        if let Some(value) = self.min_proposal_bps {
+            MIN_PROPOSAL_BPS.with(|s| *s.borrow_mut() = value);
+        }
+        if let Some(value) = self.max_listings_per_seller {
+            MAX_LISTINGS_PER_SELLER.with(|s| *s.borrow_mut() = value);
+        }
+        APPROVAL_THRESHOLDS.with(|s| *s.borrow_mut() = self.approval_thresholds.unwrap_or_default());
+        if let Some(value) = self.low_cycle_threshold {
+            LOW_CYCLE_THRESHOLD.with(|s| *s.borrow_mut() = value);
+        }
+        EVENTS.with(|s| *s.borrow_mut() = self.events);
+    }
 }
 
-#[update]
-pub fn set_kyc_status(user: Principal, status: bool) -> Result<String, String> {
-    let caller_principal = caller();
-    if get_role(&caller_principal) != Role::Admin {
-        return Err("Only admin can set KYC status".to_string());
+/// Names are unique case-insensitively; this is the NAME_INDEX key.
+fn name_key(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+fn check_name_available(name: &str) -> Result<(), String> {
+    if NAME_INDEX.with(|n| n.borrow().contains_key(&name_key(name))) {
+        return Err(format!("A property named {} already exists", name.trim()));
     }
-    KYC.with(|kyc| {
-        kyc.borrow_mut().insert(user, status);
-    });
-    EVENTS.with(|events| {
-        events.borrow_mut().push(Event {
-            event_type: EventType::KycStatusSet,
-            timestamp: now(),
-            actor: caller_principal,
-            details: format!("Set KYC status {} for user {}", status, user),
-        });
+    Ok(())
+}
+
+/// If names collide in state predating uniqueness, the lowest id wins.
+fn rebuild_name_index() {
+    let mut index: HashMap<String, PropertyId> = HashMap::new();
+    PROPERTIES.with(|props| {
+        let props = props.borrow();
+        let mut ids: Vec<&PropertyId> = props.keys().collect();
+        ids.sort();
+        for id in ids {
+            index.entry(name_key(&props[id].name)).or_insert(*id);
+        }
     });
-    Ok("KYC status updated".to_string())
+    NAME_INDEX.with(|n| *n.borrow_mut() = index);
 }
 
-#[update]
-pub fn set_role(user: Principal, role: Role) -> Result<String, String> {
-    let caller_principal = caller();
-    if get_role(&caller_principal) != Role::Admin {
-        return Err("Only admin can set roles".to_string());
-    }
-    ROLES.with(|roles| {
-        roles.borrow_mut().insert(user, role.clone());
+fn rebuild_ownership_indexes() {
+    let mut holders_by_property: HashMap<PropertyId, HashSet<Principal>> = HashMap::new();
+    let mut properties_by_holder: HashMap<Principal, HashSet<PropertyId>> = HashMap::new();
+    OWNERSHIP.with(|own| {
+        for ((pid, user), shares) in own.borrow().iter() {
+            if *shares > 0 {
+                holders_by_property.entry(*pid).or_default().insert(*user);
+                properties_by_holder.entry(*user).or_default().insert(*pid);
+            }
+        }
     });
-    EVENTS.with(|events| {
-        events.borrow_mut().push(Event {
-            event_type: EventType::RoleSet,
-            timestamp: now(),
-            actor: caller_principal,
-            details: format!("Set role {:?} for user {}", role, user),
-        });
+    HOLDERS_BY_PROPERTY.with(|h| *h.borrow_mut() = holders_by_property);
+    PROPERTIES_BY_HOLDER.with(|p| *p.borrow_mut() = properties_by_holder);
+    let mut class_issued: HashMap<(PropertyId, u32), u64> = HashMap::new();
+    CLASS_OWNERSHIP.with(|c| {
+        for ((pid, class_index, _), shares) in c.borrow().iter() {
+            *class_issued.entry((*pid, *class_index)).or_insert(0) += shares;
+        }
     });
-    Ok("Role updated".to_string())
+    CLASS_ISSUED.with(|c| *c.borrow_mut() = class_issued);
 }
 
-#[update]
-pub fn bootstrap_admin(admin: Principal) -> Result<String, String> {
-    let already_bootstrapped = BOOTSTRAPPED.with(|b| *b.borrow());
-    if already_bootstrapped {
-        return Err("Admin already bootstrapped".to_string());
-    }
+/// Installs the canister with its first admin and KYC policy. Bootstrapping is
+/// marked complete, so `bootstrap_admin` is no longer needed.
+#[init]
+fn init(initial_admin: Principal, strict_kyc: bool) {
+    DEPLOYER.with(|d| *d.borrow_mut() = Some(caller()));
+    ADMINS.with(|admins| admins.borrow_mut().clear());
     ROLES.with(|roles| {
-        roles.borrow_mut().insert(admin, Role::Admin);
+        roles.borrow_mut().insert(initial_admin, Role::Admin);
     });
+    ADMINS.with(|admins| admins.borrow_mut().push(initial_admin));
+    STRICT_KYC.with(|s| *s.borrow_mut() = strict_kyc);
     BOOTSTRAPPED.with(|b| *b.borrow_mut() = true);
-    Ok("Admin bootstrapped".to_string())
+    record_event(EventType::AdminBootstrapped, caller(), format!("Initialized with admin {} and strict KYC {}", initial_admin, strict_kyc));
+    publish_certified_root();
 }
 
-#[query]
-pub fn get_my_role() -> Role {
-    get_role(&caller())
+#[pre_upgrade]
+fn pre_upgrade() {
+    ic_cdk::storage::stable_save((VersionedState::V1(StableState::capture()),)).expect("Failed to save state to stable memory");
 }
 
-#[query]
-pub fn is_my_kyc_verified() -> bool {
-    is_kyc_verified(&caller())
+/// Upgrading from a build without `pre_upgrade` leaves stable memory empty;
+/// the canister then starts from fresh state and a controller bootstraps
+/// the admin again.
+#[post_upgrade]
+fn post_upgrade() {
+    if ic_cdk::api::stable::stable_size() == 0 {
+        ic_cdk::println!("No stable state found; starting with empty state");
+        return;
+    }
+    let (snapshot,): (VersionedState,) = ic_cdk::storage::stable_restore().expect("Failed to restore state from stable memory");
+    restore_snapshot(snapshot);
 }
 
-#[update]
-pub fn update_property_metadata(property_id: PropertyId, metadata: PropertyMetadata, caller: Principal) -> Result<String, String> {
-    if get_role(&caller) != Role::Admin {
-        return Err("Only admin can update property metadata".to_string());
+fn restore_snapshot(snapshot: VersionedState) {
+    match snapshot {
+        VersionedState::V1(state) => state.restore(),
     }
-    PROPERTIES.with(|props| {
-        let mut props = props.borrow_mut();
-        if let Some(prop) = props.get_mut(&property_id) {
-            prop.metadata = metadata;
-            EVENTS.with(|events| {
-                events.borrow_mut().push(Event {
-                    event_type: EventType::PropertyMetadataUpdated,
-                    timestamp: now(),
-                    actor: caller,
-                    details: format!("Updated metadata for property {} (id: {})", prop.name, property_id),
-                });
-            });
-            Ok("Property metadata updated".to_string())
+    let property_ids: Vec<PropertyId> = PROPERTIES.with(|props| props.borrow().keys().cloned().collect());
+    for property_id in property_ids {
+        refresh_certified_leaf(property_id);
+    }
+    publish_certified_root();
+    let schedules: Vec<RecurringIncome> = RECURRING_INCOME.with(|ri| ri.borrow().values().cloned().collect());
+    for schedule in schedules {
+        arm_recurring_income(&schedule);
+    }
+}
+
+fn get_role(principal: &Principal) -> Role {
+    ROLES.with(|roles| roles.borrow().get(principal).cloned().unwrap_or(Role::User))
+}
+
+/// Admins and managers may operate on properties; only admins manage roles and KYC.
+fn can_manage(principal: &Principal) -> bool {
+    matches!(get_role(principal), Role::Admin | Role::Manager)
+}
+
+fn is_kyc_verified(principal: &Principal) -> bool {
+    KYC.with(|kyc| kyc.borrow().get(principal).is_some_and(|valid_until| now() < *valid_until))
+}
+
+/// Grants KYC until `valid_until_ns` (never expiring if None), or revokes it.
+fn apply_kyc_status(user: Principal, status: bool, valid_until_ns: Option<u64>) {
+    KYC.with(|kyc| {
+        let mut kyc = kyc.borrow_mut();
+        if status {
+            kyc.insert(user, valid_until_ns.unwrap_or(u64::MAX));
         } else {
-            Err("Property not found".to_string())
+            kyc.remove(&user);
         }
-    })
+    });
 }
 
-#[update]
-pub fn update_property_status(property_id: PropertyId, status: PropertyStatus, caller: Principal) -> Result<String, String> {
-    if get_role(&caller) != Role::Admin {
-        return Err("Only admin can update property status".to_string());
+fn is_blacklisted(principal: &Principal) -> bool {
+    BLACKLIST.with(|b| b.borrow().contains(principal))
+}
+
+fn check_not_blacklisted(principals: &[&Principal]) -> Result<(), String> {
+    if principals.iter().any(|p| is_blacklisted(p)) {
+        return Err("Principal is blacklisted".to_string());
     }
-    let status_for_log = status.clone();
-    PROPERTIES.with(|props| {
-        let mut props = props.borrow_mut();
-        if let Some(prop) = props.get_mut(&property_id) {
-            prop.status = status;
-            EVENTS.with(|events| {
-                events.borrow_mut().push(Event {
-                    event_type: EventType::PropertyStatusUpdated,
-                    timestamp: now(),
-                    actor: caller,
-                    details: format!("Updated status for property {} (id: {}) to {:?}", prop.name, property_id, status_for_log),
-                });
-            });
-            Ok("Property status updated".to_string())
-        } else {
-            Err("Property not found".to_string())
-        }
-    })
+    Ok(())
 }
 
-// Update register_property to include metadata and status
-#[update]
-pub fn register_property(name: String, total_shares: u64, metadata: PropertyMetadata) -> Property {
-    let caller_principal = caller();
-    let property = PROPERTIES.with(|props| {
-        let mut props = props.borrow_mut();
-        let id = NEXT_PROPERTY_ID.with(|id| {
-            let mut id = id.borrow_mut();
-            let curr = *id;
-            *id += 1;
-            curr
-        });
-        let property = Property {
-            id,
-            name: name.clone(),
-            total_shares,
+/// When strict KYC is enabled, only verified principals may receive shares.
+fn check_recipient_kyc(recipient: &Principal) -> Result<(), String> {
+    let strict = STRICT_KYC.with(|s| *s.borrow());
+    if strict && !is_kyc_verified(recipient) {
+        return Err("Recipient is not KYC verified".to_string());
+    }
+    Ok(())
+}
+
+/// Private offerings: once a property has a buyer whitelist, only listed
+/// principals may receive its shares. No whitelist means the offering is public.
+fn check_whitelisted(property_id: PropertyId, recipient: &Principal) -> Result<(), String> {
+    let allowed = BUYER_WHITELISTS.with(|w| w.borrow().get(&property_id).is_none_or(|list| list.contains(recipient)));
+    if !allowed {
+        return Err("Recipient is not whitelisted for this property".to_string());
+    }
+    Ok(())
+}
+
+/// Guard at the top of every update except `set_paused`.
+fn check_not_paused() -> Result<(), String> {
+    if PAUSED.with(|p| *p.borrow()) {
+        return Err("Canister is paused".to_string());
+    }
+    Ok(())
+}
+
+/// A Sold property's share count is fixed so redemptions can't exceed the proceeds.
+fn check_not_sold(property_id: PropertyId) -> Result<(), String> {
+    if get_property(property_id).is_some_and(|p| p.status == PropertyStatus::Sold) {
+        return Err("Property is sold".to_string());
+    }
+    Ok(())
+}
+
+fn check_not_frozen(property_id: PropertyId) -> Result<(), String> {
+    if PROPERTIES.with(|props| props.borrow().get(&property_id).is_some_and(|p| p.frozen)) {
+        return Err("Property is frozen".to_string());
+    }
+    Ok(())
+}
+
+fn check_transfers_not_paused(property_id: PropertyId) -> Result<(), String> {
+    if PROPERTIES.with(|props| props.borrow().get(&property_id).is_some_and(|p| p.transfers_paused)) {
+        return Err("Transfers are paused for this property".to_string());
+    }
+    Ok(())
+}
+
+fn unlock_time(property_id: PropertyId, holder: &Principal) -> u64 {
+    LOCKUPS.with(|l| l.borrow().get(&(property_id, *holder)).cloned().unwrap_or(0))
+}
+
+/// Rejects moving a holder's shares of a property while they are locked up.
+fn check_lockup(property_id: PropertyId, holder: &Principal) -> Result<(), String> {
+    let unlock_at = unlock_time(property_id, holder);
+    if now() < unlock_at {
+        return Err(format!("Shares are locked until {}", unlock_at));
+    }
+    Ok(())
+}
+
+/// Rejects a credit that would push the recipient past the property's ownership cap.
+fn check_ownership_cap(property_id: PropertyId, recipient: &Principal, incoming: u64) -> Result<(), String> {
+    let cap = PROPERTIES.with(|props| {
+        props.borrow().get(&property_id).and_then(|p| {
+            p.max_ownership_bps.map(|bps| p.total_shares as u128 * bps as u128 / 10_000)
+        })
+    });
+    if let Some(cap) = cap {
+        let current = OWNERSHIP.with(|own| own.borrow().get(&(property_id, *recipient)).cloned().unwrap_or(0));
+        if current as u128 + incoming as u128 > cap {
+            return Err("Exceeds ownership cap".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Formats a base-unit share amount as a decimal string, e.g. 1234 with 2 decimals is "12.34".
+pub fn to_display_units(amount: u64, decimals: u8) -> String {
+    if decimals == 0 {
+        return amount.to_string();
+    }
+    let scale = 10u64.pow(decimals as u32);
+    format!("{}.{:0width$}", amount / scale, amount % scale, width = decimals as usize)
+}
+
+/// Parses a decimal string into base units, rejecting more fractional digits than `decimals`.
+pub fn from_display_units(display: &str, decimals: u8) -> Result<u64, String> {
+    let (whole, fraction) = display.trim().split_once('.').unwrap_or((display.trim(), ""));
+    if !whole.chars().chain(fraction.chars()).all(|c| c.is_ascii_digit()) {
+        return Err("Invalid amount".to_string());
+    }
+    if fraction.len() > decimals as usize {
+        return Err("Too many decimal places".to_string());
+    }
+    let scale = 10u64.pow(decimals as u32);
+    let whole: u64 = whole.parse().map_err(|_| "Invalid amount".to_string())?;
+    let fraction: u64 = if fraction.is_empty() {
+        0
+    } else {
+        let padded = format!("{:0<width$}", fraction, width = decimals as usize);
+        padded.parse().map_err(|_| "Invalid amount".to_string())?
+    };
+    whole
+        .checked_mul(scale)
+        .and_then(|w| w.checked_add(fraction))
+        .ok_or("Amount overflow".to_string())
+}
+
+/// Computes `amount * shares / total_shares` without intermediate overflow.
+fn proportional_share(amount: u64, shares: u64, total_shares: u64) -> Result<u64, String> {
+    let value = amount as u128 * shares as u128 / total_shares as u128;
+    u64::try_from(value).map_err(|_| "Income allocation overflow".to_string())
+}
+
+/// Appends a holder's new balance to BALANCE_HISTORY. Several changes within
+/// one call share a timestamp, so only the last of them is kept.
+fn record_balance(property_id: PropertyId, holder: Principal, balance: u64) {
+    let timestamp = now();
+    BALANCE_HISTORY.with(|bh| {
+        let mut bh = bh.borrow_mut();
+        let history = bh.entry((property_id, holder)).or_default();
+        match history.last_mut() {
+            Some(last) if last.0 == timestamp => last.1 = balance,
+            _ => history.push((timestamp, balance)),
+        }
+    });
+}
+
+/// Adds shares to a holder's balance, keeping the reverse indexes in sync.
+/// All ownership changes go through this and `debit_shares`.
+fn credit_shares(property_id: PropertyId, holder: Principal, amount: u64) {
+    if amount == 0 {
+        return;
+    }
+    settle_income(property_id, holder);
+    let balance = OWNERSHIP.with(|own| {
+        let mut own = own.borrow_mut();
+        let entry = own.entry((property_id, holder)).or_insert(0);
+        *entry += amount;
+        *entry
+    });
+    record_balance(property_id, holder, balance);
+    HOLDERS_BY_PROPERTY.with(|h| {
+        h.borrow_mut().entry(property_id).or_default().insert(holder);
+    });
+    PROPERTIES_BY_HOLDER.with(|p| {
+        p.borrow_mut().entry(holder).or_default().insert(property_id);
+    });
+}
+
+/// Removes shares from a holder's balance, dropping them from the indexes at zero.
+fn debit_shares(property_id: PropertyId, holder: Principal, amount: u64) -> Result<(), String> {
+    settle_income(property_id, holder);
+    let remaining = OWNERSHIP.with(|own| {
+        let mut own = own.borrow_mut();
+        let current = own.get(&(property_id, holder)).cloned().unwrap_or(0);
+        if current < amount {
+            return Err("Not enough shares".to_string());
+        }
+        let remaining = current - amount;
+        if remaining == 0 {
+            own.remove(&(property_id, holder));
+        } else {
+            own.insert((property_id, holder), remaining);
+        }
+        Ok(remaining)
+    })?;
+    record_balance(property_id, holder, remaining);
+    if remaining == 0 {
+        INCOME_CHECKPOINTS.with(|c| c.borrow_mut().remove(&(property_id, holder)));
+        HOLDERS_BY_PROPERTY.with(|h| {
+            let mut h = h.borrow_mut();
+            if let Some(holders) = h.get_mut(&property_id) {
+                holders.remove(&holder);
+                if holders.is_empty() {
+                    h.remove(&property_id);
+                }
+            }
+        });
+        PROPERTIES_BY_HOLDER.with(|p| {
+            let mut p = p.borrow_mut();
+            if let Some(properties) = p.get_mut(&holder) {
+                properties.remove(&property_id);
+                if properties.is_empty() {
+                    p.remove(&holder);
+                }
+            }
+        });
+    }
+    Ok(())
+}
+
+fn class_shares(property_id: PropertyId, class_index: u32, holder: Principal) -> u64 {
+    CLASS_OWNERSHIP.with(|c| c.borrow().get(&(property_id, class_index, holder)).cloned().unwrap_or(0))
+}
+
+/// Shares of one class currently held by anyone.
+fn class_issued(property_id: PropertyId, class_index: u32) -> u64 {
+    CLASS_ISSUED.with(|c| c.borrow().get(&(property_id, class_index)).cloned().unwrap_or(0))
+}
+
+fn adjust_class_issued(property_id: PropertyId, class_index: u32, credited: u64, debited: u64) {
+    CLASS_ISSUED.with(|c| {
+        let mut c = c.borrow_mut();
+        let issued = c.entry((property_id, class_index)).or_insert(0);
+        *issued = (*issued + credited).saturating_sub(debited);
+        if *issued == 0 {
+            c.remove(&(property_id, class_index));
+        }
+    });
+}
+
+/// `credit_shares` for a property with share classes; the holder's total in
+/// OWNERSHIP is credited first so income settles against the old balances.
+fn credit_class_shares(property_id: PropertyId, class_index: u32, holder: Principal, amount: u64) {
+    if amount == 0 {
+        return;
+    }
+    credit_shares(property_id, holder, amount);
+    CLASS_OWNERSHIP.with(|c| {
+        *c.borrow_mut().entry((property_id, class_index, holder)).or_insert(0) += amount;
+    });
+    adjust_class_issued(property_id, class_index, amount, 0);
+}
+
+fn debit_class_shares(property_id: PropertyId, class_index: u32, holder: Principal, amount: u64) -> Result<(), String> {
+    let current = class_shares(property_id, class_index, holder);
+    if current < amount {
+        return Err("Not enough shares of this class".to_string());
+    }
+    debit_shares(property_id, holder, amount)?;
+    if current == amount {
+        CLASS_OWNERSHIP.with(|c| c.borrow_mut().remove(&(property_id, class_index, holder)));
+        CLASS_INCOME_CHECKPOINTS.with(|c| c.borrow_mut().remove(&(property_id, class_index, holder)));
+    } else {
+        CLASS_OWNERSHIP.with(|c| c.borrow_mut().insert((property_id, class_index, holder), current - amount));
+    }
+    adjust_class_issued(property_id, class_index, 0, amount);
+    Ok(())
+}
+
+/// Drops a holder's per-class entries after their whole position was debited.
+fn clear_class_holdings(property_id: PropertyId, holder: Principal) {
+    for class_index in 0..share_class_count(property_id) {
+        adjust_class_issued(property_id, class_index, 0, class_shares(property_id, class_index, holder));
+    }
+    CLASS_OWNERSHIP.with(|c| c.borrow_mut().retain(|(pid, _, h), _| *pid != property_id || *h != holder));
+    CLASS_INCOME_CHECKPOINTS.with(|c| c.borrow_mut().retain(|(pid, _, h), _| *pid != property_id || *h != holder));
+}
+
+/// Share-class properties move shares only through the class-aware endpoints.
+fn check_unclassed(property_id: PropertyId) -> Result<(), String> {
+    if PROPERTIES.with(|props| props.borrow().get(&property_id).is_some_and(|p| !p.share_classes.is_empty())) {
+        return Err("Property has share classes; use the class-aware endpoints".to_string());
+    }
+    Ok(())
+}
+
+/// Current non-zero holdings of a property, read through the holder index.
+fn holders_of(property_id: PropertyId) -> Vec<(Principal, u64)> {
+    HOLDERS_BY_PROPERTY.with(|h| {
+        h.borrow()
+            .get(&property_id)
+            .map(|holders| holders.iter().map(|user| (*user, get_ownership(property_id, *user))).collect())
+            .unwrap_or_default()
+    })
+}
+
+/// Issued shares (`total_shares - shares_available`) must equal the sum of all holdings.
+fn share_invariant_holds(property_id: PropertyId) -> bool {
+    let issued = match PROPERTIES.with(|props| props.borrow().get(&property_id).map(|p| p.total_shares.checked_sub(p.shares_available))) {
+        Some(Some(issued)) => issued as u128,
+        _ => return false,
+    };
+    let held: u128 = OWNERSHIP.with(|own| {
+        own.borrow().iter().filter(|((pid, _), _)| *pid == property_id).map(|(_, shares)| *shares as u128).sum()
+    });
+    held == issued
+}
+
+// Certification: each property is a labeled leaf under "properties" in an
+// IC hash tree. The leaf is the SHA-256 of the candid encoding of the
+// property and its holders, so certifying one entry covers both.
+
+const CERTIFIED_LABEL: &[u8] = b"properties";
+
+fn sha256(parts: &[&[u8]]) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+fn domain_sep(tag: &str) -> Vec<u8> {
+    let mut sep = vec![tag.len() as u8];
+    sep.extend_from_slice(tag.as_bytes());
+    sep
+}
+
+fn labeled_hash(label: &[u8], subtree_hash: &[u8; 32]) -> [u8; 32] {
+    sha256(&[&domain_sep("ic-hashtree-labeled"), label, subtree_hash])
+}
+
+fn leaf_hash(value: &[u8]) -> [u8; 32] {
+    sha256(&[&domain_sep("ic-hashtree-leaf"), value])
+}
+
+fn fork_hash(left: &[u8; 32], right: &[u8; 32]) -> [u8; 32] {
+    sha256(&[&domain_sep("ic-hashtree-fork"), left, right])
+}
+
+fn sorted_holders(property_id: PropertyId) -> Vec<(Principal, u64)> {
+    let mut holders = holders_of(property_id);
+    holders.sort_by_key(|(holder, _)| *holder);
+    holders
+}
+
+fn certified_digest(property: &Property, holders: &Vec<(Principal, u64)>) -> [u8; 32] {
+    let encoded = candid::encode_args((property, holders)).expect("Failed to encode certified property");
+    sha256(&[&encoded])
+}
+
+/// Hash of a balanced fork tree over sorted `(id, digest)` leaves.
+fn property_tree_hash(leaves: &[(PropertyId, [u8; 32])]) -> [u8; 32] {
+    match leaves {
+        [] => sha256(&[&domain_sep("ic-hashtree-empty")]),
+        [(id, digest)] => labeled_hash(&id.to_be_bytes(), &leaf_hash(digest)),
+        _ => {
+            let (left, right) = leaves.split_at(leaves.len() / 2);
+            fork_hash(&property_tree_hash(left), &property_tree_hash(right))
+        }
+    }
+}
+
+/// Same shape as `property_tree_hash`, with every branch not leading to `target` pruned.
+fn property_tree_witness(leaves: &[(PropertyId, [u8; 32])], target: PropertyId) -> HashTree {
+    match leaves {
+        [] => HashTree::Empty,
+        [(id, digest)] if *id == target => HashTree::Labeled(id.to_be_bytes().to_vec(), Box::new(HashTree::Leaf(digest.to_vec()))),
+        [_] => HashTree::Pruned(property_tree_hash(leaves).to_vec()),
+        _ => {
+            let (left, right) = leaves.split_at(leaves.len() / 2);
+            let branch = |half: &[(PropertyId, [u8; 32])]| {
+                if half.iter().any(|(id, _)| *id == target) {
+                    property_tree_witness(half, target)
+                } else {
+                    HashTree::Pruned(property_tree_hash(half).to_vec())
+                }
+            };
+            HashTree::Fork(Box::new(branch(left)), Box::new(branch(right)))
+        }
+    }
+}
+
+fn certified_leaves() -> Vec<(PropertyId, [u8; 32])> {
+    CERTIFIED_PROPERTIES.with(|c| c.borrow().iter().map(|(id, digest)| (*id, *digest)).collect())
+}
+
+fn publish_certified_root() {
+    let root = labeled_hash(CERTIFIED_LABEL, &property_tree_hash(&certified_leaves()));
+    set_certified_data(&root);
+}
+
+/// Refreshes a property's certified leaf and the canister's certified data.
+/// Call after any change to the property record or its holdings.
+fn certify_property(property_id: PropertyId) {
+    refresh_certified_leaf(property_id);
+    publish_certified_root();
+}
+
+fn refresh_certified_leaf(property_id: PropertyId) {
+    match get_property(property_id) {
+        Some(property) => {
+            let digest = certified_digest(&property, &sorted_holders(property_id));
+            CERTIFIED_PROPERTIES.with(|c| c.borrow_mut().insert(property_id, digest));
+        }
+        None => {
+            CERTIFIED_PROPERTIES.with(|c| c.borrow_mut().remove(&property_id));
+        }
+    }
+}
+
+/// A property and its holders with a certificate and witness, so clients
+/// can verify the response without a replicated call. Must be called as a query.
+#[query]
+pub fn get_property_certified(property_id: PropertyId) -> Result<CertifiedProperty, String> {
+    let property = get_property(property_id).ok_or("Property not found".to_string())?;
+    let certificate = data_certificate().ok_or("No certificate available; call as a query".to_string())?;
+    Ok(CertifiedProperty {
+        holders: sorted_holders(property_id),
+        property,
+        certificate,
+        witness: HashTree::Labeled(CERTIFIED_LABEL.to_vec(), Box::new(property_tree_witness(&certified_leaves(), property_id))),
+    })
+}
+
+fn assert_share_invariant(property_id: PropertyId) {
+    if cfg!(debug_assertions) && PROPERTIES.with(|props| props.borrow().contains_key(&property_id)) {
+        assert!(share_invariant_holds(property_id), "Share invariant violated for property {}", property_id);
+    }
+}
+
+// System API wrappers. Unit tests run natively, where these calls trap, so
+// under cfg(test) the same names come from the test harness instead.
+#[cfg(test)]
+use tests::env::{caller, canister_balance, clear_timer, data_certificate, is_controller, notify_event, now, set_certified_data, set_timer_interval};
+
+#[cfg(not(test))]
+fn caller() -> Principal {
+    ic_cdk::api::caller()
+}
+
+/// Current IC time in nanoseconds since the epoch.
+#[cfg(not(test))]
+fn now() -> u64 {
+    ic_cdk::api::time()
+}
+
+#[cfg(not(test))]
+fn is_controller(principal: &Principal) -> bool {
+    ic_cdk::api::is_controller(principal)
+}
+
+#[cfg(not(test))]
+fn canister_balance() -> u64 {
+    ic_cdk::api::canister_balance()
+}
+
+#[cfg(not(test))]
+fn set_certified_data(data: &[u8]) {
+    ic_cdk::api::set_certified_data(data);
+}
+
+#[cfg(not(test))]
+fn data_certificate() -> Option<Vec<u8>> {
+    ic_cdk::api::data_certificate()
+}
+
+#[cfg(not(test))]
+fn notify_event(canister: Principal, event: &Event) {
+    let _ = ic_cdk::notify(canister, NOTIFY_METHOD, (event.clone(),));
+}
+
+#[cfg(not(test))]
+fn set_timer_interval(interval: Duration, func: impl FnMut() + 'static) -> TimerId {
+    ic_cdk_timers::set_timer_interval(interval, func)
+}
+
+#[cfg(not(test))]
+fn clear_timer(timer_id: TimerId) {
+    ic_cdk_timers::clear_timer(timer_id);
+}
+
+/// Appends an entry to the audit log. Event ids are their position in the log.
+fn record_event(event_type: EventType, actor: Principal, details: String) {
+    push_event(event_type, None, actor, details);
+}
+
+/// `record_event` for events about one property, which feed `get_property_activity`.
+fn record_property_event(event_type: EventType, property_id: PropertyId, actor: Principal, details: String) {
+    push_event(event_type, Some(property_id), actor, details);
+}
+
+fn push_event(event_type: EventType, property_id: Option<PropertyId>, actor: Principal, details: String) {
+    let event = EVENTS.with(|events| {
+        let mut events = events.borrow_mut();
+        let id = events.len() as u64;
+        let event = Event {
+            id,
+            event_type,
+            timestamp: now(),
+            actor,
+            details,
+            property_id,
+        };
+        events.push(event.clone());
+        event
+    });
+    notify_subscribers(&event);
+}
+
+/// Best-effort push of `event` to every notifier subscribed to its type. Uses
+/// one-way calls, so a subscriber that rejects or is unreachable never rolls
+/// back or blocks the operation that recorded the event.
+fn notify_subscribers(event: &Event) {
+    let subscribers: Vec<Principal> = NOTIFIERS.with(|n| {
+        n.borrow().iter().filter(|(_, kinds)| kinds.contains(&event.event_type)).map(|(canister, _)| *canister).collect()
+    });
+    for canister in subscribers {
+        notify_event(canister, event);
+    }
+}
+
+#[update]
+pub fn set_kyc_status(user: Principal, status: bool, valid_until_ns: Option<u64>) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can set KYC status".to_string());
+    }
+    apply_kyc_status(user, status, valid_until_ns);
+    record_event(EventType::KycStatusSet, caller_principal, format!("Set KYC status {} for user {} (valid until {:?})", status, user, valid_until_ns));
+    Ok("KYC status updated".to_string())
+}
+
+/// Admin applies many KYC updates in one call; grants never expire. Returns how
+/// many principals' verification status actually changed.
+#[update]
+pub fn set_kyc_status_batch(entries: Vec<(Principal, bool)>) -> Result<u64, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can set KYC status".to_string());
+    }
+    let mut changed = 0;
+    for (user, status) in &entries {
+        if is_kyc_verified(user) != *status {
+            changed += 1;
+        }
+        apply_kyc_status(*user, *status, None);
+    }
+    record_event(EventType::KycStatusSet, caller_principal, format!("Applied {} batch KYC updates ({} changed)", entries.len(), changed));
+    Ok(changed)
+}
+
+/// Time (ns) a user's KYC verification expires; None if never verified or revoked.
+#[query]
+pub fn get_kyc_expiry(user: Principal) -> Option<u64> {
+    KYC.with(|kyc| kyc.borrow().get(&user).cloned())
+}
+
+/// Admin adds or removes a principal from the blacklist, freezing all their share operations, redemptions and withdrawals.
+#[update]
+pub fn set_blacklist(user: Principal, blacklisted: bool) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can set the blacklist".to_string());
+    }
+    BLACKLIST.with(|b| {
+        let mut b = b.borrow_mut();
+        if blacklisted {
+            b.insert(user);
+        } else {
+            b.remove(&user);
+        }
+    });
+    record_event(EventType::BlacklistSet, caller_principal, format!("Set blacklisted {} for user {}", blacklisted, user));
+    Ok("Blacklist updated".to_string())
+}
+
+#[query]
+pub fn get_blacklist_status(user: Principal) -> bool {
+    is_blacklisted(&user)
+}
+
+/// Admin admits `user` to a private offering. The first addition turns the
+/// property's whitelist on; `clear_whitelist` makes it public again.
+#[update]
+pub fn add_to_whitelist(property_id: PropertyId, user: Principal) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can manage whitelists".to_string());
+    }
+    if get_property(property_id).is_none() {
+        return Err("Property not found".to_string());
+    }
+    BUYER_WHITELISTS.with(|w| w.borrow_mut().entry(property_id).or_default().insert(user));
+    record_property_event(EventType::WhitelistUpdated, property_id, caller_principal, format!("Whitelisted {} for property {}", user, property_id));
+    Ok("Whitelist updated".to_string())
+}
+
+/// Removes `user` from a property's whitelist. The whitelist stays in force
+/// even when it becomes empty.
+#[update]
+pub fn remove_from_whitelist(property_id: PropertyId, user: Principal) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can manage whitelists".to_string());
+    }
+    let removed = BUYER_WHITELISTS.with(|w| w.borrow_mut().get_mut(&property_id).is_some_and(|list| list.remove(&user)));
+    if !removed {
+        return Err("User is not whitelisted for this property".to_string());
+    }
+    record_property_event(EventType::WhitelistUpdated, property_id, caller_principal, format!("Removed {} from the whitelist for property {}", user, property_id));
+    Ok("Whitelist updated".to_string())
+}
+
+/// Drops a property's whitelist, opening it to all buyers.
+#[update]
+pub fn clear_whitelist(property_id: PropertyId) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can manage whitelists".to_string());
+    }
+    if BUYER_WHITELISTS.with(|w| w.borrow_mut().remove(&property_id)).is_none() {
+        return Err("Property has no whitelist".to_string());
+    }
+    record_property_event(EventType::WhitelistUpdated, property_id, caller_principal, format!("Cleared the whitelist for property {}", property_id));
+    Ok("Whitelist cleared".to_string())
+}
+
+/// A property's whitelisted buyers, or `None` for a public offering.
+#[query]
+pub fn get_whitelist(property_id: PropertyId) -> Option<Vec<Principal>> {
+    BUYER_WHITELISTS.with(|w| {
+        w.borrow().get(&property_id).map(|list| {
+            let mut users: Vec<Principal> = list.iter().cloned().collect();
+            users.sort();
+            users
+        })
+    })
+}
+
+/// Admin emergency stop: while paused every update except this one returns
+/// "Canister is paused" and recurring income deposits are skipped. Queries
+/// keep working.
+#[update]
+pub fn set_paused(paused: bool) -> Result<String, String> {
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can pause the canister".to_string());
+    }
+    PAUSED.with(|p| *p.borrow_mut() = paused);
+    record_event(EventType::PausedSet, caller_principal, format!("Set canister paused to {}", paused));
+    Ok(if paused { "Canister paused" } else { "Canister unpaused" }.to_string())
+}
+
+#[query]
+pub fn is_paused() -> bool {
+    PAUSED.with(|p| *p.borrow())
+}
+
+/// Admin toggles whether share recipients must be KYC verified.
+#[update]
+pub fn set_strict_kyc(enabled: bool) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can set strict KYC".to_string());
+    }
+    STRICT_KYC.with(|s| *s.borrow_mut() = enabled);
+    record_event(EventType::StrictKycSet, caller_principal, format!("Set strict KYC enforcement to {}", enabled));
+    Ok("Strict KYC updated".to_string())
+}
+
+#[query]
+pub fn is_strict_kyc_enabled() -> bool {
+    STRICT_KYC.with(|s| *s.borrow())
+}
+
+/// Admin sets a non-admin role directly. Granting Admin goes through
+/// `propose_role` / `accept_role` so a mistyped principal cannot receive it.
+#[update]
+pub fn set_role(user: Principal, role: Role) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can set roles".to_string());
+    }
+    if role == Role::Admin {
+        return Err("Admin must be granted with propose_role and accept_role".to_string());
+    }
+    assign_role(user, role.clone())?;
+    record_event(EventType::RoleSet, caller_principal, format!("Set role {:?} for user {}", role, user));
+    Ok("Role updated".to_string())
+}
+
+/// First step of a role handover: admin proposes `role` for `user`, who must
+/// call `accept_role` within ROLE_HANDOVER_WINDOW_SECS for it to apply.
+/// Replaces any earlier pending proposal for the same user.
+#[update]
+pub fn propose_role(user: Principal, role: Role) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can propose roles".to_string());
+    }
+    if user == Principal::anonymous() {
+        return Err("Cannot propose a role for the anonymous principal".to_string());
+    }
+    check_not_last_admin(&user, &role)?;
+    let expires_at = now().saturating_add(ROLE_HANDOVER_WINDOW_SECS * 1_000_000_000);
+    PENDING_ROLES.with(|p| {
+        p.borrow_mut().insert(user, PendingRole { role: role.clone(), proposed_by: caller_principal, expires_at });
+    });
+    record_event(EventType::RoleProposed, caller_principal, format!("Proposed role {:?} for user {} (expires at {})", role, user, expires_at));
+    Ok("Role proposed".to_string())
+}
+
+/// Second step: the proposed principal accepts their pending role. The
+/// last-admin check is repeated, since admins may have changed meanwhile.
+#[update]
+pub fn accept_role() -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    let pending = PENDING_ROLES.with(|p| p.borrow_mut().remove(&caller_principal)).ok_or("No pending role for caller".to_string())?;
+    if now() > pending.expires_at {
+        return Err("Role proposal has expired".to_string());
+    }
+    assign_role(caller_principal, pending.role.clone())?;
+    let event_type = if pending.role == Role::Admin { EventType::AdminAdded } else { EventType::RoleSet };
+    record_event(event_type, caller_principal, format!("Accepted role {:?} proposed by {}", pending.role, pending.proposed_by));
+    Ok("Role updated".to_string())
+}
+
+#[query]
+pub fn get_pending_role(user: Principal) -> Option<PendingRole> {
+    PENDING_ROLES.with(|p| p.borrow().get(&user).cloned())
+}
+
+#[update]
+pub fn bootstrap_admin(admin: Principal) -> Result<String, String> {
+    check_not_paused()?;
+    if !is_controller(&caller()) {
+        return Err("Only a controller can bootstrap the admin".to_string());
+    }
+    let already_bootstrapped = BOOTSTRAPPED.with(|b| *b.borrow());
+    if already_bootstrapped {
+        return Err("Admin already bootstrapped".to_string());
+    }
+    // Drop the placeholder admin seeded before any real admin existed
+    ADMINS.with(|admins| admins.borrow_mut().retain(|a| get_role(a) == Role::Admin));
+    assign_role(admin, Role::Admin)?;
+    BOOTSTRAPPED.with(|b| *b.borrow_mut() = true);
+    record_event(EventType::AdminBootstrapped, caller(), format!("Bootstrapped admin {}", admin));
+    Ok("Admin bootstrapped".to_string())
+}
+
+/// Sets a principal's role, keeping ADMINS consistent with ROLES. Refuses to
+/// demote the last remaining admin.
+fn check_not_last_admin(user: &Principal, role: &Role) -> Result<(), String> {
+    if *role != Role::Admin && get_role(user) == Role::Admin {
+        let admin_count = ROLES.with(|roles| roles.borrow().values().filter(|r| **r == Role::Admin).count());
+        if admin_count <= 1 {
+            return Err("Cannot remove last admin".to_string());
+        }
+    }
+    Ok(())
+}
+
+fn assign_role(user: Principal, role: Role) -> Result<(), String> {
+    check_not_last_admin(&user, &role)?;
+    ROLES.with(|roles| {
+        roles.borrow_mut().insert(user, role.clone());
+    });
+    ADMINS.with(|admins| {
+        let mut admins = admins.borrow_mut();
+        if role == Role::Admin {
+            if !admins.contains(&user) {
+                admins.push(user);
+            }
+        } else {
+            admins.retain(|a| *a != user);
+        }
+    });
+    Ok(())
+}
+
+/// Proposes Admin for `user`; like any other role it only applies once they
+/// call `accept_role`.
+#[update]
+pub fn add_admin(user: Principal) -> Result<String, String> {
+    if get_role(&caller()) != Role::Admin {
+        return Err("Only admin can add admins".to_string());
+    }
+    propose_role(user, Role::Admin)
+}
+
+/// Demotes an admin to a plain user. The final admin cannot be removed.
+#[update]
+pub fn remove_admin(user: Principal) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can remove admins".to_string());
+    }
+    if get_role(&user) != Role::Admin {
+        return Err("User is not an admin".to_string());
+    }
+    assign_role(user, Role::User)?;
+    record_event(EventType::AdminRemoved, caller_principal, format!("Removed admin {}", user));
+    Ok("Admin removed".to_string())
+}
+
+#[query]
+pub fn get_admins() -> Vec<Principal> {
+    ADMINS.with(|admins| admins.borrow().clone())
+}
+
+/// Principal that installed the canister, captured at init.
+#[query]
+pub fn get_deployer() -> Option<Principal> {
+    DEPLOYER.with(|d| *d.borrow())
+}
+
+#[query]
+pub fn get_my_role() -> Role {
+    get_role(&caller())
+}
+
+#[query]
+pub fn is_my_kyc_verified() -> bool {
+    is_kyc_verified(&caller())
+}
+
+#[update]
+pub fn update_property_metadata(property_id: PropertyId, metadata: PropertyMetadata) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if !can_manage(&caller_principal) {
+        return Err("Only admin or manager can update property metadata".to_string());
+    }
+    apply_property_metadata(property_id, metadata, caller_principal)
+}
+
+fn normalize_tag(tag: &str) -> String {
+    tag.trim().to_lowercase()
+}
+
+/// Normalizes tags and trims the geo fields, rejecting out-of-range coordinates.
+fn normalize_metadata(mut metadata: PropertyMetadata) -> Result<PropertyMetadata, String> {
+    if metadata.lat.is_some_and(|lat| !(-90.0..=90.0).contains(&lat)) {
+        return Err("Latitude must be between -90 and 90".to_string());
+    }
+    if metadata.lng.is_some_and(|lng| !(-180.0..=180.0).contains(&lng)) {
+        return Err("Longitude must be between -180 and 180".to_string());
+    }
+    metadata.country = metadata.country.trim().to_string();
+    metadata.city = metadata.city.trim().to_string();
+    let mut tags: Vec<String> = metadata.tags.iter().map(|t| normalize_tag(t)).filter(|t| !t.is_empty()).collect();
+    tags.sort();
+    tags.dedup();
+    metadata.tags = tags;
+    Ok(metadata)
+}
+
+fn apply_property_metadata(property_id: PropertyId, metadata: PropertyMetadata, actor: Principal) -> Result<String, String> {
+    let metadata = normalize_metadata(metadata)?;
+    PROPERTIES.with(|props| {
+        let mut props = props.borrow_mut();
+        if let Some(prop) = props.get_mut(&property_id) {
+            prop.metadata = metadata;
+            record_property_event(EventType::PropertyMetadataUpdated, property_id, actor, format!("Updated metadata for property {} (id: {})", prop.name, property_id));
+            Ok("Property metadata updated".to_string())
+        } else {
+            Err("Property not found".to_string())
+        }
+    })
+    .inspect(|_| certify_property(property_id))
+}
+
+#[update]
+pub fn update_property_status(property_id: PropertyId, status: PropertyStatus) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if !can_manage(&caller_principal) {
+        return Err("Only admin or manager can update property status".to_string());
+    }
+    apply_property_status(property_id, status, caller_principal)
+}
+
+/// Active and Maintenance move freely between each other; either can move to
+/// Sold, which is terminal.
+fn is_valid_status_transition(from: &PropertyStatus, to: &PropertyStatus) -> bool {
+    use PropertyStatus::*;
+    matches!((from, to), (Active | Maintenance, Active | Maintenance | Sold))
+}
+
+fn apply_property_status(property_id: PropertyId, status: PropertyStatus, actor: Principal) -> Result<String, String> {
+    let status_for_log = status.clone();
+    PROPERTIES.with(|props| {
+        let mut props = props.borrow_mut();
+        if let Some(prop) = props.get_mut(&property_id) {
+            if !is_valid_status_transition(&prop.status, &status) {
+                return Err("Invalid status transition".to_string());
+            }
+            prop.status = status;
+            record_property_event(EventType::PropertyStatusUpdated, property_id, actor, format!("Updated status for property {} (id: {}) to {:?}", prop.name, property_id, status_for_log));
+            Ok("Property status updated".to_string())
+        } else {
+            Err("Property not found".to_string())
+        }
+    })
+    .inspect(|_| certify_property(property_id))
+}
+
+/// Admin corrects a property's name. Holders can do the same through a Rename proposal.
+#[update]
+pub fn rename_property(property_id: PropertyId, new_name: String) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can rename properties".to_string());
+    }
+    apply_property_rename(property_id, new_name, caller_principal)
+}
+
+// The token symbol is derived from the id, so only the name itself changes
+fn apply_property_rename(property_id: PropertyId, new_name: String, actor: Principal) -> Result<String, String> {
+    let new_name = new_name.trim().to_string();
+    if new_name.is_empty() {
+        return Err("Property name cannot be empty".to_string());
+    }
+    // Renaming to a different capitalization of the same name is allowed
+    if NAME_INDEX.with(|n| n.borrow().get(&name_key(&new_name)).is_some_and(|id| *id != property_id)) {
+        return Err(format!("A property named {} already exists", new_name));
+    }
+    let old_name = PROPERTIES.with(|props| {
+        let mut props = props.borrow_mut();
+        let prop = props.get_mut(&property_id).ok_or("Property not found".to_string())?;
+        Ok::<_, String>(std::mem::replace(&mut prop.name, new_name.clone()))
+    })?;
+    NAME_INDEX.with(|n| {
+        let mut n = n.borrow_mut();
+        if n.get(&name_key(&old_name)) == Some(&property_id) {
+            n.remove(&name_key(&old_name));
+        }
+        n.insert(name_key(&new_name), property_id);
+    });
+    certify_property(property_id);
+    record_property_event(EventType::PropertyRenamed, property_id, actor, format!("Renamed property {} from {} to {}", property_id, old_name, new_name));
+    Ok("Property renamed".to_string())
+}
+
+fn apply_transfers_paused(property_id: PropertyId, paused: bool, actor: Principal) -> Result<String, String> {
+    PROPERTIES.with(|props| {
+        let mut props = props.borrow_mut();
+        let prop = props.get_mut(&property_id).ok_or("Property not found".to_string())?;
+        prop.transfers_paused = paused;
+        Ok::<_, String>(())
+    })?;
+    certify_property(property_id);
+    record_property_event(EventType::TransfersPausedSet, property_id, actor, format!("Set transfers paused {} for property {}", paused, property_id));
+    Ok("Transfer pause updated".to_string())
+}
+
+/// Admin freezes or unfreezes a property. Income still accrues while frozen,
+/// but shares cannot move and claims are blocked.
+#[update]
+pub fn set_property_frozen(property_id: PropertyId, frozen: bool) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can freeze properties".to_string());
+    }
+    PROPERTIES.with(|props| {
+        let mut props = props.borrow_mut();
+        let prop = props.get_mut(&property_id).ok_or("Property not found".to_string())?;
+        prop.frozen = frozen;
+        Ok::<_, String>(())
+    })?;
+    certify_property(property_id);
+    record_property_event(EventType::PropertyFrozenSet, property_id, caller_principal, format!("Set frozen {} for property {}", frozen, property_id));
+    Ok("Property frozen status updated".to_string())
+}
+
+/// Checks registration inputs and returns the trimmed name to store.
+fn validate_new_property(name: &str, total_shares: u64, max_ownership_bps: Option<u16>, decimals: u8) -> Result<String, String> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err("Property name cannot be empty".to_string());
+    }
+    if total_shares == 0 {
+        return Err("Total shares must be greater than zero".to_string());
+    }
+    if max_ownership_bps.is_some_and(|bps| bps > 10_000) {
+        return Err("Ownership cap cannot exceed 10000 bps".to_string());
+    }
+    if decimals > MAX_DECIMALS {
+        return Err(format!("Decimals cannot exceed {}", MAX_DECIMALS));
+    }
+    check_name_available(name)?;
+    Ok(name.to_string())
+}
+
+// Update register_property to include metadata and status
+#[update]
+pub fn register_property(name: String, total_shares: u64, metadata: PropertyMetadata, max_ownership_bps: Option<u16>, decimals: u8) -> Result<Property, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can register properties".to_string());
+    }
+    let name = validate_new_property(&name, total_shares, max_ownership_bps, decimals)?;
+    let metadata = normalize_metadata(metadata)?;
+    Ok(insert_property(name, total_shares, metadata, max_ownership_bps, decimals, caller_principal))
+}
+
+/// Admin registers many properties at once, with no ownership cap and whole
+/// shares. Every entry is validated (including name uniqueness within the
+/// batch) before any is created, so the batch either fully succeeds or
+/// changes nothing. Properties get consecutive ids in batch order.
+#[update]
+pub fn register_properties(batch: Vec<(String, u64, PropertyMetadata)>) -> Result<Vec<Property>, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can register properties".to_string());
+    }
+    let mut seen: HashSet<String> = HashSet::new();
+    let mut validated = Vec::with_capacity(batch.len());
+    for (index, (name, total_shares, metadata)) in batch.into_iter().enumerate() {
+        let name = validate_new_property(&name, total_shares, None, 0).map_err(|e| format!("Entry {}: {}", index, e))?;
+        if !seen.insert(name_key(&name)) {
+            return Err(format!("Entry {}: Duplicate property name {} in batch", index, name));
+        }
+        let metadata = normalize_metadata(metadata).map_err(|e| format!("Entry {}: {}", index, e))?;
+        validated.push((name, total_shares, metadata));
+    }
+    Ok(validated.into_iter().map(|(name, total_shares, metadata)| insert_property(name, total_shares, metadata, None, 0, caller_principal)).collect())
+}
+
+/// Creates an already-validated property under the next id.
+fn insert_property(name: String, total_shares: u64, metadata: PropertyMetadata, max_ownership_bps: Option<u16>, decimals: u8, actor: Principal) -> Property {
+    let property = PROPERTIES.with(|props| {
+        let mut props = props.borrow_mut();
+        let id = NEXT_PROPERTY_ID.with(|id| {
+            let mut id = id.borrow_mut();
+            let curr = *id;
+            *id += 1;
+            curr
+        });
+        let property = Property {
+            id,
+            name: name.clone(),
+            total_shares,
             shares_available: total_shares,
-            metadata: metadata.clone(),
+            decimals,
+            metadata,
             status: PropertyStatus::Active,
+            max_ownership_bps,
+            frozen: false,
+            transfers_paused: false,
+            fully_subscribed_at: None,
+            share_classes: Vec::new(),
+        };
+        props.insert(id, property.clone());
+        NAME_INDEX.with(|n| n.borrow_mut().insert(name_key(&name), id));
+        property
+    });
+    certify_property(property.id);
+    record_property_event(EventType::PropertyRegistered, property.id, actor, format!("Registered property: {} (id: {})", name, property.id));
+    property
+}
+
+#[update]
+pub fn issue_shares(property_id: PropertyId, to: Principal, amount: u64, lockup_secs: Option<u64>) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if !can_manage(&caller_principal) {
+        return Err("Only admin or manager can issue shares".to_string());
+    }
+    check_not_blacklisted(&[&caller_principal, &to])?;
+    check_unclassed(property_id)?;
+    check_not_sold(property_id)?;
+    check_recipient_kyc(&to)?;
+    check_whitelisted(property_id, &to)?;
+    check_ownership_cap(property_id, &to, amount)?;
+    let mut success = false;
+    PROPERTIES.with(|props| {
+        let mut props = props.borrow_mut();
+        if let Some(prop) = props.get_mut(&property_id) {
+            if prop.shares_available >= amount {
+                prop.shares_available -= amount;
+                mark_if_fully_subscribed(prop, caller_principal);
+                success = true;
+            }
+        }
+    });
+    if success {
+        credit_shares(property_id, to, amount);
+        if let Some(secs) = lockup_secs {
+            // Extend, never shorten, an existing lockup
+            let unlock_at = now().saturating_add(secs.saturating_mul(1_000_000_000));
+            LOCKUPS.with(|l| {
+                let mut l = l.borrow_mut();
+                let entry = l.entry((property_id, to)).or_insert(0);
+                *entry = (*entry).max(unlock_at);
+            });
+        }
+        assert_share_invariant(property_id);
+        certify_property(property_id);
+        record_property_event(EventType::SharesIssued, property_id, caller_principal, format!("Issued {} shares of property {} to {}", amount, property_id, to));
+        Ok("Shares issued".to_string())
+    } else {
+        Err("Not enough shares or property not found".to_string())
+    }
+}
+
+/// Stamps `fully_subscribed_at` the first time a property's availability hits zero.
+fn mark_if_fully_subscribed(prop: &mut Property, actor: Principal) {
+    if prop.shares_available == 0 && prop.fully_subscribed_at.is_none() {
+        prop.fully_subscribed_at = Some(now());
+        record_property_event(EventType::PropertyFullySubscribed, prop.id, actor, format!("Property {} (id: {}) is fully subscribed", prop.name, prop.id));
+    }
+}
+
+/// Admin unwinds an offering that never filled: every holder's shares go back
+/// to `shares_available` and open listings, lockups and allowances for the
+/// property are dropped. Only allowed while the property is Active and has
+/// never been fully subscribed. Income already allocated stays claimable.
+#[update]
+pub fn cancel_offering(property_id: PropertyId) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can cancel offerings".to_string());
+    }
+    let property = get_property(property_id).ok_or("Property not found".to_string())?;
+    if property.status != PropertyStatus::Active || property.fully_subscribed_at.is_some() {
+        return Err("Offering is no longer in its subscription phase".to_string());
+    }
+    let holders = holders_of(property_id);
+    for (holder, shares) in &holders {
+        debit_shares(property_id, *holder, *shares)?;
+        clear_class_holdings(property_id, *holder);
+    }
+    PROPERTIES.with(|props| {
+        if let Some(prop) = props.borrow_mut().get_mut(&property_id) {
+            prop.shares_available = prop.total_shares;
+        }
+    });
+    MARKETPLACE.with(|mp| mp.borrow_mut().retain(|l| l.property_id != property_id));
+    LOCKUPS.with(|l| l.borrow_mut().retain(|(pid, _), _| *pid != property_id));
+    ALLOWANCES.with(|a| a.borrow_mut().retain(|(pid, _, _), _| *pid != property_id));
+    assert_share_invariant(property_id);
+    certify_property(property_id);
+    record_property_event(EventType::OfferingCancelled, property_id, caller_principal, format!("Cancelled offering for property {} (id: {}), reclaiming shares from {} holders", property.name, property_id, holders.len()));
+    Ok(format!("Offering cancelled; reclaimed shares from {} holders", holders.len()))
+}
+
+/// Whether every share of the property is currently issued.
+#[query]
+pub fn is_fully_subscribed(property_id: PropertyId) -> bool {
+    get_property(property_id).is_some_and(|p| p.shares_available == 0)
+}
+
+/// Admin issues shares to many recipients at once. Every allocation is validated
+/// before any is applied, so the batch either fully succeeds or changes nothing.
+#[update]
+pub fn batch_issue_shares(property_id: PropertyId, allocations: Vec<(Principal, u64)>) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can batch issue shares".to_string());
+    }
+    check_not_blacklisted(&[&caller_principal])?;
+    check_unclassed(property_id)?;
+    check_not_sold(property_id)?;
+    let property = get_property(property_id).ok_or("Property not found".to_string())?;
+    // Merge repeated recipients so per-holder checks see their full allocation
+    let mut per_recipient: HashMap<Principal, u64> = HashMap::new();
+    let mut total: u64 = 0;
+    for (to, amount) in &allocations {
+        total = total.checked_add(*amount).ok_or("Allocation total overflow".to_string())?;
+        let entry = per_recipient.entry(*to).or_insert(0);
+        *entry = entry.saturating_add(*amount);
+    }
+    if total > property.shares_available {
+        return Err("Not enough shares available for batch".to_string());
+    }
+    for (to, amount) in &per_recipient {
+        check_not_blacklisted(&[to])?;
+        check_recipient_kyc(to)?;
+        check_whitelisted(property_id, to)?;
+        check_ownership_cap(property_id, to, *amount)?;
+    }
+    PROPERTIES.with(|props| {
+        if let Some(prop) = props.borrow_mut().get_mut(&property_id) {
+            prop.shares_available -= total;
+            mark_if_fully_subscribed(prop, caller_principal);
+        }
+    });
+    for (to, amount) in &per_recipient {
+        credit_shares(property_id, *to, *amount);
+    }
+    assert_share_invariant(property_id);
+    certify_property(property_id);
+    for (to, amount) in &allocations {
+        record_property_event(EventType::SharesIssued, property_id, caller_principal, format!("Issued {} shares of property {} to {}", amount, property_id, to));
+    }
+    Ok(format!("Issued shares to {} recipients", per_recipient.len()))
+}
+
+/// Caller burns shares they hold. A non-permanent burn retires them back to
+/// `shares_available`; a permanent burn (admin-only) reduces `total_shares`.
+#[update]
+pub fn burn_shares(property_id: PropertyId, amount: u64, permanent: bool) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if permanent && get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can permanently burn shares".to_string());
+    }
+    check_not_blacklisted(&[&caller_principal])?;
+    check_not_frozen(property_id)?;
+    check_unclassed(property_id)?;
+    check_not_sold(property_id)?;
+    if get_property(property_id).is_none() {
+        return Err("Property not found".to_string());
+    }
+    let unlisted = get_ownership(property_id, caller_principal).saturating_sub(get_listed_shares(property_id, caller_principal));
+    if unlisted < amount {
+        return Err("Not enough unlisted shares to burn".to_string());
+    }
+    debit_shares(property_id, caller_principal, amount)?;
+    PROPERTIES.with(|props| {
+        if let Some(prop) = props.borrow_mut().get_mut(&property_id) {
+            if permanent {
+                prop.total_shares -= amount;
+            } else {
+                prop.shares_available += amount;
+            }
+        }
+    });
+    assert_share_invariant(property_id);
+    certify_property(property_id);
+    record_property_event(EventType::SharesBurned, property_id, caller_principal, format!("Burned {} shares of property {} (permanent: {})", amount, property_id, permanent));
+    Ok("Shares burned".to_string())
+}
+
+/// Admin splits a property's shares into classes, e.g. preferred and common
+/// tranches with different income weights. Class totals must add up to
+/// `total_shares` and weights to 10000 bps; an empty list reverts to a single
+/// class of common shares. Only allowed before any shares are issued.
+#[update]
+pub fn set_share_classes(property_id: PropertyId, classes: Vec<ShareClass>) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can set share classes".to_string());
+    }
+    check_not_sold(property_id)?;
+    let property = get_property(property_id).ok_or("Property not found".to_string())?;
+    if property.shares_available != property.total_shares {
+        return Err("Share classes can only be set before any shares are issued".to_string());
+    }
+    let mut classes = classes;
+    let mut total: u64 = 0;
+    let mut weight: u32 = 0;
+    for class in classes.iter_mut() {
+        class.name = class.name.trim().to_string();
+        if class.name.is_empty() {
+            return Err("Share class name cannot be empty".to_string());
+        }
+        if class.total == 0 {
+            return Err("Share class total must be greater than zero".to_string());
+        }
+        total = total.checked_add(class.total).ok_or("Share class total overflow".to_string())?;
+        weight += class.income_weight_bps as u32;
+    }
+    if !classes.is_empty() {
+        if total != property.total_shares {
+            return Err("Share class totals must add up to total shares".to_string());
+        }
+        if weight != 10_000 {
+            return Err("Share class income weights must add up to 10000 bps".to_string());
+        }
+    }
+    PROPERTIES.with(|props| {
+        if let Some(prop) = props.borrow_mut().get_mut(&property_id) {
+            prop.share_classes = classes.clone();
+        }
+    });
+    certify_property(property_id);
+    record_property_event(EventType::ShareClassesSet, property_id, caller_principal, format!("Set {} share classes for property {}", classes.len(), property_id));
+    Ok("Share classes set".to_string())
+}
+
+fn get_share_class(property_id: PropertyId, class_index: u32) -> Result<ShareClass, String> {
+    let property = get_property(property_id).ok_or("Property not found".to_string())?;
+    property.share_classes.get(class_index as usize).cloned().ok_or("Share class not found".to_string())
+}
+
+/// `issue_shares` for a property with share classes.
+#[update]
+pub fn issue_class_shares(property_id: PropertyId, class_index: u32, to: Principal, amount: u64) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if !can_manage(&caller_principal) {
+        return Err("Only admin or manager can issue shares".to_string());
+    }
+    check_not_blacklisted(&[&caller_principal, &to])?;
+    check_not_sold(property_id)?;
+    let class = get_share_class(property_id, class_index)?;
+    check_recipient_kyc(&to)?;
+    check_whitelisted(property_id, &to)?;
+    check_ownership_cap(property_id, &to, amount)?;
+    if class.total - class_issued(property_id, class_index) < amount {
+        return Err("Not enough shares of this class".to_string());
+    }
+    PROPERTIES.with(|props| {
+        if let Some(prop) = props.borrow_mut().get_mut(&property_id) {
+            prop.shares_available -= amount;
+            mark_if_fully_subscribed(prop, caller_principal);
+        }
+    });
+    credit_class_shares(property_id, class_index, to, amount);
+    assert_share_invariant(property_id);
+    certify_property(property_id);
+    record_property_event(EventType::SharesIssued, property_id, caller_principal, format!("Issued {} {} shares of property {} to {}", amount, class.name, property_id, to));
+    Ok("Shares issued".to_string())
+}
+
+/// Caller transfers shares of one class to another user. Subject to the same
+/// checks and transfer fee as `transfer_shares`, and logged in TRANSFERS with
+/// its class. Share-class properties can't otherwise be traded: the generic
+/// transfer, marketplace, burn, buyback and force-transfer endpoints reject
+/// them (see `check_unclassed`).
+#[update]
+pub fn transfer_class_shares(property_id: PropertyId, class_index: u32, to: Principal, amount: u64) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if caller_principal == to {
+        return Err("Cannot transfer shares to yourself".to_string());
+    }
+    check_not_blacklisted(&[&caller_principal, &to])?;
+    let class = get_share_class(property_id, class_index)?;
+    check_not_frozen(property_id)?;
+    check_transfers_not_paused(property_id)?;
+    check_recipient_kyc(&to)?;
+    check_whitelisted(property_id, &to)?;
+    check_lockup(property_id, &caller_principal)?;
+    let fee = transfer_fee(amount);
+    let received = amount - fee;
+    check_ownership_cap(property_id, &to, received)?;
+    debit_class_shares(property_id, class_index, caller_principal, amount)?;
+    credit_class_shares(property_id, class_index, to, received);
+    return_fee_shares(property_id, fee);
+    assert_share_invariant(property_id);
+    certify_property(property_id);
+    let tx_index = record_transfer(property_id, caller_principal, to, amount, fee, None, Some(class_index));
+    record_property_event(EventType::SharesTransferred, property_id, caller_principal, format!("Transferred {} {} shares of property {} from {} to {} (tx {}, fee {})", amount, class.name, property_id, caller_principal, to, tx_index, fee));
+    Ok("Shares transferred".to_string())
+}
+
+/// Shares of one class held by `user`.
+#[query]
+pub fn get_class_ownership(property_id: PropertyId, class_index: u32, user: Principal) -> u64 {
+    class_shares(property_id, class_index, user)
+}
+
+/// Unlock time (ns) of a holder's shares in a property; 0 if never locked.
+#[query]
+pub fn get_lockup(property_id: PropertyId, user: Principal) -> u64 {
+    LOCKUPS.with(|l| l.borrow().get(&(property_id, user)).cloned().unwrap_or(0))
+}
+
+/// Admin removes a property registered by mistake. Only allowed while no
+/// shares have been issued; the record is kept in ARCHIVED_PROPERTIES.
+/// Its documents and valuations are kept with it for history, while its
+/// configuration (whitelist, claim window, price bounds, recurring income,
+/// delegations, allowances and lockups) is cleared.
+#[update]
+pub fn archive_property(property_id: PropertyId) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can archive properties".to_string());
+    }
+    let property = get_property(property_id).ok_or("Property not found".to_string())?;
+    if property.shares_available != property.total_shares {
+        return Err("Cannot archive a property with issued shares".to_string());
+    }
+    if get_holder_count(property_id) > 0 {
+        return Err("Cannot archive a property with holders".to_string());
+    }
+    if get_reserve(property_id) > 0 {
+        return Err("Cannot archive a property with reserve funds".to_string());
+    }
+    if BUYBACKS.with(|b| b.borrow().contains_key(&property_id)) {
+        return Err("Cannot archive a property with an open buyback".to_string());
+    }
+    PROPERTIES.with(|props| props.borrow_mut().remove(&property_id));
+    NAME_INDEX.with(|n| {
+        let mut n = n.borrow_mut();
+        if n.get(&name_key(&property.name)) == Some(&property_id) {
+            n.remove(&name_key(&property.name));
+        }
+    });
+    certify_property(property_id);
+    PRICE_BOUNDS.with(|pb| pb.borrow_mut().remove(&property_id));
+    stop_recurring_income(property_id);
+    BUYER_WHITELISTS.with(|w| w.borrow_mut().remove(&property_id));
+    CLAIM_WINDOWS.with(|w| w.borrow_mut().remove(&property_id));
+    DELEGATIONS.with(|d| d.borrow_mut().retain(|(pid, _), _| *pid != property_id));
+    ALLOWANCES.with(|a| a.borrow_mut().retain(|(pid, _, _), _| *pid != property_id));
+    LOCKUPS.with(|l| l.borrow_mut().retain(|(pid, _), _| *pid != property_id));
+    ARCHIVED_PROPERTIES.with(|archived| archived.borrow_mut().insert(property_id, property.clone()));
+    record_property_event(EventType::PropertyArchived, property_id, caller_principal, format!("Archived property: {} (id: {})", property.name, property_id));
+    Ok("Property archived".to_string())
+}
+
+#[query]
+pub fn get_archived_properties() -> Vec<Property> {
+    let mut archived: Vec<Property> = ARCHIVED_PROPERTIES.with(|archived| archived.borrow().values().cloned().collect());
+    archived.sort_by_key(|p| p.id);
+    archived
+}
+
+#[query]
+pub fn get_property(property_id: PropertyId) -> Option<Property> {
+    PROPERTIES.with(|props| props.borrow().get(&property_id).cloned())
+}
+
+/// Looks a property up by name, ignoring case and surrounding whitespace.
+#[query]
+pub fn get_property_by_name(name: String) -> Option<Property> {
+    NAME_INDEX.with(|n| n.borrow().get(&name_key(&name)).cloned()).and_then(get_property)
+}
+
+/// Token symbol for a property, e.g. "RWA7" for property 7.
+fn property_symbol(property_id: PropertyId) -> String {
+    format!("RWA{}", property_id)
+}
+
+/// ICRC-1 style metadata for a property treated as a token. Empty if the property does not exist.
+#[query]
+pub fn icrc1_metadata(property_id: PropertyId) -> Vec<(String, MetadataValue)> {
+    let Some(property) = get_property(property_id) else {
+        return Vec::new();
+    };
+    vec![
+        ("icrc1:name".to_string(), MetadataValue::Text(property.name)),
+        ("icrc1:symbol".to_string(), MetadataValue::Text(property_symbol(property_id))),
+        ("icrc1:decimals".to_string(), MetadataValue::Nat(property.decimals.into())),
+        ("icrc1:total_supply".to_string(), MetadataValue::Nat(property.total_shares.into())),
+    ]
+}
+
+/// ICRC-1 style alias for `get_ownership`.
+#[query]
+pub fn icrc1_balance_of(property_id: PropertyId, owner: Principal) -> u64 {
+    get_ownership(property_id, owner)
+}
+
+/// ICRC-1 style alias for `get_total_shares`; unissued shares count toward supply,
+/// matching `icrc1:total_supply` in the metadata.
+#[query]
+pub fn icrc1_total_supply(property_id: PropertyId) -> u64 {
+    get_total_shares(property_id)
+}
+
+/// Recompute the property's holdings and check they match its issued shares.
+#[query]
+pub fn verify_property_integrity(property_id: PropertyId) -> bool {
+    share_invariant_holds(property_id)
+}
+
+/// Properties whose city matches, ignoring case and surrounding whitespace, sorted by id.
+#[query]
+pub fn search_properties_by_city(city: String) -> Vec<Property> {
+    let city = city.trim().to_lowercase();
+    let mut matching: Vec<Property> = PROPERTIES.with(|props| props.borrow().values().filter(|p| p.metadata.city.to_lowercase() == city).cloned().collect());
+    matching.sort_by_key(|p| p.id);
+    matching
+}
+
+/// Distinct tags used by any property, sorted.
+#[query]
+pub fn get_all_tags() -> Vec<String> {
+    let tags: BTreeSet<String> = PROPERTIES.with(|props| props.borrow().values().flat_map(|p| p.metadata.tags.iter().cloned()).collect());
+    tags.into_iter().collect()
+}
+
+/// Get a page of properties sorted by id, optionally filtered by status
+/// and by tag (case-insensitive). `total` counts all properties matching the filter.
+#[query]
+pub fn get_all_properties(offset: u64, limit: u64, status_filter: Option<PropertyStatus>, tag: Option<String>) -> PropertyPage {
+    let limit = limit.min(MAX_PAGE_SIZE) as usize;
+    let tag = tag.map(|t| normalize_tag(&t));
+    PROPERTIES.with(|props| {
+        let props = props.borrow();
+        let mut matching: Vec<&Property> = props
+            .values()
+            .filter(|p| status_filter.as_ref().is_none_or(|s| p.status == *s))
+            .filter(|p| tag.as_ref().is_none_or(|t| p.metadata.tags.contains(t)))
+            .collect();
+        matching.sort_by_key(|p| p.id);
+        PropertyPage {
+            total: matching.len() as u64,
+            properties: matching.into_iter().skip(offset as usize).take(limit).cloned().collect(),
+        }
+    })
+}
+
+#[query]
+pub fn get_ownership(property_id: PropertyId, user: Principal) -> u64 {
+    OWNERSHIP.with(|own| own.borrow().get(&(property_id, user)).cloned().unwrap_or(0))
+}
+
+/// A holder's balance as of `timestamp` (ns), i.e. after every change recorded
+/// at or before that time. 0 before their first recorded change.
+#[query]
+pub fn get_balance_at(property_id: PropertyId, user: Principal, timestamp: u64) -> u64 {
+    BALANCE_HISTORY.with(|bh| {
+        bh.borrow()
+            .get(&(property_id, user))
+            .and_then(|history| {
+                let idx = history.partition_point(|(t, _)| *t <= timestamp);
+                idx.checked_sub(1).map(|i| history[i].1)
+            })
+            .unwrap_or(0)
+    })
+}
+
+#[query]
+pub fn get_total_shares(property_id: PropertyId) -> u64 {
+    get_property(property_id).map(|p| p.total_shares).unwrap_or(0)
+}
+
+/// Shares issued to holders, i.e. `total_shares - shares_available`.
+#[query]
+pub fn get_circulating_shares(property_id: PropertyId) -> u64 {
+    get_property(property_id).map(|p| p.total_shares.saturating_sub(p.shares_available)).unwrap_or(0)
+}
+
+#[query]
+pub fn get_holder_count(property_id: PropertyId) -> u64 {
+    HOLDERS_BY_PROPERTY.with(|h| h.borrow().get(&property_id).map(|holders| holders.len() as u64).unwrap_or(0))
+}
+
+/// Holders of a property with their share counts, largest first (ties by principal).
+#[query]
+pub fn get_property_holders(property_id: PropertyId, offset: u64, limit: u64) -> Vec<(Principal, u64)> {
+    let limit = limit.min(MAX_PAGE_SIZE) as usize;
+    let mut holders = holders_of(property_id);
+    holders.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    holders.into_iter().skip(offset as usize).take(limit).collect()
+}
+
+/// Admin schedules `amount` of rental income to be distributed every
+/// `interval_secs`. Replaces any existing schedule for the property.
+#[update]
+pub fn schedule_recurring_income(property_id: PropertyId, amount: u64, interval_secs: u64) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can schedule recurring income".to_string());
+    }
+    if PROPERTIES.with(|props| !props.borrow().contains_key(&property_id)) {
+        return Err("Property not found".to_string());
+    }
+    if amount == 0 || interval_secs == 0 {
+        return Err("Amount and interval must be positive".to_string());
+    }
+    stop_recurring_income(property_id);
+    let schedule = RecurringIncome { property_id, amount, interval_secs, scheduled_by: caller_principal };
+    arm_recurring_income(&schedule);
+    RECURRING_INCOME.with(|ri| ri.borrow_mut().insert(property_id, schedule));
+    record_property_event(EventType::RecurringIncomeScheduled, property_id, caller_principal, format!("Scheduled {} rental income every {}s for property {}", amount, interval_secs, property_id));
+    Ok("Recurring income scheduled".to_string())
+}
+
+#[update]
+pub fn cancel_recurring_income(property_id: PropertyId) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can cancel recurring income".to_string());
+    }
+    if !stop_recurring_income(property_id) {
+        return Err("No recurring income scheduled".to_string());
+    }
+    record_property_event(EventType::RecurringIncomeCancelled, property_id, caller_principal, format!("Cancelled recurring income for property {}", property_id));
+    Ok("Recurring income cancelled".to_string())
+}
+
+#[query]
+pub fn get_recurring_income(property_id: PropertyId) -> Option<RecurringIncome> {
+    RECURRING_INCOME.with(|ri| ri.borrow().get(&property_id).cloned())
+}
+
+fn arm_recurring_income(schedule: &RecurringIncome) {
+    let property_id = schedule.property_id;
+    let timer_id = set_timer_interval(Duration::from_secs(schedule.interval_secs), move || run_recurring_income(property_id));
+    RECURRING_TIMERS.with(|t| t.borrow_mut().insert(property_id, timer_id));
+}
+
+/// Removes a property's schedule and clears its timer. Returns whether one existed.
+fn stop_recurring_income(property_id: PropertyId) -> bool {
+    if let Some(timer_id) = RECURRING_TIMERS.with(|t| t.borrow_mut().remove(&property_id)) {
+        clear_timer(timer_id);
+    }
+    RECURRING_INCOME.with(|ri| ri.borrow_mut().remove(&property_id)).is_some()
+}
+
+/// Timer callback: distributes one period of scheduled income.
+fn run_recurring_income(property_id: PropertyId) {
+    let Some(schedule) = get_recurring_income(property_id) else {
+        return;
+    };
+    // Skipped deposits are not made up after unpausing
+    if let Err(e) = check_not_paused() {
+        ic_cdk::println!("Recurring income for property {} skipped: {}", property_id, e);
+        return;
+    }
+    if let Err(e) = distribute_income(property_id, schedule.amount, schedule.scheduled_by) {
+        ic_cdk::println!("Recurring income for property {} failed: {}", property_id, e);
+    }
+}
+
+/// Admin or manager deposits rental income for a property. Distributes to all current owners proportionally.
+#[update]
+pub fn deposit_rental_income(property_id: PropertyId, amount: u64) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if !can_manage(&caller_principal) {
+        return Err("Unauthorized to deposit income".to_string());
+    }
+    check_not_blacklisted(&[&caller_principal])?;
+    distribute_income(property_id, amount, caller_principal)
+}
+
+/// Number of share classes a property has; zero means a single class of common shares.
+fn share_class_count(property_id: PropertyId) -> u32 {
+    PROPERTIES.with(|props| props.borrow().get(&property_id).map(|p| p.share_classes.len() as u32).unwrap_or(0))
+}
+
+/// Accumulator values right before and right after a deposit made while the
+/// property had a claim window, and when income from that deposit stops being
+/// claimable. Only accrual between the two can expire.
+#[derive(CandidType, Deserialize, Clone)]
+struct IncomeMark {
+    expires_at: u64,
+    accs: Vec<u128>,               // one per share class, or a single value without classes
+    start_accs: Option<Vec<u128>>, // `None` expires nothing
+}
+
+/// How a deposit splits: accumulator steps (one per share class, or a single
+/// step without classes), the whole units that accrue to issued shares, the
+/// part attributable to unissued shares, and the integer-division dust left
+/// over. Accrual below one unit is not lost: it stays in `fraction` and is
+/// counted as allocated once later deposits top it up to a whole unit.
+struct IncomeSplit {
+    increments: Vec<u128>,
+    allocated: u64,
+    fraction: u128,
+    unissued: u64,
+    dust: u64,
+}
+
+/// Splits a deposit of `amount`. Each class gets its `income_weight_bps` cut,
+/// spread evenly over the class total.
+fn income_increments(property_id: PropertyId, amount: u64) -> Result<IncomeSplit, String> {
+    let property = get_property(property_id).filter(|p| p.total_shares > 0).ok_or("Property not found or has no shares".to_string())?;
+    let mut increments = Vec::new();
+    let mut accrued: u128 = DISTRIBUTED_FRACTION.with(|f| f.borrow().get(&property_id).cloned().unwrap_or(0));
+    let mut unissued: u64 = 0;
+    if property.share_classes.is_empty() {
+        let increment = amount as u128 * INCOME_SCALE / property.total_shares as u128;
+        let issued = property.total_shares - property.shares_available;
+        accrued += issued as u128 * increment;
+        unissued = (amount as u128 * property.shares_available as u128 / property.total_shares as u128) as u64;
+        increments.push(increment);
+    } else {
+        for (index, class) in property.share_classes.iter().enumerate() {
+            let class_amount = amount as u128 * class.income_weight_bps as u128 / 10_000;
+            let increment = class_amount * INCOME_SCALE / class.total as u128;
+            let issued = class_issued(property_id, index as u32);
+            accrued += issued as u128 * increment;
+            unissued += (class_amount * (class.total - issued) as u128 / class.total as u128) as u64;
+            increments.push(increment);
+        }
+    }
+    let allocated = (accrued / INCOME_SCALE) as u64;
+    Ok(IncomeSplit { increments, allocated, fraction: accrued % INCOME_SCALE, unissued, dust: amount.saturating_sub(allocated + unissued) })
+}
+
+/// A holder's scaled share of one deposit, given that deposit's increments.
+fn deposit_accrual(property_id: PropertyId, holder: Principal, increments: &[u128]) -> u128 {
+    if share_class_count(property_id) == 0 {
+        get_ownership(property_id, holder) as u128 * increments[0]
+    } else {
+        increments.iter().enumerate().map(|(c, increment)| class_shares(property_id, c as u32, holder) as u128 * increment).sum()
+    }
+}
+
+/// Under `HalfUp`, rounds holders whose share of this deposit has a fraction
+/// of at least one half up to the next unit, for as long as the deposit's own
+/// dust covers it. Largest fractions go first, ties broken by principal, so
+/// the result depends only on the deposit and current holdings. Returns the
+/// scaled top-up per holder and moves it from `dust` into `allocated`.
+/// This needs every holder's fraction, so half-up deposits are O(holders).
+fn half_up_topups(property_id: PropertyId, split: &mut IncomeSplit) -> Vec<(Principal, u128)> {
+    if ROUNDING_MODE.with(|m| *m.borrow()) != RoundingMode::HalfUp {
+        return Vec::new();
+    }
+    let mut budget = (split.dust as u128 * INCOME_SCALE).saturating_sub(split.fraction);
+    let mut candidates: Vec<(Principal, u128)> = holders_of(property_id)
+        .into_iter()
+        .map(|(holder, _)| (holder, deposit_accrual(property_id, holder, &split.increments) % INCOME_SCALE))
+        .filter(|(_, fraction)| *fraction >= INCOME_SCALE / 2)
+        .collect();
+    candidates.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+    let mut topups = Vec::new();
+    for (holder, fraction) in candidates {
+        let topup = INCOME_SCALE - fraction;
+        // Fractions are sorted largest first, so every later top-up is bigger
+        if topup > budget {
+            break;
+        }
+        budget -= topup;
+        topups.push((holder, topup));
+    }
+    let accrued = split.allocated as u128 * INCOME_SCALE + split.fraction + topups.iter().map(|(_, topup)| topup).sum::<u128>();
+    let allocated = (accrued / INCOME_SCALE) as u64;
+    split.dust -= allocated - split.allocated;
+    split.allocated = allocated;
+    split.fraction = accrued % INCOME_SCALE;
+    topups
+}
+
+/// Each holder's share of a deposit of `amount` made now, in whole units and
+/// rounded under the current rounding mode. Under `Floor` the carried-over
+/// dust from earlier deposits is included. `remainder` is what the deposit
+/// leaves to the reserve or carry-over rather than the accumulator.
+fn compute_income_allocations(property_id: PropertyId, amount: u64) -> Result<DistributionPreview, String> {
+    let amount = amount.saturating_add(carried_income(property_id));
+    let mut split = income_increments(property_id, amount)?;
+    let topups: HashMap<Principal, u128> = half_up_topups(property_id, &mut split).into_iter().collect();
+    let mut allocations: Vec<(Principal, u64)> = holders_of(property_id)
+        .into_iter()
+        .map(|(user, _)| {
+            let scaled = deposit_accrual(property_id, user, &split.increments) + topups.get(&user).cloned().unwrap_or(0);
+            (user, (scaled / INCOME_SCALE) as u64)
+        })
+        .collect();
+    allocations.sort_by_key(|(user, _)| *user);
+    Ok(DistributionPreview { allocations, remainder: split.unissued + split.dust })
+}
+
+/// Dust held back from earlier deposits under `RoundingMode::Floor`.
+fn carried_income(property_id: PropertyId) -> u64 {
+    if ROUNDING_MODE.with(|m| *m.borrow()) != RoundingMode::Floor {
+        return 0;
+    }
+    INCOME_CARRY.with(|c| c.borrow().get(&property_id).cloned().unwrap_or(0))
+}
+
+/// A holder's accrual since their last settlement, still scaled by INCOME_SCALE.
+fn scaled_pending_income(property_id: PropertyId, holder: Principal) -> u128 {
+    scaled_accrual(&holder_basis(property_id, holder), &holder_checkpoints(property_id, holder), &current_accs(property_id))
+}
+
+/// The property's accumulator values: one per share class, or a single value without classes.
+fn current_accs(property_id: PropertyId) -> Vec<u128> {
+    let classes = share_class_count(property_id);
+    if classes == 0 {
+        vec![INCOME_PER_SHARE.with(|i| i.borrow().get(&property_id).cloned().unwrap_or(0))]
+    } else {
+        (0..classes).map(|c| INCOME_PER_CLASS_SHARE.with(|i| i.borrow().get(&(property_id, c)).cloned().unwrap_or(0))).collect()
+    }
+}
+
+/// A holder's shares in the layout of `current_accs`: one entry per share
+/// class, or their total without classes.
+fn holder_basis(property_id: PropertyId, holder: Principal) -> Vec<u64> {
+    let classes = share_class_count(property_id);
+    if classes == 0 {
+        vec![get_ownership(property_id, holder)]
+    } else {
+        (0..classes).map(|c| class_shares(property_id, c, holder)).collect()
+    }
+}
+
+/// A holder's accumulator checkpoints, in the layout of `current_accs`.
+fn holder_checkpoints(property_id: PropertyId, holder: Principal) -> Vec<u128> {
+    let classes = share_class_count(property_id);
+    if classes == 0 {
+        vec![INCOME_CHECKPOINTS.with(|c| c.borrow().get(&(property_id, holder)).cloned().unwrap_or(0))]
+    } else {
+        (0..classes).map(|c| CLASS_INCOME_CHECKPOINTS.with(|cp| cp.borrow().get(&(property_id, c, holder)).cloned().unwrap_or(0))).collect()
+    }
+}
+
+/// Scaled accrual of `basis` shares as the accumulators move from `from` to
+/// `to`. Values recorded under a different class layout accrue nothing.
+fn scaled_accrual(basis: &[u64], from: &[u128], to: &[u128]) -> u128 {
+    if from.len() != basis.len() || to.len() != basis.len() {
+        return 0;
+    }
+    basis.iter().zip(from.iter().zip(to)).map(|(shares, (from, to))| *shares as u128 * to.saturating_sub(*from)).sum()
+}
+
+/// Whole units of a holder's pending accrual that came from deposits made
+/// under a claim window, by income mark index. Accrual from other deposits
+/// is never in here, so it never expires.
+fn marked_accrual(property_id: PropertyId, holder: Principal) -> Vec<(u64, u64)> {
+    let basis = holder_basis(property_id, holder);
+    if basis.iter().all(|shares| *shares == 0) {
+        return Vec::new();
+    }
+    let checkpoints = holder_checkpoints(property_id, holder);
+    INCOME_MARKS.with(|m| {
+        let m = m.borrow();
+        let Some(marks) = m.get(&property_id) else {
+            return Vec::new();
         };
-        props.insert(id, property.clone());
-        property
+        // Accumulators only grow, so deposits already settled are a prefix
+        let first = marks.partition_point(|mark| mark.accs.len() != checkpoints.len() || mark.accs.iter().zip(&checkpoints).all(|(acc, checkpoint)| acc <= checkpoint));
+        marks[first..]
+            .iter()
+            .enumerate()
+            .filter_map(|(offset, mark)| {
+                let units = scaled_accrual(&basis, mark.start_accs.as_ref()?, &mark.accs) / INCOME_SCALE;
+                (units > 0).then(|| ((first + offset) as u64, u64::try_from(units).unwrap_or(u64::MAX)))
+            })
+            .collect()
+    })
+}
+
+/// Sub-unit accrual left over from a holder's earlier settlements.
+fn income_remainder(property_id: PropertyId, holder: Principal) -> u128 {
+    INCOME_REMAINDERS.with(|r| r.borrow().get(&(property_id, holder)).cloned().unwrap_or(0))
+}
+
+/// Income a holder has accrued since their last settlement, not yet in UNCLAIMED_INCOME.
+fn pending_income(property_id: PropertyId, holder: Principal) -> u64 {
+    let scaled = scaled_pending_income(property_id, holder) + income_remainder(property_id, holder);
+    u64::try_from(scaled / INCOME_SCALE).unwrap_or(u64::MAX)
+}
+
+/// Pending income summed over every current holder of a property.
+fn pending_income_for_property(property_id: PropertyId) -> u64 {
+    holders_of(property_id).into_iter().fold(0u64, |acc, (holder, _)| acc.saturating_add(pending_income(property_id, holder)))
+}
+
+/// Moves a holder's pending income into UNCLAIMED_INCOME and advances their
+/// checkpoint. Must run before any change to the holder's balance, otherwise
+/// accrual is computed against the wrong share count. Only whole units are
+/// settled; the fraction stays in INCOME_REMAINDERS for the next settlement.
+fn settle_income(property_id: PropertyId, holder: Principal) {
+    let marked = marked_accrual(property_id, holder);
+    let scaled = scaled_pending_income(property_id, holder) + income_remainder(property_id, holder);
+    let pending = u64::try_from(scaled / INCOME_SCALE).unwrap_or(u64::MAX);
+    let remainder = scaled % INCOME_SCALE;
+    INCOME_REMAINDERS.with(|r| {
+        let mut r = r.borrow_mut();
+        if remainder == 0 {
+            r.remove(&(property_id, holder));
+        } else {
+            r.insert((property_id, holder), remainder);
+        }
     });
-    EVENTS.with(|events| {
-        events.borrow_mut().push(Event {
-            event_type: EventType::PropertyRegistered,
+    if pending > 0 {
+        UNCLAIMED_INCOME.with(|ui| {
+            let mut ui = ui.borrow_mut();
+            let entry = ui.entry((property_id, holder)).or_insert(0);
+            *entry = entry.saturating_add(pending);
+        });
+    }
+    if !marked.is_empty() {
+        INCOME_SEGMENTS.with(|s| s.borrow_mut().entry((property_id, holder)).or_default().extend(marked));
+    }
+    let acc = INCOME_PER_SHARE.with(|i| i.borrow().get(&property_id).cloned().unwrap_or(0));
+    INCOME_CHECKPOINTS.with(|c| c.borrow_mut().insert((property_id, holder), acc));
+    for c in 0..share_class_count(property_id) {
+        let acc = INCOME_PER_CLASS_SHARE.with(|i| i.borrow().get(&(property_id, c)).cloned().unwrap_or(0));
+        CLASS_INCOME_CHECKPOINTS.with(|cp| cp.borrow_mut().insert((property_id, c, holder), acc));
+    }
+}
+
+/// Number of the property's income marks whose claim deadline has passed.
+/// Deadlines never decrease, so the expired marks are always a prefix.
+fn expired_mark_count(property_id: PropertyId) -> usize {
+    let now = now();
+    INCOME_MARKS.with(|m| m.borrow().get(&property_id).map(|marks| marks.partition_point(|mark| mark.expires_at < now)).unwrap_or(0))
+}
+
+/// A holder's income that is past its claim deadline: settled segments and
+/// pending accrual from deposits whose mark has expired.
+fn expired_income(property_id: PropertyId, holder: Principal) -> u64 {
+    let expired = expired_mark_count(property_id) as u64;
+    if expired == 0 {
+        return 0;
+    }
+    let settled = INCOME_SEGMENTS.with(|s| s.borrow().get(&(property_id, holder)).cloned().unwrap_or_default());
+    settled
+        .into_iter()
+        .chain(marked_accrual(property_id, holder))
+        .filter(|(mark, _)| *mark < expired)
+        .fold(0u64, |acc, (_, amount)| acc.saturating_add(amount))
+}
+
+/// Moves a holder's expired income into the property reserve and returns the
+/// amount. The holder is settled first so pending accrual is split by deposit.
+fn expire_income(property_id: PropertyId, holder: Principal) -> u64 {
+    let expired = expired_mark_count(property_id) as u64;
+    if expired == 0 {
+        return 0;
+    }
+    settle_income(property_id, holder);
+    let amount: u64 = INCOME_SEGMENTS.with(|s| {
+        let mut s = s.borrow_mut();
+        let Some(segments) = s.get_mut(&(property_id, holder)) else {
+            return 0;
+        };
+        let amount = segments.iter().filter(|(mark, _)| *mark < expired).fold(0u64, |acc, (_, amount)| acc.saturating_add(*amount));
+        segments.retain(|(mark, _)| *mark >= expired);
+        if segments.is_empty() {
+            s.remove(&(property_id, holder));
+        }
+        amount
+    });
+    if amount > 0 {
+        UNCLAIMED_INCOME.with(|ui| {
+            let mut ui = ui.borrow_mut();
+            let remaining = ui.get(&(property_id, holder)).cloned().unwrap_or(0).saturating_sub(amount);
+            if remaining == 0 {
+                ui.remove(&(property_id, holder));
+            } else {
+                ui.insert((property_id, holder), remaining);
+            }
+        });
+        // Swept income no longer counts as distributed, keeping deposited == distributed + reserve + carry
+        DISTRIBUTED_INCOME.with(|di| {
+            if let Some(total) = di.borrow_mut().get_mut(&property_id) {
+                *total = total.saturating_sub(amount);
+            }
+        });
+        credit_reserve(property_id, amount);
+        record_property_event(EventType::IncomeExpired, property_id, holder, format!("Expired {} unclaimed income of {} for property {}", amount, holder, property_id));
+    }
+    amount
+}
+
+/// Admin sets how long income from each new deposit stays claimable; `None`
+/// removes the window for future deposits. Deposits already made keep the
+/// deadline they were given.
+#[update]
+pub fn set_claim_window(property_id: PropertyId, window_secs: Option<u64>) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can set claim windows".to_string());
+    }
+    if get_property(property_id).is_none() {
+        return Err("Property not found".to_string());
+    }
+    match window_secs {
+        Some(0) => return Err("Claim window must be greater than zero".to_string()),
+        Some(secs) => CLAIM_WINDOWS.with(|w| w.borrow_mut().insert(property_id, secs)),
+        None => CLAIM_WINDOWS.with(|w| w.borrow_mut().remove(&property_id)),
+    };
+    record_property_event(EventType::ClaimWindowSet, property_id, caller_principal, format!("Set claim window for property {} to {:?} seconds", property_id, window_secs));
+    Ok("Claim window updated".to_string())
+}
+
+#[query]
+pub fn get_claim_window(property_id: PropertyId) -> Option<u64> {
+    CLAIM_WINDOWS.with(|w| w.borrow().get(&property_id).cloned())
+}
+
+/// Admin moves every holder's income that is past its claim deadline into
+/// the property reserve. Returns the total swept.
+#[update]
+pub fn sweep_expired_income(property_id: PropertyId) -> Result<u64, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can sweep expired income".to_string());
+    }
+    if get_property(property_id).is_none() {
+        return Err("Property not found".to_string());
+    }
+    let mut holders: BTreeSet<Principal> = holders_of(property_id).into_iter().map(|(holder, _)| holder).collect();
+    INCOME_SEGMENTS.with(|s| holders.extend(s.borrow().keys().filter(|(pid, _)| *pid == property_id).map(|(_, holder)| *holder)));
+    let swept = holders.into_iter().fold(0u64, |acc, holder| acc.saturating_add(expire_income(property_id, holder)));
+    record_property_event(EventType::ExpiredIncomeSwept, property_id, caller_principal, format!("Swept {} expired income into the reserve for property {}", swept, property_id));
+    Ok(swept)
+}
+
+/// Admin picks how deposit rounding dust is handled. Leaving `Floor` moves any
+/// carried-over dust into the property reserves so none is stranded.
+#[update]
+pub fn set_rounding_mode(mode: RoundingMode) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can set the rounding mode".to_string());
+    }
+    if mode != RoundingMode::Floor {
+        let carried: Vec<(PropertyId, u64)> = INCOME_CARRY.with(|c| c.borrow_mut().drain().collect());
+        for (property_id, dust) in carried {
+            credit_reserve(property_id, dust);
+        }
+    }
+    ROUNDING_MODE.with(|m| *m.borrow_mut() = mode);
+    record_event(EventType::RoundingModeSet, caller_principal, format!("Set income rounding mode to {:?}", mode));
+    Ok("Rounding mode updated".to_string())
+}
+
+#[query]
+pub fn get_rounding_mode() -> RoundingMode {
+    ROUNDING_MODE.with(|m| *m.borrow())
+}
+
+/// Shows how `amount` would be split if deposited now, without changing state.
+#[query]
+pub fn preview_income_distribution(property_id: PropertyId, amount: u64) -> Result<DistributionPreview, String> {
+    compute_income_allocations(property_id, amount)
+}
+
+/// Splits `amount` across the property's current owners in proportion to their shares.
+/// With share classes, each class first takes its weighted cut, which is then
+/// split in proportion to holdings within the class.
+///
+/// Deposits are O(1): they advance the property's per-share accumulator, and each
+/// holder's income is computed lazily from their checkpoint (see `settle_income`).
+/// Holdings at the moment of the deposit determine who earns it; later transfers
+/// only change the basis for subsequent deposits.
+///
+/// The portion attributable to unissued shares is credited to the property
+/// reserve. The integer-division dust follows the global `RoundingMode`: under
+/// `ReserveRemainder` it also goes to the reserve; under `HalfUp` it first
+/// funds this deposit's round-ups (see `half_up_topups`) and the rest goes to
+/// the reserve; under `Floor` it is carried into the property's next deposit.
+/// Either way deposited == distributed + reserve + carry.
+///
+/// Distributed income is counted in whole units. Holders' accrual below one
+/// unit is kept, per property in DISTRIBUTED_FRACTION and per holder in
+/// INCOME_REMAINDERS, so it is paid out once it adds up rather than dropped.
+/// Holders are therefore never paid more than was distributed.
+fn distribute_income(property_id: PropertyId, amount: u64, actor: Principal) -> Result<String, String> {
+    let carried = carried_income(property_id);
+    let mut split = income_increments(property_id, amount.checked_add(carried).ok_or("Rental income total overflow".to_string())?)?;
+    let topups = half_up_topups(property_id, &mut split);
+    let increments = split.increments;
+    let allocated = split.allocated;
+    // Track total income
+    RENTAL_INCOME.with(|ri| {
+        let mut ri = ri.borrow_mut();
+        let total = ri.entry(property_id).or_insert(0);
+        *total = total.checked_add(amount).ok_or("Rental income total overflow".to_string())?;
+        Ok::<_, String>(())
+    })?;
+    if share_class_count(property_id) == 0 {
+        INCOME_PER_SHARE.with(|i| {
+            let mut i = i.borrow_mut();
+            let acc = i.entry(property_id).or_insert(0);
+            *acc = acc.saturating_add(increments[0]);
+        });
+    } else {
+        INCOME_PER_CLASS_SHARE.with(|i| {
+            let mut i = i.borrow_mut();
+            for (c, increment) in increments.iter().enumerate() {
+                let acc = i.entry((property_id, c as u32)).or_insert(0);
+                *acc = acc.saturating_add(*increment);
+            }
+        });
+    }
+    DISTRIBUTED_INCOME.with(|di| {
+        let mut di = di.borrow_mut();
+        let total = di.entry(property_id).or_insert(0);
+        *total = total.saturating_add(allocated);
+    });
+    DISTRIBUTED_FRACTION.with(|f| f.borrow_mut().insert(property_id, split.fraction));
+    INCOME_REMAINDERS.with(|r| {
+        let mut r = r.borrow_mut();
+        for (holder, topup) in topups {
+            *r.entry((property_id, holder)).or_insert(0) += topup;
+        }
+    });
+    let remainder = if ROUNDING_MODE.with(|m| *m.borrow()) == RoundingMode::Floor {
+        INCOME_CARRY.with(|c| c.borrow_mut().insert(property_id, split.dust));
+        split.unissued
+    } else {
+        split.unissued + split.dust
+    };
+    credit_reserve(property_id, remainder);
+    if let Some(window) = CLAIM_WINDOWS.with(|w| w.borrow().get(&property_id).cloned()) {
+        let accs = current_accs(property_id);
+        let start_accs = accs.iter().zip(&increments).map(|(acc, increment)| acc - increment).collect();
+        INCOME_MARKS.with(|m| {
+            let mut m = m.borrow_mut();
+            let marks = m.entry(property_id).or_default();
+            let floor = marks.last().map(|mark| mark.expires_at).unwrap_or(0);
+            let expires_at = now().saturating_add(window.saturating_mul(1_000_000_000)).max(floor);
+            marks.push(IncomeMark { expires_at, accs, start_accs: Some(start_accs) });
+        });
+    }
+    record_property_event(EventType::RentalIncomeDeposited, property_id, actor, format!("Deposited rental income {} for property {} ({} to reserve)", amount, property_id, remainder));
+    Ok("Rental income distributed".to_string())
+}
+
+fn credit_reserve(property_id: PropertyId, amount: u64) {
+    RESERVES.with(|r| {
+        let mut r = r.borrow_mut();
+        let reserve = r.entry(property_id).or_insert(0);
+        *reserve = reserve.saturating_add(amount);
+    });
+}
+
+fn debit_reserve(property_id: PropertyId, amount: u64) -> Result<(), String> {
+    RESERVES.with(|r| {
+        let mut r = r.borrow_mut();
+        let reserve = r.entry(property_id).or_insert(0);
+        if *reserve < amount {
+            return Err("Insufficient reserve".to_string());
+        }
+        *reserve -= amount;
+        Ok(())
+    })
+}
+
+/// Admin or manager adds funds to a property's maintenance reserve.
+#[update]
+pub fn deposit_reserve(property_id: PropertyId, amount: u64) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if !can_manage(&caller_principal) {
+        return Err("Unauthorized to manage reserves".to_string());
+    }
+    if get_property(property_id).is_none() {
+        return Err("Property not found".to_string());
+    }
+    credit_reserve(property_id, amount);
+    record_property_event(EventType::ReserveDeposited, property_id, caller_principal, format!("Deposited {} into reserve for property {}", amount, property_id));
+    Ok("Reserve deposited".to_string())
+}
+
+/// Admin or manager spends from a property's reserve; the reason is kept in the audit log.
+#[update]
+pub fn spend_reserve(property_id: PropertyId, amount: u64, reason: String) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if !can_manage(&caller_principal) {
+        return Err("Unauthorized to manage reserves".to_string());
+    }
+    debit_reserve(property_id, amount)?;
+    record_property_event(EventType::ReserveSpent, property_id, caller_principal, format!("Spent {} from reserve for property {}: {}", amount, property_id, reason));
+    Ok("Reserve spent".to_string())
+}
+
+#[query]
+pub fn get_reserve(property_id: PropertyId) -> u64 {
+    RESERVES.with(|r| r.borrow().get(&property_id).cloned().unwrap_or(0))
+}
+
+/// Caller claims their own unclaimed rental income for a property.
+#[update]
+pub fn claim_income(property_id: PropertyId) -> Result<u64, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    check_not_blacklisted(&[&caller_principal])?;
+    check_not_frozen(property_id)?;
+    let expired = expire_income(property_id, caller_principal);
+    settle_income(property_id, caller_principal);
+    let mut claimed = 0;
+    UNCLAIMED_INCOME.with(|ui| {
+        let mut ui = ui.borrow_mut();
+        claimed = ui.remove(&(property_id, caller_principal)).unwrap_or(0);
+    });
+    INCOME_SEGMENTS.with(|s| s.borrow_mut().remove(&(property_id, caller_principal)));
+    if claimed > 0 {
+        record_claim(property_id, caller_principal, claimed);
+    } else if expired > 0 {
+        return Err("Unclaimed income has expired".to_string());
+    }
+    Ok(claimed)
+}
+
+/// Claims the caller's unclaimed income across every property at once.
+/// Frozen properties are skipped and keep their balance.
+#[update]
+pub fn claim_all_income() -> Result<Vec<(PropertyId, u64)>, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    check_not_blacklisted(&[&caller_principal])?;
+    let held: Vec<PropertyId> = PROPERTIES_BY_HOLDER.with(|p| p.borrow().get(&caller_principal).map(|ids| ids.iter().cloned().collect()).unwrap_or_default());
+    for property_id in held {
+        settle_income(property_id, caller_principal);
+    }
+    let mut pending: Vec<PropertyId> = UNCLAIMED_INCOME.with(|ui| {
+        ui.borrow().keys().filter(|(_, holder)| *holder == caller_principal).map(|(pid, _)| *pid).collect()
+    });
+    pending.sort();
+    let mut claims = Vec::new();
+    for property_id in pending {
+        if check_not_frozen(property_id).is_err() {
+            continue;
+        }
+        expire_income(property_id, caller_principal);
+        let claimed = UNCLAIMED_INCOME.with(|ui| ui.borrow_mut().remove(&(property_id, caller_principal)).unwrap_or(0));
+        INCOME_SEGMENTS.with(|s| s.borrow_mut().remove(&(property_id, caller_principal)));
+        if claimed > 0 {
+            record_claim(property_id, caller_principal, claimed);
+            claims.push((property_id, claimed));
+        }
+    }
+    Ok(claims)
+}
+
+fn record_claim(property_id: PropertyId, holder: Principal, claimed: u64) {
+    CLAIMED_INCOME.with(|ci| {
+        let mut ci = ci.borrow_mut();
+        let total = ci.entry((property_id, holder)).or_insert(0);
+        *total = total.saturating_add(claimed);
+    });
+    CLAIM_HISTORY.with(|ch| {
+        ch.borrow_mut().entry(holder).or_default().push(ClaimRecord {
+            property_id,
+            amount: claimed,
             timestamp: now(),
-            actor: caller_principal,
-            details: format!("Registered property: {} (id: {})", name, property.id),
         });
     });
-    property
+    record_property_event(EventType::RentalIncomeClaimed, property_id, holder, format!("Claimed {} rental income for property {}", claimed, property_id));
+}
+
+/// Total rental income ever allocated to holders of a property; the rest of
+/// RENTAL_INCOME went to the reserve as rounding dust.
+#[query]
+pub fn get_distributed_income(property_id: PropertyId) -> u64 {
+    DISTRIBUTED_INCOME.with(|di| di.borrow().get(&property_id).cloned().unwrap_or(0))
+}
+
+#[query]
+pub fn get_property_income_summary(property_id: PropertyId) -> IncomeSummary {
+    let sum_for_property = |map: &HashMap<(PropertyId, Principal), u64>| {
+        map.iter().filter(|((pid, _), _)| *pid == property_id).fold(0u64, |acc, (_, v)| acc.saturating_add(*v))
+    };
+    IncomeSummary {
+        total_deposited: RENTAL_INCOME.with(|ri| ri.borrow().get(&property_id).cloned().unwrap_or(0)),
+        total_unclaimed: UNCLAIMED_INCOME.with(|ui| sum_for_property(&ui.borrow())).saturating_add(pending_income_for_property(property_id)),
+        total_claimed: CLAIMED_INCOME.with(|ci| sum_for_property(&ci.borrow())),
+    }
+}
+
+/// Query unclaimed rental income for a user and property.
+#[query]
+pub fn get_unclaimed_income(property_id: PropertyId, user: Principal) -> u64 {
+    let settled = UNCLAIMED_INCOME.with(|ui| ui.borrow().get(&(property_id, user)).cloned().unwrap_or(0));
+    settled.saturating_add(pending_income(property_id, user)).saturating_sub(expired_income(property_id, user))
+}
+
+/// Shares that will be used to allocate the user's portion of the next deposit.
+#[query]
+pub fn get_income_accrual_basis(property_id: PropertyId, user: Principal) -> u64 {
+    get_ownership(property_id, user)
+}
+
+/// Query total rental income claimed so far by a user for a property.
+#[query]
+pub fn get_claimed_income(property_id: PropertyId, user: Principal) -> u64 {
+    CLAIMED_INCOME.with(|ci| ci.borrow().get(&(property_id, user)).cloned().unwrap_or(0))
+}
+
+/// Query every income claim made by a user, oldest first.
+#[query]
+pub fn get_claim_history(user: Principal) -> Vec<ClaimRecord> {
+    CLAIM_HISTORY.with(|ch| ch.borrow().get(&user).cloned().unwrap_or_default())
 }
 
+/// Admin or manager credits funds received off-chain to a user's balance.
 #[update]
-pub fn issue_shares(property_id: PropertyId, to: Principal, amount: u64) -> Result<String, String> {
+pub fn deposit_funds(user: Principal, amount: u64) -> Result<String, String> {
+    check_not_paused()?;
     let caller_principal = caller();
-    let mut success = false;
-    PROPERTIES.with(|props| {
-        let mut props = props.borrow_mut();
-        if let Some(prop) = props.get_mut(&property_id) {
-            if prop.shares_available >= amount {
-                prop.shares_available -= amount;
-                OWNERSHIP.with(|own| {
-                    let mut own = own.borrow_mut();
-                    *own.entry((property_id, to)).or_insert(0) += amount;
-                });
-                success = true;
-            }
+    if !can_manage(&caller_principal) {
+        return Err("Only admin or manager can deposit funds".to_string());
+    }
+    BALANCES.with(|b| {
+        let mut b = b.borrow_mut();
+        let balance = b.entry(user).or_insert(0);
+        *balance = balance.checked_add(amount).ok_or("Balance overflow".to_string())?;
+        Ok::<_, String>(())
+    })?;
+    record_event(EventType::FundsDeposited, caller_principal, format!("Deposited {} funds for {}", amount, user));
+    Ok("Funds deposited".to_string())
+}
+
+/// Caller withdraws funds from their own balance.
+#[update]
+pub fn withdraw_funds(amount: u64) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    check_not_blacklisted(&[&caller_principal])?;
+    BALANCES.with(|b| {
+        let mut b = b.borrow_mut();
+        let balance = b.entry(caller_principal).or_insert(0);
+        if *balance < amount {
+            return Err("Insufficient funds".to_string());
         }
+        *balance -= amount;
+        Ok(())
+    })?;
+    record_event(EventType::FundsWithdrawn, caller_principal, format!("Withdrew {} funds", amount));
+    Ok("Funds withdrawn".to_string())
+}
+
+#[query]
+pub fn get_balance(user: Principal) -> u64 {
+    BALANCES.with(|b| b.borrow().get(&user).cloned().unwrap_or(0))
+}
+
+/// Admin records a new appraisal of a property's value.
+#[update]
+pub fn record_valuation(property_id: PropertyId, valuation: u64) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can record valuations".to_string());
+    }
+    if get_property(property_id).is_none() {
+        return Err("Property not found".to_string());
+    }
+    VALUATIONS.with(|v| {
+        v.borrow_mut().entry(property_id).or_default().push((now(), valuation));
     });
-    if success {
-        EVENTS.with(|events| {
-            events.borrow_mut().push(Event {
-                event_type: EventType::SharesIssued,
-                timestamp: now(),
-                actor: caller_principal,
-                details: format!("Issued {} shares of property {} to {}", amount, property_id, to),
-            });
-        });
-        Ok("Shares issued".to_string())
-    } else {
-        Err("Not enough shares or property not found".to_string())
+    record_property_event(EventType::ValuationRecorded, property_id, caller_principal, format!("Recorded valuation {} for property {}", valuation, property_id));
+    Ok("Valuation recorded".to_string())
+}
+
+/// Valuation history for a property, oldest first, with implied price per
+/// share. Archived properties keep theirs.
+#[query]
+pub fn get_valuation_history(property_id: PropertyId) -> Vec<ValuationRecord> {
+    let total_shares = get_property(property_id)
+        .or_else(|| ARCHIVED_PROPERTIES.with(|archived| archived.borrow().get(&property_id).cloned()))
+        .map(|p| p.total_shares)
+        .unwrap_or(0);
+    VALUATIONS.with(|v| {
+        v.borrow()
+            .get(&property_id)
+            .map(|history| {
+                history
+                    .iter()
+                    .map(|(timestamp, valuation)| ValuationRecord {
+                        timestamp: *timestamp,
+                        valuation: *valuation,
+                        price_per_share: valuation.checked_div(total_shares).unwrap_or(0),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default()
+    })
+}
+
+/// Admin attaches a legal document (title deed, prospectus, ...) to a property.
+#[update]
+pub fn add_document(property_id: PropertyId, name: String, uri: String, sha256: [u8; 32]) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can add documents".to_string());
+    }
+    if get_property(property_id).is_none() {
+        return Err("Property not found".to_string());
     }
+    let name = name.trim().to_string();
+    let uri = uri.trim().to_string();
+    if name.is_empty() || uri.is_empty() {
+        return Err("Document name and URI cannot be empty".to_string());
+    }
+    DOCUMENTS.with(|d| {
+        d.borrow_mut().entry(property_id).or_default().push(DocRef { name: name.clone(), uri: uri.clone(), sha256 });
+    });
+    record_property_event(EventType::DocumentAdded, property_id, caller_principal, format!("Added document {} ({}) to property {}", name, uri, property_id));
+    Ok("Document added".to_string())
 }
 
+/// Documents attached to a property, oldest first.
 #[query]
-pub fn get_property(property_id: PropertyId) -> Option<Property> {
-    PROPERTIES.with(|props| props.borrow().get(&property_id).cloned())
+pub fn get_documents(property_id: PropertyId) -> Vec<DocRef> {
+    DOCUMENTS.with(|d| d.borrow().get(&property_id).cloned().unwrap_or_default())
+}
+
+/// Admin records the total proceeds from selling a property, to be redeemed by holders.
+#[update]
+pub fn set_sale_proceeds(property_id: PropertyId, total_amount: u64) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can set sale proceeds".to_string());
+    }
+    if get_property(property_id).is_none() {
+        return Err("Property not found".to_string());
+    }
+    if SALE_PROCEEDS.with(|sp| sp.borrow().contains_key(&property_id)) {
+        return Err("Sale proceeds already set".to_string());
+    }
+    SALE_PROCEEDS.with(|sp| {
+        sp.borrow_mut().insert(property_id, total_amount);
+    });
+    record_property_event(EventType::SaleProceedsSet, property_id, caller_principal, format!("Set sale proceeds {} for property {}", total_amount, property_id));
+    Ok("Sale proceeds recorded".to_string())
 }
 
 #[query]
-pub fn get_ownership(property_id: PropertyId, user: Principal) -> u64 {
-    OWNERSHIP.with(|own| own.borrow().get(&(property_id, user)).cloned().unwrap_or(0))
+pub fn get_sale_proceeds(property_id: PropertyId) -> Option<u64> {
+    SALE_PROCEEDS.with(|sp| sp.borrow().get(&property_id).cloned())
 }
 
-/// Admin deposits rental income for a property. Distributes to all current owners proportionally.
+/// Caller redeems all their shares of a Sold property for their proportional
+/// share of the sale proceeds, credited to their balance. Redeemed shares return
+/// to `shares_available` but can't be issued again, and total payouts never
+/// exceed the recorded proceeds.
 #[update]
-pub fn deposit_rental_income(property_id: PropertyId, amount: u64) -> Result<String, String> {
+pub fn redeem_shares(property_id: PropertyId) -> Result<u64, String> {
+    check_not_paused()?;
     let caller_principal = caller();
-    // Track total income
-    RENTAL_INCOME.with(|ri| {
-        let mut ri = ri.borrow_mut();
-        *ri.entry(property_id).or_insert(0) += amount;
+    check_not_blacklisted(&[&caller_principal])?;
+    let property = get_property(property_id).ok_or("Property not found".to_string())?;
+    if property.status != PropertyStatus::Sold {
+        return Err("Property is not sold".to_string());
+    }
+    check_not_frozen(property_id)?;
+    let proceeds = get_sale_proceeds(property_id).ok_or("Sale proceeds not set".to_string())?;
+    let shares = get_ownership(property_id, caller_principal);
+    if shares == 0 {
+        return Err("No shares to redeem".to_string());
+    }
+    let redeemed = REDEEMED_PROCEEDS.with(|r| r.borrow().get(&property_id).cloned().unwrap_or(0));
+    let payout = proportional_share(proceeds, shares, property.total_shares)?.min(proceeds.saturating_sub(redeemed));
+    REDEEMED_PROCEEDS.with(|r| r.borrow_mut().insert(property_id, redeemed + payout));
+    BALANCES.with(|b| {
+        let mut b = b.borrow_mut();
+        let balance = b.entry(caller_principal).or_insert(0);
+        *balance = balance.saturating_add(payout);
     });
-    // Distribute to owners
-    let mut total_shares = 0;
+    debit_shares(property_id, caller_principal, shares)?;
+    clear_class_holdings(property_id, caller_principal);
+    trim_listings(property_id, caller_principal);
     PROPERTIES.with(|props| {
-        if let Some(prop) = props.borrow().get(&property_id) {
-            total_shares = prop.total_shares;
+        if let Some(prop) = props.borrow_mut().get_mut(&property_id) {
+            prop.shares_available += shares;
         }
     });
-    if total_shares == 0 {
-        return Err("Property not found or has no shares".to_string());
+    assert_share_invariant(property_id);
+    certify_property(property_id);
+    record_property_event(EventType::SharesRedeemed, property_id, caller_principal, format!("Redeemed {} shares of property {} for {}", shares, property_id, payout));
+    Ok(payout)
+}
+
+/// Admin offers to buy back up to `max_shares` at `price_per_share`. The full
+/// cost is moved out of the treasury into escrow up front, so every accepted
+/// sale is guaranteed to be paid. One buyback per property at a time.
+#[update]
+pub fn open_buyback(property_id: PropertyId, price_per_share: u64, max_shares: u64) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can open buybacks".to_string());
     }
-    // Find all owners
-    OWNERSHIP.with(|own| {
-        let own = own.borrow();
-        for ((pid, user), shares) in own.iter() {
-            if *pid == property_id && *shares > 0 {
-                let user_income = amount * shares / total_shares;
-                UNCLAIMED_INCOME.with(|ui| {
-                    let mut ui = ui.borrow_mut();
-                    *ui.entry((property_id, user.clone())).or_insert(0) += user_income;
-                });
-            }
+    if get_property(property_id).is_none() {
+        return Err("Property not found".to_string());
+    }
+    if price_per_share == 0 || max_shares == 0 {
+        return Err("Buyback price and size must be greater than zero".to_string());
+    }
+    if BUYBACKS.with(|b| b.borrow().contains_key(&property_id)) {
+        return Err("A buyback is already open for this property".to_string());
+    }
+    let escrow = price_per_share.checked_mul(max_shares).ok_or("Buyback cost overflow".to_string())?;
+    TREASURY.with(|t| {
+        let mut t = t.borrow_mut();
+        if *t < escrow {
+            return Err("Insufficient treasury funds for buyback".to_string());
         }
+        *t -= escrow;
+        Ok(())
+    })?;
+    BUYBACKS.with(|b| {
+        b.borrow_mut().insert(property_id, Buyback { property_id, price_per_share, max_shares, remaining: max_shares, opened_at: now() });
     });
-    EVENTS.with(|events| {
-        events.borrow_mut().push(Event {
-            event_type: EventType::RentalIncomeDeposited,
-            timestamp: now(),
-            actor: caller_principal,
-            details: format!("Deposited rental income {} for property {}", amount, property_id),
-        });
+    record_property_event(EventType::BuybackOpened, property_id, caller_principal, format!("Opened buyback of up to {} shares of property {} at {} per share", max_shares, property_id, price_per_share));
+    Ok("Buyback opened".to_string())
+}
+
+/// Admin closes a buyback, returning the unused escrow to the treasury.
+#[update]
+pub fn close_buyback(property_id: PropertyId) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can close buybacks".to_string());
+    }
+    let buyback = BUYBACKS.with(|b| b.borrow_mut().remove(&property_id)).ok_or("No open buyback for this property".to_string())?;
+    let refund = buyback.remaining * buyback.price_per_share;
+    TREASURY.with(|t| {
+        let mut t = t.borrow_mut();
+        *t = t.saturating_add(refund);
     });
-    Ok("Rental income distributed".to_string())
+    record_property_event(EventType::BuybackClosed, property_id, caller_principal, format!("Closed buyback for property {}; {} of {} shares bought back, {} returned to treasury", property_id, buyback.max_shares - buyback.remaining, buyback.max_shares, refund));
+    Ok("Buyback closed".to_string())
 }
 
-/// User claims their unclaimed rental income for a property.
+/// Caller sells shares into the open buyback at its fixed price. Bought-back
+/// shares return to `shares_available` and the proceeds go to the caller's balance.
 #[update]
-pub fn claim_income(property_id: PropertyId, user: Principal) -> u64 {
+pub fn sell_to_buyback(property_id: PropertyId, amount: u64) -> Result<u64, String> {
+    check_not_paused()?;
     let caller_principal = caller();
-    let mut claimed = 0;
-    UNCLAIMED_INCOME.with(|ui| {
-        let mut ui = ui.borrow_mut();
-        claimed = ui.remove(&(property_id, user)).unwrap_or(0);
+    check_not_blacklisted(&[&caller_principal])?;
+    check_not_frozen(property_id)?;
+    check_unclassed(property_id)?;
+    check_lockup(property_id, &caller_principal)?;
+    if amount == 0 {
+        return Err("Amount must be greater than zero".to_string());
+    }
+    let buyback = BUYBACKS.with(|b| b.borrow().get(&property_id).cloned()).ok_or("No open buyback for this property".to_string())?;
+    if buyback.remaining < amount {
+        return Err(format!("Buyback only accepts {} more shares", buyback.remaining));
+    }
+    let unlisted = get_ownership(property_id, caller_principal).saturating_sub(get_listed_shares(property_id, caller_principal));
+    if unlisted < amount {
+        return Err("Not enough unlisted shares to sell".to_string());
+    }
+    let payout = amount * buyback.price_per_share;
+    debit_shares(property_id, caller_principal, amount)?;
+    PROPERTIES.with(|props| {
+        if let Some(prop) = props.borrow_mut().get_mut(&property_id) {
+            prop.shares_available += amount;
+        }
     });
-    if claimed > 0 {
-        EVENTS.with(|events| {
-            events.borrow_mut().push(Event {
-                event_type: EventType::RentalIncomeClaimed,
-                timestamp: now(),
-                actor: caller_principal,
-                details: format!("Claimed {} rental income for property {}", claimed, property_id),
-            });
-        });
+    BUYBACKS.with(|b| {
+        if let Some(buyback) = b.borrow_mut().get_mut(&property_id) {
+            buyback.remaining -= amount;
+        }
+    });
+    BALANCES.with(|b| {
+        let mut b = b.borrow_mut();
+        let balance = b.entry(caller_principal).or_insert(0);
+        *balance = balance.saturating_add(payout);
+    });
+    assert_share_invariant(property_id);
+    certify_property(property_id);
+    record_property_event(EventType::SharesSoldToBuyback, property_id, caller_principal, format!("Sold {} shares of property {} to buyback for {}", amount, property_id, payout));
+    Ok(payout)
+}
+
+#[query]
+pub fn get_buyback(property_id: PropertyId) -> Option<Buyback> {
+    BUYBACKS.with(|b| b.borrow().get(&property_id).cloned())
+}
+
+/// Admin sets the marketplace fee, in basis points of trade value.
+#[update]
+pub fn set_market_fee_bps(fee_bps: u16) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can set the market fee".to_string());
     }
-    claimed
+    if fee_bps > 10_000 {
+        return Err("Fee cannot exceed 10000 bps".to_string());
+    }
+    FEE_BPS.with(|f| *f.borrow_mut() = fee_bps);
+    record_event(EventType::MarketFeeSet, caller_principal, format!("Set market fee to {} bps", fee_bps));
+    Ok("Market fee updated".to_string())
 }
 
-/// Query unclaimed rental income for a user and property.
 #[query]
-pub fn get_unclaimed_income(property_id: PropertyId, user: Principal) -> u64 {
-    UNCLAIMED_INCOME.with(|ui| ui.borrow().get(&(property_id, user)).cloned().unwrap_or(0))
+pub fn get_market_fee_bps() -> u16 {
+    FEE_BPS.with(|f| *f.borrow())
+}
+
+/// Admin sets the transfer tax on direct transfers, withheld in shares.
+#[update]
+pub fn set_transfer_fee_bps(fee_bps: u16) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can set the transfer fee".to_string());
+    }
+    if fee_bps > 10_000 {
+        return Err("Fee cannot exceed 10000 bps".to_string());
+    }
+    TRANSFER_FEE_BPS.with(|f| *f.borrow_mut() = fee_bps);
+    record_event(EventType::TransferFeeSet, caller_principal, format!("Set transfer fee to {} bps", fee_bps));
+    Ok("Transfer fee updated".to_string())
+}
+
+#[query]
+pub fn get_transfer_fee_bps() -> u16 {
+    TRANSFER_FEE_BPS.with(|f| *f.borrow())
 }
 
-/// List shares for sale on the marketplace
+/// Admin sets the band of prices per share that new listings must fall within.
 #[update]
-pub fn list_shares_for_sale(property_id: PropertyId, seller: Principal, amount: u64, price_per_share: u64) -> Result<String, String> {
+pub fn set_price_bounds(property_id: PropertyId, min_price_per_share: u64, max_price_per_share: u64) -> Result<String, String> {
+    check_not_paused()?;
     let caller_principal = caller();
-    // Check seller owns enough shares
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can set price bounds".to_string());
+    }
+    if PROPERTIES.with(|props| !props.borrow().contains_key(&property_id)) {
+        return Err("Property not found".to_string());
+    }
+    if min_price_per_share > max_price_per_share {
+        return Err("Minimum price cannot exceed maximum price".to_string());
+    }
+    PRICE_BOUNDS.with(|pb| {
+        pb.borrow_mut().insert(property_id, PriceBounds { min_price_per_share, max_price_per_share });
+    });
+    record_property_event(EventType::PriceBoundsSet, property_id, caller_principal, format!("Set price bounds for property {} to {}..={} per share", property_id, min_price_per_share, max_price_per_share));
+    Ok("Price bounds updated".to_string())
+}
+
+#[query]
+pub fn get_price_bounds(property_id: PropertyId) -> Option<PriceBounds> {
+    PRICE_BOUNDS.with(|pb| pb.borrow().get(&property_id).cloned())
+}
+
+fn check_price_bounds(property_id: PropertyId, price_per_share: u64) -> Result<(), String> {
+    let Some(bounds) = get_price_bounds(property_id) else {
+        return Ok(());
+    };
+    if price_per_share < bounds.min_price_per_share {
+        return Err(format!("Price {} is below the minimum of {} per share", price_per_share, bounds.min_price_per_share));
+    }
+    if price_per_share > bounds.max_price_per_share {
+        return Err(format!("Price {} is above the maximum of {} per share", price_per_share, bounds.max_price_per_share));
+    }
+    Ok(())
+}
+
+#[query]
+pub fn get_treasury_balance() -> Result<u64, String> {
+    if get_role(&caller()) != Role::Admin {
+        return Err("Only admin can view the treasury".to_string());
+    }
+    Ok(TREASURY.with(|t| *t.borrow()))
+}
+
+/// Shares a seller currently has reserved across open listings for a property.
+/// Reservations are released as listings are bought down or cancelled.
+#[query]
+pub fn get_listed_shares(property_id: PropertyId, seller: Principal) -> u64 {
+    MARKETPLACE.with(|mp| {
+        mp.borrow()
+            .iter()
+            .filter(|l| l.property_id == property_id && l.seller == seller)
+            .fold(0u64, |acc, l| acc.saturating_add(l.amount))
+    })
+}
+
+/// Shrinks `seller`'s listings for a property, newest first, until they are
+/// backed by the shares the seller still holds. For paths that take shares
+/// without the seller's consent, such as forced transfers and redemption.
+fn trim_listings(property_id: PropertyId, seller: Principal) {
+    let mut excess = get_listed_shares(property_id, seller).saturating_sub(get_ownership(property_id, seller));
+    if excess == 0 {
+        return;
+    }
+    MARKETPLACE.with(|mp| {
+        let mut mp = mp.borrow_mut();
+        for listing in mp.iter_mut().rev().filter(|l| l.property_id == property_id && l.seller == seller) {
+            let cut = listing.amount.min(excess);
+            listing.amount -= cut;
+            excess -= cut;
+            if excess == 0 {
+                break;
+            }
+        }
+        mp.retain(|l| l.amount > 0);
+    });
+}
+
+/// Caller lists their own shares for sale on the marketplace. `currency` and
+/// `price_decimals` describe how to display the price and default to
+/// DEFAULT_CURRENCY and DEFAULT_PRICE_DECIMALS, so existing callers can omit them.
+#[update]
+pub fn list_shares_for_sale(property_id: PropertyId, amount: u64, price_per_share: u64, currency: Option<String>, price_decimals: Option<u8>) -> Result<String, String> {
+    check_not_paused()?;
+    let seller = caller();
+    let currency = currency.map(|c| c.trim().to_uppercase()).unwrap_or_else(|| DEFAULT_CURRENCY.to_string());
+    if currency.is_empty() || currency.len() > MAX_CURRENCY_LEN || !currency.chars().all(|c| c.is_ascii_alphanumeric()) {
+        return Err(format!("Currency must be 1 to {} letters or digits", MAX_CURRENCY_LEN));
+    }
+    let price_decimals = price_decimals.unwrap_or(DEFAULT_PRICE_DECIMALS);
+    if price_decimals > MAX_DECIMALS {
+        return Err(format!("Price decimals cannot exceed {}", MAX_DECIMALS));
+    }
+    check_not_blacklisted(&[&seller])?;
+    check_not_frozen(property_id)?;
+    check_unclassed(property_id)?;
+    check_lockup(property_id, &seller)?;
+    check_price_bounds(property_id, price_per_share)?;
+    // Check seller owns enough shares not already committed to other listings
     let owned = OWNERSHIP.with(|own| own.borrow().get(&(property_id, seller)).cloned().unwrap_or(0));
-    if owned < amount {
+    if owned.saturating_sub(get_listed_shares(property_id, seller)) < amount {
         return Err("Not enough shares to list".to_string());
     }
+    let max_listings = MAX_LISTINGS_PER_SELLER.with(|m| *m.borrow());
+    let open_listings = MARKETPLACE.with(|mp| mp.borrow().iter().filter(|l| l.seller == seller).count());
+    if open_listings >= max_listings as usize {
+        return Err(format!("Seller already has the maximum of {} open listings", max_listings));
+    }
     // Add listing
+    let listing_id = NEXT_LISTING_ID.with(|next| {
+        let mut next = next.borrow_mut();
+        let curr = *next;
+        *next += 1;
+        curr
+    });
     MARKETPLACE.with(|mp| {
         mp.borrow_mut().push(Listing {
+            listing_id,
             property_id,
             seller,
             amount,
             price_per_share,
+            currency: currency.clone(),
+            price_decimals,
         });
     });
-    EVENTS.with(|events| {
-        events.borrow_mut().push(Event {
-            event_type: EventType::SharesListedForSale,
+    record_property_event(EventType::SharesListedForSale, property_id, seller, format!("Listed {} shares of property {} for sale at {} {} per share (listing {})", amount, property_id, price_per_share, currency, listing_id));
+    Ok("Shares listed for sale".to_string())
+}
+
+/// Admin caps how many open listings a single seller may have at once.
+#[update]
+pub fn set_max_listings_per_seller(max_listings: u32) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can set the listing cap".to_string());
+    }
+    if max_listings == 0 {
+        return Err("Listing cap must be at least 1".to_string());
+    }
+    MAX_LISTINGS_PER_SELLER.with(|m| *m.borrow_mut() = max_listings);
+    record_event(EventType::MaxListingsSet, caller_principal, format!("Set maximum listings per seller to {}", max_listings));
+    Ok("Listing cap updated".to_string())
+}
+
+#[query]
+pub fn get_max_listings_per_seller() -> u32 {
+    MAX_LISTINGS_PER_SELLER.with(|m| *m.borrow())
+}
+
+/// Buy shares from a specific marketplace listing. The buyer pays
+/// `amount * price_per_share` from their balance; the market fee goes to
+/// the treasury and the rest to the seller.
+#[update]
+pub fn buy_shares(listing_id: u64, buyer: Principal, amount: u64) -> Result<String, String> {
+    let caller_principal = caller();
+    if caller_principal != buyer {
+        return Err("Buyer must be the caller".to_string());
+    }
+    let (listing, cost) = validate_purchase(listing_id, buyer, amount).map_err(|e| e.to_string())?;
+    settle_listing_fill(&listing, buyer, amount, cost)?;
+    assert_share_invariant(listing.property_id);
+    certify_property(listing.property_id);
+    Ok("Shares bought successfully".to_string())
+}
+
+/// Dry run of `buy_shares`: returns the error the purchase would fail with, if any.
+#[query]
+pub fn can_buy_shares(listing_id: u64, buyer: Principal, amount: u64) -> Result<(), RwaError> {
+    validate_purchase(listing_id, buyer, amount).map(|_| ())
+}
+
+/// Every check `buy_shares` makes before settling, including the pause
+/// guard, shared with `can_buy_shares`. Returns the listing and the trade's cost.
+fn validate_purchase(listing_id: u64, buyer: Principal, amount: u64) -> Result<(Listing, u64), RwaError> {
+    check_not_paused().map_err(|_| RwaError::Paused)?;
+    check_recipient_kyc(&buyer).map_err(|_| RwaError::NotKycVerified)?;
+    let listing = MARKETPLACE
+        .with(|mp| mp.borrow().iter().find(|l| l.listing_id == listing_id && l.amount >= amount).cloned())
+        .ok_or(RwaError::ListingUnavailable)?;
+    let property_id = listing.property_id;
+    let seller = listing.seller;
+    if buyer == seller {
+        return Err(RwaError::OwnListing);
+    }
+    check_not_blacklisted(&[&buyer, &seller]).map_err(|_| RwaError::Blacklisted)?;
+    check_whitelisted(property_id, &buyer).map_err(|_| RwaError::NotWhitelisted)?;
+    check_not_frozen(property_id).map_err(|_| RwaError::PropertyFrozen)?;
+    check_transfers_not_paused(property_id).map_err(|_| RwaError::TransfersPaused)?;
+    check_lockup(property_id, &seller).map_err(|_| RwaError::SharesLocked { until: unlock_time(property_id, &seller) })?;
+    check_ownership_cap(property_id, &buyer, amount).map_err(|_| RwaError::OwnershipCapExceeded)?;
+    if get_ownership(property_id, seller) < amount {
+        return Err(RwaError::ListingUnavailable);
+    }
+    let cost = amount.checked_mul(listing.price_per_share).ok_or(RwaError::TradeValueOverflow)?;
+    if get_balance(buyer) < cost {
+        return Err(RwaError::InsufficientFunds);
+    }
+    Ok((listing, cost))
+}
+
+/// Moves funds and shares for one (possibly partial) fill of a listing.
+/// Callers must already have checked the buyer's balance and the seller's
+/// ownership so that nothing here can fail halfway.
+fn settle_listing_fill(listing: &Listing, buyer: Principal, amount: u64, cost: u64) -> Result<(), String> {
+    let property_id = listing.property_id;
+    let seller = listing.seller;
+    let fee_bps = FEE_BPS.with(|f| *f.borrow());
+    let fee = (cost as u128 * fee_bps as u128 / 10_000) as u64;
+    // Settle funds before any shares move
+    BALANCES.with(|b| {
+        let mut b = b.borrow_mut();
+        *b.entry(buyer).or_insert(0) -= cost;
+        let seller_balance = b.entry(seller).or_insert(0);
+        *seller_balance = seller_balance.saturating_add(cost - fee);
+    });
+    TREASURY.with(|t| {
+        let mut t = t.borrow_mut();
+        *t = t.saturating_add(fee);
+    });
+    // Transfer shares
+    debit_shares(property_id, seller, amount)?;
+    credit_shares(property_id, buyer, amount);
+    // Reduce or remove listing
+    MARKETPLACE.with(|mp| {
+        let mut mp = mp.borrow_mut();
+        if let Some(pos) = mp.iter().position(|l| l.listing_id == listing.listing_id) {
+            if mp[pos].amount == amount {
+                mp.remove(pos);
+            } else {
+                mp[pos].amount -= amount;
+            }
+        }
+    });
+    TRADES.with(|t| {
+        t.borrow_mut().push(Trade {
+            property_id,
+            buyer,
+            seller,
+            amount,
+            price_per_share: listing.price_per_share,
             timestamp: now(),
-            actor: caller_principal,
-            details: format!("Listed {} shares of property {} for sale at {} per share", amount, property_id, price_per_share),
         });
     });
-    Ok("Shares listed for sale".to_string())
+    record_property_event(EventType::SharesBought, property_id, buyer, format!("Bought {} shares of property {} from {} for {} (listing {})", amount, property_id, seller, cost, listing.listing_id));
+    Ok(())
+}
+
+#[query]
+pub fn get_last_price(property_id: PropertyId) -> Option<u64> {
+    TRADES.with(|t| t.borrow().iter().rev().find(|trade| trade.property_id == property_id).map(|trade| trade.price_per_share))
+}
+
+/// Volume-weighted average price per share over trades in the last
+/// `window_secs`, rounded down. None if nothing traded in the window.
+#[query]
+pub fn get_vwap(property_id: PropertyId, window_secs: u64) -> Option<u64> {
+    let since = now().saturating_sub(window_secs.saturating_mul(1_000_000_000));
+    let (value, volume) = TRADES.with(|t| {
+        t.borrow()
+            .iter()
+            .filter(|trade| trade.property_id == property_id && trade.timestamp >= since)
+            .fold((0u128, 0u128), |(value, volume), trade| {
+                (value + trade.amount as u128 * trade.price_per_share as u128, volume + trade.amount as u128)
+            })
+    });
+    if volume == 0 {
+        return None;
+    }
+    Some((value / volume) as u64)
+}
+
+/// Settled trades for a property, oldest first. `limit` is clamped to MAX_PAGE_SIZE.
+#[query]
+pub fn get_trade_history(property_id: PropertyId, offset: u64, limit: u64) -> TradePage {
+    let limit = limit.min(MAX_PAGE_SIZE) as usize;
+    TRADES.with(|t| {
+        let t = t.borrow();
+        let matching: Vec<&Trade> = t.iter().filter(|trade| trade.property_id == property_id).collect();
+        TradePage {
+            total: matching.len() as u64,
+            trades: matching.into_iter().skip(offset as usize).take(limit).cloned().collect(),
+        }
+    })
+}
+
+/// Buys up to `amount` shares of a property, filling the cheapest open
+/// listings first (oldest first at equal prices). Stops early if liquidity
+/// runs out; the returned fill reports how much was actually bought.
+#[update]
+pub fn buy_shares_best_price(property_id: PropertyId, buyer: Principal, amount: u64) -> Result<MarketFill, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if caller_principal != buyer {
+        return Err("Buyer must be the caller".to_string());
+    }
+    check_recipient_kyc(&buyer)?;
+    check_not_blacklisted(&[&buyer])?;
+    check_whitelisted(property_id, &buyer)?;
+    check_not_frozen(property_id)?;
+    check_transfers_not_paused(property_id)?;
+    let mut listings: Vec<Listing> = MARKETPLACE.with(|mp| mp.borrow().iter().filter(|l| l.property_id == property_id).cloned().collect());
+    listings.sort_by_key(|l| (l.price_per_share, l.listing_id));
+    // Plan every fill before touching state so the purchase is all-or-nothing
+    let mut seller_available: HashMap<Principal, u64> = HashMap::new();
+    let mut plan: Vec<(Listing, u64, u64)> = Vec::new();
+    let mut remaining = amount;
+    let mut total_cost: u64 = 0;
+    for listing in listings {
+        if remaining == 0 {
+            break;
+        }
+        let seller = listing.seller;
+        // Skip the buyer's own listings rather than self-trading
+        if seller == buyer || check_not_blacklisted(&[&seller]).is_err() || check_lockup(property_id, &seller).is_err() {
+            continue;
+        }
+        let available = seller_available.entry(seller).or_insert_with(|| get_ownership(property_id, seller));
+        let fill = remaining.min(listing.amount).min(*available);
+        if fill == 0 {
+            continue;
+        }
+        let cost = fill.checked_mul(listing.price_per_share).ok_or("Trade value overflow".to_string())?;
+        total_cost = total_cost.checked_add(cost).ok_or("Trade value overflow".to_string())?;
+        *available -= fill;
+        remaining -= fill;
+        plan.push((listing, fill, cost));
+    }
+    let filled = amount - remaining;
+    if filled == 0 {
+        return Err("No matching listings".to_string());
+    }
+    check_ownership_cap(property_id, &buyer, filled)?;
+    if get_balance(buyer) < total_cost {
+        return Err("Insufficient funds".to_string());
+    }
+    let mut fills = Vec::with_capacity(plan.len());
+    for (listing, fill, cost) in plan {
+        settle_listing_fill(&listing, buyer, fill, cost)?;
+        fills.push(ListingFill {
+            listing_id: listing.listing_id,
+            seller: listing.seller,
+            amount: fill,
+            price_per_share: listing.price_per_share,
+            cost,
+        });
+    }
+    assert_share_invariant(property_id);
+    certify_property(property_id);
+    Ok(MarketFill { filled, total_cost, fills })
+}
+
+/// Cancel a marketplace listing. Only the original seller may cancel.
+#[update]
+pub fn cancel_listing(listing_id: u64) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    let result = MARKETPLACE.with(|mp| {
+        let mut mp = mp.borrow_mut();
+        let pos = match mp.iter().position(|l| l.listing_id == listing_id) {
+            Some(pos) => pos,
+            None => return Err("Listing not found".to_string()),
+        };
+        if mp[pos].seller != caller_principal {
+            return Err("Only the seller can cancel this listing".to_string());
+        }
+        Ok(mp.remove(pos))
+    });
+    let listing = result?;
+    record_property_event(EventType::ListingCancelled, listing.property_id, caller_principal, format!("Cancelled listing {} of {} shares of property {}", listing_id, listing.amount, listing.property_id));
+    Ok("Listing cancelled".to_string())
+}
+
+/// Transfer shares directly between users. The caller must be `from`;
+/// spenders acting for an owner use `transfer_from`.
+#[update]
+pub fn transfer_shares(property_id: PropertyId, from: Principal, to: Principal, amount: u64) -> Result<String, String> {
+    check_not_paused()?;
+    if caller() != from {
+        return Err("Sender must be the caller".to_string());
+    }
+    execute_transfer(property_id, from, to, amount, None)?;
+    Ok("Shares transferred".to_string())
+}
+
+/// Like `transfer_shares`, but takes an optional memo (at most MAX_MEMO_LEN
+/// bytes) and returns the transfer's index in the TRANSFERS log.
+#[update]
+pub fn transfer_shares_v2(property_id: PropertyId, from: Principal, to: Principal, amount: u64, memo: Option<Vec<u8>>) -> Result<u64, String> {
+    check_not_paused()?;
+    if caller() != from {
+        return Err("Sender must be the caller".to_string());
+    }
+    execute_transfer(property_id, from, to, amount, memo)
+}
+
+/// Caller lets `spender` move up to `amount` of their shares via
+/// `transfer_from`. Replaces any previous allowance; 0 revokes it.
+#[update]
+pub fn approve_shares(property_id: PropertyId, spender: Principal, amount: u64) -> Result<String, String> {
+    check_not_paused()?;
+    let owner = caller();
+    check_not_blacklisted(&[&owner, &spender])?;
+    if owner == spender {
+        return Err("Cannot approve yourself".to_string());
+    }
+    ALLOWANCES.with(|a| {
+        let mut a = a.borrow_mut();
+        if amount == 0 {
+            a.remove(&(property_id, owner, spender));
+        } else {
+            a.insert((property_id, owner, spender), amount);
+        }
+    });
+    record_property_event(EventType::SharesApproved, property_id, owner, format!("Approved {} to spend {} shares of property {}", spender, amount, property_id));
+    Ok("Allowance updated".to_string())
+}
+
+#[query]
+pub fn get_allowance(property_id: PropertyId, owner: Principal, spender: Principal) -> u64 {
+    ALLOWANCES.with(|a| a.borrow().get(&(property_id, owner, spender)).cloned().unwrap_or(0))
 }
 
-/// Buy shares from the marketplace
+/// Caller spends part of their allowance to move `owner`'s shares to `to`.
+/// Returns the transfer's index in the TRANSFERS log.
 #[update]
-pub fn buy_shares(property_id: PropertyId, seller: Principal, buyer: Principal, amount: u64) -> Result<String, String> {
-    let caller_principal = caller();
-    let mut found = false;
-    MARKETPLACE.with(|mp| {
-        let mut mp = mp.borrow_mut();
-        if let Some(pos) = mp.iter().position(|l| l.property_id == property_id && l.seller == seller && l.amount >= amount) {
-            let price_per_share = mp[pos].price_per_share;
-            // Transfer shares
-            OWNERSHIP.with(|own| {
-                let mut own = own.borrow_mut();
-                // Remove from seller
-                let seller_shares = own.entry((property_id, seller)).or_insert(0);
-                if *seller_shares < amount {
-                    return;
-                }
-                *seller_shares -= amount;
-                // Add to buyer
-                *own.entry((property_id, buyer)).or_insert(0) += amount;
-            });
-            // Reduce or remove listing
-            if mp[pos].amount == amount {
-                mp.remove(pos);
-            } else {
-                mp[pos].amount -= amount;
-            }
-            found = true;
-            EVENTS.with(|events| {
-                events.borrow_mut().push(Event {
-                    event_type: EventType::SharesBought,
-                    timestamp: now(),
-                    actor: caller_principal,
-                    details: format!("Bought {} shares of property {} from {}", amount, property_id, seller),
-                });
-            });
+pub fn transfer_from(property_id: PropertyId, owner: Principal, to: Principal, amount: u64) -> Result<u64, String> {
+    check_not_paused()?;
+    let spender = caller();
+    let allowance = get_allowance(property_id, owner, spender);
+    if allowance < amount {
+        return Err("Insufficient allowance".to_string());
+    }
+    let tx_index = execute_transfer(property_id, owner, to, amount, None)?;
+    ALLOWANCES.with(|a| {
+        let mut a = a.borrow_mut();
+        if allowance == amount {
+            a.remove(&(property_id, owner, spender));
+        } else {
+            a.insert((property_id, owner, spender), allowance - amount);
         }
     });
-    if found {
-        Ok("Shares bought successfully".to_string())
-    } else {
-        Err("Listing not found or insufficient shares".to_string())
-    }
+    Ok(tx_index)
 }
 
-/// Transfer shares directly between users
+/// Admin moves shares under a legal or court order. Bypasses lockups, the
+/// frozen flag and transfer pauses, but the sender must hold the shares and
+/// the recipient must pass the usual KYC, blacklist and whitelist checks.
+/// Logged in TRANSFERS with no fee and the reason, cut to MAX_MEMO_LEN bytes,
+/// as its memo; the event keeps the full reason.
 #[update]
-pub fn transfer_shares(property_id: PropertyId, from: Principal, to: Principal, amount: u64) -> Result<String, String> {
+pub fn admin_force_transfer(property_id: PropertyId, from: Principal, to: Principal, amount: u64, reason: String) -> Result<String, String> {
+    check_not_paused()?;
     let caller_principal = caller();
-    let result = OWNERSHIP.with(|own| {
-        let mut own = own.borrow_mut();
-        let from_shares = own.entry((property_id, from)).or_insert(0);
-        if *from_shares < amount {
-            return Err("Not enough shares to transfer".to_string());
-        }
-        *from_shares -= amount;
-        *own.entry((property_id, to)).or_insert(0) += amount;
-        Ok("Shares transferred".to_string())
-    });
-    if result.is_ok() {
-        EVENTS.with(|events| {
-            events.borrow_mut().push(Event {
-                event_type: EventType::SharesTransferred,
-                timestamp: now(),
-                actor: caller_principal,
-                details: format!("Transferred {} shares of property {} from {} to {}", amount, property_id, from, to),
-            });
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can force transfers".to_string());
+    }
+    let reason = reason.trim().to_string();
+    if reason.is_empty() {
+        return Err("A reason is required for forced transfers".to_string());
+    }
+    if from == to {
+        return Err("Cannot transfer shares to yourself".to_string());
+    }
+    if get_property(property_id).is_none() {
+        return Err("Property not found".to_string());
+    }
+    check_not_blacklisted(&[&to])?;
+    check_unclassed(property_id)?;
+    check_recipient_kyc(&to)?;
+    check_whitelisted(property_id, &to)?;
+    debit_shares(property_id, from, amount).map_err(|_| "Not enough shares to transfer".to_string())?;
+    credit_shares(property_id, to, amount);
+    trim_listings(property_id, from);
+    assert_share_invariant(property_id);
+    certify_property(property_id);
+    let mut memo_len = reason.len().min(MAX_MEMO_LEN);
+    while !reason.is_char_boundary(memo_len) {
+        memo_len -= 1;
+    }
+    let tx_index = record_transfer(property_id, from, to, amount, 0, Some(reason.as_bytes()[..memo_len].to_vec()), None);
+    record_property_event(EventType::SharesForceTransferred, property_id, caller_principal, format!("Force-transferred {} shares of property {} from {} to {} (tx {}): {}", amount, property_id, from, to, tx_index, reason));
+    Ok("Shares force-transferred".to_string())
+}
+
+/// Shared transfer path; callers are responsible for authorizing the move.
+/// The transfer fee is withheld in shares, rounded down, and returned to
+/// `shares_available`, so `to` receives `amount - fee`.
+fn execute_transfer(property_id: PropertyId, from: Principal, to: Principal, amount: u64, memo: Option<Vec<u8>>) -> Result<u64, String> {
+    let caller_principal = caller();
+    if from == to {
+        return Err("Cannot transfer shares to yourself".to_string());
+    }
+    if memo.as_ref().is_some_and(|m| m.len() > MAX_MEMO_LEN) {
+        return Err(format!("Memo cannot exceed {} bytes", MAX_MEMO_LEN));
+    }
+    check_not_blacklisted(&[&caller_principal, &from, &to])?;
+    check_not_frozen(property_id)?;
+    check_unclassed(property_id)?;
+    check_transfers_not_paused(property_id)?;
+    check_recipient_kyc(&to)?;
+    check_whitelisted(property_id, &to)?;
+    check_lockup(property_id, &from)?;
+    // Shares backing open listings stay with the seller until cancelled or sold
+    if get_ownership(property_id, from).saturating_sub(get_listed_shares(property_id, from)) < amount {
+        return Err("Not enough unlisted shares to transfer".to_string());
+    }
+    let fee = transfer_fee(amount);
+    let received = amount - fee;
+    check_ownership_cap(property_id, &to, received)?;
+    debit_shares(property_id, from, amount).map_err(|_| "Not enough shares to transfer".to_string())?;
+    credit_shares(property_id, to, received);
+    return_fee_shares(property_id, fee);
+    assert_share_invariant(property_id);
+    certify_property(property_id);
+    let tx_index = record_transfer(property_id, from, to, amount, fee, memo, None);
+    record_property_event(EventType::SharesTransferred, property_id, caller_principal, format!("Transferred {} shares of property {} from {} to {} (tx {}, fee {})", amount, property_id, from, to, tx_index, fee));
+    Ok(tx_index)
+}
+
+/// Transfer fee on `amount`, in shares, rounded down.
+fn transfer_fee(amount: u64) -> u64 {
+    (amount as u128 * TRANSFER_FEE_BPS.with(|f| *f.borrow()) as u128 / 10_000) as u64
+}
+
+/// Withheld fee shares go back to `shares_available` so they can be issued again.
+fn return_fee_shares(property_id: PropertyId, fee: u64) {
+    if fee > 0 {
+        PROPERTIES.with(|props| {
+            if let Some(prop) = props.borrow_mut().get_mut(&property_id) {
+                prop.shares_available += fee;
+            }
         });
     }
-    result
+}
+
+/// Appends to the TRANSFERS log and returns the new record's tx_index.
+fn record_transfer(property_id: PropertyId, from: Principal, to: Principal, amount: u64, fee: u64, memo: Option<Vec<u8>>, class_index: Option<u32>) -> u64 {
+    TRANSFERS.with(|t| {
+        let mut t = t.borrow_mut();
+        let tx_index = t.len() as u64;
+        t.push(TransferRecord {
+            tx_index,
+            property_id,
+            from,
+            to,
+            amount,
+            timestamp: now(),
+            memo,
+            fee,
+            class_index,
+        });
+        tx_index
+    })
+}
+
+#[query]
+pub fn get_transfer(tx_index: u64) -> Option<TransferRecord> {
+    TRANSFERS.with(|t| t.borrow().get(tx_index as usize).cloned())
 }
 
 /// Get all marketplace listings
@@ -503,9 +4125,49 @@ pub fn get_marketplace_listings() -> Vec<Listing> {
     MARKETPLACE.with(|mp| mp.borrow().clone())
 }
 
+/// Open listings for a property aggregated by price level.
+#[query]
+pub fn get_order_book(property_id: PropertyId) -> OrderBook {
+    let mut levels: BTreeMap<u64, u64> = BTreeMap::new();
+    MARKETPLACE.with(|mp| {
+        for listing in mp.borrow().iter().filter(|l| l.property_id == property_id) {
+            let level = levels.entry(listing.price_per_share).or_insert(0);
+            *level = level.saturating_add(listing.amount);
+        }
+    });
+    OrderBook {
+        property_id,
+        levels: levels.into_iter().collect(),
+    }
+}
+
+/// Get a page of marketplace listings. `limit` is clamped to MAX_PAGE_SIZE.
+#[query]
+pub fn get_marketplace_listings_paged(offset: u64, limit: u64) -> ListingPage {
+    let limit = limit.min(MAX_PAGE_SIZE) as usize;
+    MARKETPLACE.with(|mp| {
+        let mp = mp.borrow();
+        ListingPage {
+            listings: mp.iter().skip(offset as usize).take(limit).cloned().collect(),
+            total: mp.len() as u64,
+        }
+    })
+}
+
 #[update]
-pub fn submit_proposal(property_id: PropertyId, description: String) -> Proposal {
+pub fn submit_proposal(property_id: PropertyId, description: String, action: Option<ProposalAction>, voting_period_secs: u64) -> Result<Proposal, String> {
+    check_not_paused()?;
     let proposer = caller();
+    let total_shares = get_property(property_id).map(|p| p.total_shares).ok_or("Property not found".to_string())?;
+    // Proposers must hold shares, and at least MIN_PROPOSAL_BPS of the property
+    let owned = get_ownership(property_id, proposer);
+    let min_bps = MIN_PROPOSAL_BPS.with(|m| *m.borrow());
+    if owned == 0 || (owned as u128 * 10_000) < total_shares as u128 * min_bps as u128 {
+        return Err("Insufficient stake to propose".to_string());
+    }
+    let voting_deadline_ns = now().saturating_add(voting_period_secs.saturating_mul(1_000_000_000));
+    // Freeze voting power now so shares bought after submission carry no weight
+    let snapshot: HashMap<Principal, u64> = holders_of(property_id).into_iter().collect();
     let id = NEXT_PROPOSAL_ID.with(|next| {
         let mut next = next.borrow_mut();
         let curr = *next;
@@ -517,99 +4179,396 @@ pub fn submit_proposal(property_id: PropertyId, description: String) -> Proposal
         property_id,
         proposer,
         description: description.clone(),
+        action,
         status: ProposalStatus::Open,
         yes_votes: 0,
         no_votes: 0,
         votes: HashMap::new(),
+        vote_weights: HashMap::new(),
+        voting_deadline_ns,
     };
     PROPOSALS.with(|props| {
         props.borrow_mut().insert(id, proposal.clone());
     });
-    EVENTS.with(|events| {
-        events.borrow_mut().push(Event {
-            event_type: EventType::ProposalSubmitted,
-            timestamp: now(),
-            actor: proposer,
-            details: format!("Submitted proposal {} for property {}: {}", id, property_id, description),
-        });
-    });
-    proposal
+    PROPOSAL_SNAPSHOTS.with(|s| s.borrow_mut().insert(id, snapshot));
+    record_property_event(EventType::ProposalSubmitted, property_id, proposer, format!("Submitted proposal {} for property {}: {}", id, property_id, description));
+    Ok(proposal)
 }
 
 #[update]
 pub fn vote_on_proposal(proposal_id: u64, vote: bool) -> Result<String, String> {
+    check_not_paused()?;
     let voter = caller();
-    let mut found = false;
+    let mut result = Err("Proposal not found, not open, or no shares".to_string());
     PROPOSALS.with(|props| {
         let mut props = props.borrow_mut();
         if let Some(prop) = props.get_mut(&proposal_id) {
             if prop.status != ProposalStatus::Open {
                 return;
             }
-            if prop.votes.contains_key(&voter) {
+            if now() > prop.voting_deadline_ns {
+                result = Err("Voting period ended".to_string());
                 return;
             }
-            // Get voter's shares for the property
-            let shares = OWNERSHIP.with(|own| own.borrow().get(&(prop.property_id, voter)).cloned().unwrap_or(0));
+            // Voter's snapshotted shares plus any delegated to them
+            let shares = PROPOSAL_SNAPSHOTS.with(|s| s.borrow().get(&proposal_id).map(|snapshot| voting_weight(prop.property_id, &voter, snapshot)).unwrap_or(0));
             if shares == 0 {
                 return;
             }
-            prop.votes.insert(voter, vote);
+            // A repeat vote replaces the earlier one
+            if let Some(previous) = prop.votes.insert(voter, vote) {
+                let previous_weight = prop.vote_weights.get(&voter).cloned().unwrap_or(0);
+                if previous {
+                    prop.yes_votes -= previous_weight;
+                } else {
+                    prop.no_votes -= previous_weight;
+                }
+            }
+            prop.vote_weights.insert(voter, shares);
             if vote {
                 prop.yes_votes += shares;
             } else {
                 prop.no_votes += shares;
             }
-            found = true;
-            EVENTS.with(|events| {
-                events.borrow_mut().push(Event {
-                    event_type: EventType::ProposalVoted,
-                    timestamp: now(),
-                    actor: voter,
-                    details: format!("Voted {} on proposal {} for property {}", if vote {"yes"} else {"no"}, proposal_id, prop.property_id),
-                });
-            });
+            result = Ok("Vote recorded".to_string());
+            record_property_event(EventType::ProposalVoted, prop.property_id, voter, format!("Voted {} on proposal {} for property {}", if vote {"yes"} else {"no"}, proposal_id, prop.property_id));
         }
     });
-    if found {
-        Ok("Vote recorded".to_string())
-    } else {
-        Err("Proposal not found, not open, already voted, or no shares".to_string())
+    result
+}
+
+/// Voting weight for a property: own snapshotted shares unless delegated away, plus
+/// snapshotted shares of every holder delegating directly to `voter`. Delegation is
+/// not transitive.
+fn voting_weight(property_id: PropertyId, voter: &Principal, snapshot: &HashMap<Principal, u64>) -> u64 {
+    let shares_of = |p: &Principal| snapshot.get(p).cloned().unwrap_or(0);
+    DELEGATIONS.with(|d| {
+        let d = d.borrow();
+        let own = if d.contains_key(&(property_id, *voter)) { 0 } else { shares_of(voter) };
+        d.iter()
+            .filter(|((pid, _), delegate)| *pid == property_id && *delegate == voter)
+            .fold(own, |acc, ((_, delegator), _)| acc.saturating_add(shares_of(delegator)))
+    })
+}
+
+/// Whether `voter` has a vote counted on a proposal for the property that is
+/// still taking votes. Delegation changes are refused while one exists, since
+/// shares already counted in that vote would otherwise be counted again.
+fn has_live_vote(property_id: PropertyId, voter: &Principal) -> bool {
+    let at = now();
+    PROPOSALS.with(|props| {
+        props.borrow().values().any(|p| {
+            p.property_id == property_id && p.status == ProposalStatus::Open && at <= p.voting_deadline_ns && p.votes.contains_key(voter)
+        })
+    })
+}
+
+/// Caller delegates their voting power for a property to another principal.
+/// Not allowed while the caller, or the caller's current delegate, has voted on
+/// a proposal that is still open.
+#[update]
+pub fn delegate_vote(property_id: PropertyId, to: Principal) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if to == caller_principal {
+        return Err("Cannot delegate to yourself".to_string());
+    }
+    if has_live_vote(property_id, &caller_principal) {
+        return Err("Cannot delegate while your vote is counted on an open proposal".to_string());
+    }
+    if let Some(delegate) = get_delegate(property_id, caller_principal) {
+        if has_live_vote(property_id, &delegate) {
+            return Err("Cannot re-delegate while your delegate's vote is counted on an open proposal".to_string());
+        }
+    }
+    DELEGATIONS.with(|d| {
+        let mut d = d.borrow_mut();
+        // Walk the delegate's chain; reaching the caller would form a cycle
+        let mut current = to;
+        while let Some(next) = d.get(&(property_id, current)) {
+            if *next == caller_principal {
+                return Err("Delegation would create a cycle".to_string());
+            }
+            current = *next;
+        }
+        d.insert((property_id, caller_principal), to);
+        Ok(())
+    })?;
+    record_property_event(EventType::VoteDelegated, property_id, caller_principal, format!("Delegated votes for property {} to {}", property_id, to));
+    Ok("Vote delegated".to_string())
+}
+
+/// Caller revokes their delegation for a property and votes with their own shares
+/// again. Not allowed while the delegate has voted on a proposal that is still open.
+#[update]
+pub fn revoke_delegation(property_id: PropertyId) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    let delegate = get_delegate(property_id, caller_principal).ok_or("No delegation to revoke".to_string())?;
+    if has_live_vote(property_id, &delegate) {
+        return Err("Cannot revoke while your delegate's vote is counted on an open proposal".to_string());
     }
+    DELEGATIONS.with(|d| d.borrow_mut().remove(&(property_id, caller_principal)));
+    record_property_event(EventType::DelegationRevoked, property_id, caller_principal, format!("Revoked vote delegation for property {}", property_id));
+    Ok("Delegation revoked".to_string())
+}
+
+#[query]
+pub fn get_delegate(property_id: PropertyId, user: Principal) -> Option<Principal> {
+    DELEGATIONS.with(|d| d.borrow().get(&(property_id, user)).cloned())
 }
 
+/// Finalizes a proposal once its voting deadline has passed: executes it if
+/// it reached quorum and its approval threshold, otherwise rejects it. A
+/// proposal short of quorum fails with `Err("Quorum not reached")`, but is
+/// still closed as Rejected so it doesn't stay open forever.
 #[update]
 pub fn execute_proposal(proposal_id: u64) -> Result<String, String> {
+    check_not_paused()?;
     let caller_principal = caller();
-    let mut result = Err("Proposal not found or not open".to_string());
+    let mut result = Err("Proposal not found".to_string());
     PROPOSALS.with(|props| {
         let mut props = props.borrow_mut();
         if let Some(prop) = props.get_mut(&proposal_id) {
+            // Distinct from "not found" so retrying clients know the first run landed
             if prop.status != ProposalStatus::Open {
+                result = Err("Proposal already finalized".to_string());
+                return;
+            }
+            // Votes can change until the deadline, so the outcome is only final after it
+            if now() <= prop.voting_deadline_ns {
+                result = Err("Voting period not ended".to_string());
                 return;
             }
-            // Simple majority
-            if prop.yes_votes > prop.no_votes {
+            // Quorum is measured against the property's total shares; no more votes
+            // can arrive, so a proposal short of quorum is closed as rejected even
+            // though the call reports failure
+            let total_shares = PROPERTIES.with(|p| p.borrow().get(&prop.property_id).map(|p| p.total_shares).unwrap_or(0));
+            let quorum_bps = QUORUM_BPS.with(|q| *q.borrow());
+            let participating = prop.yes_votes as u128 + prop.no_votes as u128;
+            if participating * 10_000 < total_shares as u128 * quorum_bps as u128 {
+                prop.status = ProposalStatus::Rejected;
+                result = Err("Quorum not reached".to_string());
+                record_property_event(EventType::ProposalRejected, prop.property_id, caller_principal, format!("Rejected proposal {} for property {}: quorum not reached", proposal_id, prop.property_id));
+                return;
+            }
+            // Yes votes must beat no votes and reach the action's threshold share of votes cast
+            let threshold_bps = get_approval_threshold_bps(proposal_kind(&prop.action));
+            if prop.yes_votes > prop.no_votes && prop.yes_votes as u128 * 10_000 >= participating * threshold_bps as u128 {
+                // Keep the proposal open if its action fails so it can be retried
+                if let Some(action) = prop.action.clone() {
+                    if let Err(e) = apply_proposal_action(prop.property_id, action, caller_principal) {
+                        result = Err(e);
+                        return;
+                    }
+                }
                 prop.status = ProposalStatus::Approved;
                 prop.status = ProposalStatus::Executed;
                 result = Ok("Proposal approved and executed".to_string());
-                EVENTS.with(|events| {
-                    events.borrow_mut().push(Event {
-                        event_type: EventType::ProposalExecuted,
-                        timestamp: now(),
-                        actor: caller_principal,
-                        details: format!("Executed proposal {} for property {}", proposal_id, prop.property_id),
-                    });
-                });
+                record_property_event(EventType::ProposalExecuted, prop.property_id, caller_principal, format!("Executed proposal {} for property {}", proposal_id, prop.property_id));
             } else {
                 prop.status = ProposalStatus::Rejected;
                 result = Ok("Proposal rejected".to_string());
+                record_property_event(EventType::ProposalRejected, prop.property_id, caller_principal, format!("Rejected proposal {} for property {}", proposal_id, prop.property_id));
             }
         }
     });
     result
 }
 
+fn apply_proposal_action(property_id: PropertyId, action: ProposalAction, actor: Principal) -> Result<String, String> {
+    match action {
+        ProposalAction::UpdateStatus(status) => apply_property_status(property_id, status, actor),
+        ProposalAction::UpdateMetadata(metadata) => apply_property_metadata(property_id, metadata, actor),
+        ProposalAction::DistributeReserve(amount) => {
+            debit_reserve(property_id, amount)?;
+            distribute_income(property_id, amount, actor).inspect_err(|_| credit_reserve(property_id, amount))
+        }
+        ProposalAction::SetTransfersPaused(paused) => apply_transfers_paused(property_id, paused, actor),
+        ProposalAction::Rename(name) => apply_property_rename(property_id, name, actor),
+    }
+}
+
+/// Admin sets the quorum, in basis points of a property's total shares.
+#[update]
+pub fn set_quorum_bps(quorum_bps: u16) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can set quorum".to_string());
+    }
+    if quorum_bps > 10_000 {
+        return Err("Quorum cannot exceed 10000 bps".to_string());
+    }
+    QUORUM_BPS.with(|q| *q.borrow_mut() = quorum_bps);
+    record_event(EventType::QuorumSet, caller_principal, format!("Set proposal quorum to {} bps", quorum_bps));
+    Ok("Quorum updated".to_string())
+}
+
+#[query]
+pub fn get_quorum_bps() -> u16 {
+    QUORUM_BPS.with(|q| *q.borrow())
+}
+
+/// Admin sets the minimum stake, in basis points of total shares, needed to submit a proposal.
+#[update]
+pub fn set_min_proposal_bps(min_proposal_bps: u16) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can set the proposal stake".to_string());
+    }
+    if min_proposal_bps > 10_000 {
+        return Err("Proposal stake cannot exceed 10000 bps".to_string());
+    }
+    MIN_PROPOSAL_BPS.with(|m| *m.borrow_mut() = min_proposal_bps);
+    record_event(EventType::MinProposalStakeSet, caller_principal, format!("Set minimum proposal stake to {} bps", min_proposal_bps));
+    Ok("Proposal stake updated".to_string())
+}
+
+#[query]
+pub fn get_min_proposal_bps() -> u16 {
+    MIN_PROPOSAL_BPS.with(|m| *m.borrow())
+}
+
+fn proposal_kind(action: &Option<ProposalAction>) -> ProposalKind {
+    match action {
+        None => ProposalKind::Signal,
+        Some(ProposalAction::UpdateStatus(_)) => ProposalKind::UpdateStatus,
+        Some(ProposalAction::UpdateMetadata(_)) => ProposalKind::UpdateMetadata,
+        Some(ProposalAction::DistributeReserve(_)) => ProposalKind::DistributeReserve,
+        Some(ProposalAction::SetTransfersPaused(_)) => ProposalKind::SetTransfersPaused,
+        Some(ProposalAction::Rename(_)) => ProposalKind::Rename,
+    }
+}
+
+/// Status changes (e.g. marking a property Sold) and moving reserve funds
+/// need a two-thirds supermajority; everything else a simple majority.
+fn default_approval_threshold_bps(kind: ProposalKind) -> u16 {
+    match kind {
+        ProposalKind::UpdateStatus | ProposalKind::DistributeReserve => 6667,
+        _ => 5000,
+    }
+}
+
+/// Admin overrides the share of votes cast that must be yes for a kind of proposal.
+#[update]
+pub fn set_approval_threshold_bps(kind: ProposalKind, threshold_bps: u16) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can set approval thresholds".to_string());
+    }
+    if !(5000..=10_000).contains(&threshold_bps) {
+        return Err("Approval threshold must be between 5000 and 10000 bps".to_string());
+    }
+    APPROVAL_THRESHOLDS.with(|t| t.borrow_mut().insert(kind, threshold_bps));
+    record_event(EventType::ApprovalThresholdSet, caller_principal, format!("Set approval threshold for {:?} proposals to {} bps", kind, threshold_bps));
+    Ok("Approval threshold updated".to_string())
+}
+
+#[query]
+pub fn get_approval_threshold_bps(kind: ProposalKind) -> u16 {
+    APPROVAL_THRESHOLDS.with(|t| t.borrow().get(&kind).cloned()).unwrap_or_else(|| default_approval_threshold_bps(kind))
+}
+
+#[query]
+pub fn get_proposal(proposal_id: u64) -> Option<Proposal> {
+    PROPOSALS.with(|props| props.borrow().get(&proposal_id).cloned())
+}
+
+#[query]
+pub fn get_proposal_stats(proposal_id: u64) -> Option<ProposalStats> {
+    let proposal = get_proposal(proposal_id)?;
+    let total_shares = get_total_shares(proposal.property_id);
+    let participating_shares = proposal.yes_votes.saturating_add(proposal.no_votes);
+    let turnout_bps = (participating_shares as u128 * 10_000).checked_div(total_shares as u128).unwrap_or(0) as u64;
+    Some(ProposalStats {
+        yes_votes: proposal.yes_votes,
+        no_votes: proposal.no_votes,
+        participating_shares,
+        total_shares,
+        turnout_bps,
+        voter_count: proposal.votes.len() as u64,
+    })
+}
+
+/// `user`'s holdings of the proposal's property when it was submitted, before
+/// delegation.
+#[query]
+pub fn get_snapshot_shares(proposal_id: u64, user: Principal) -> u64 {
+    PROPOSAL_SNAPSHOTS.with(|s| s.borrow().get(&proposal_id).and_then(|snapshot| snapshot.get(&user)).cloned().unwrap_or(0))
+}
+
+/// How each voter voted on a proposal and with what weight, heaviest first.
+/// Weights are the ones counted in the tally, i.e. snapshot holdings.
+#[query]
+pub fn get_proposal_votes(proposal_id: u64) -> Vec<VoteRecord> {
+    let Some(proposal) = get_proposal(proposal_id) else {
+        return Vec::new();
+    };
+    let mut votes: Vec<VoteRecord> = proposal
+        .votes
+        .iter()
+        .map(|(voter, choice)| VoteRecord {
+            voter: *voter,
+            choice: *choice,
+            weight: proposal.vote_weights.get(voter).cloned().unwrap_or_else(|| get_snapshot_shares(proposal_id, *voter)),
+        })
+        .collect();
+    votes.sort_by(|a, b| b.weight.cmp(&a.weight).then(a.voter.cmp(&b.voter)));
+    votes
+}
+
+/// The proposer or an admin withdraws a proposal that is still open.
+#[update]
+pub fn cancel_proposal(proposal_id: u64) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    PROPOSALS.with(|props| {
+        let mut props = props.borrow_mut();
+        let prop = props.get_mut(&proposal_id).ok_or("Proposal not found".to_string())?;
+        if prop.proposer != caller_principal && get_role(&caller_principal) != Role::Admin {
+            return Err("Only the proposer or an admin can cancel a proposal".to_string());
+        }
+        if prop.status != ProposalStatus::Open {
+            return Err("Only open proposals can be cancelled".to_string());
+        }
+        prop.status = ProposalStatus::Cancelled;
+        record_property_event(EventType::ProposalCancelled, prop.property_id, caller_principal, format!("Cancelled proposal {} for property {}", proposal_id, prop.property_id));
+        Ok("Proposal cancelled".to_string())
+    })
+}
+
+/// Current holders of the proposal's property can add to its discussion thread.
+#[update]
+pub fn add_comment(proposal_id: u64, text: String) -> Result<String, String> {
+    check_not_paused()?;
+    let author = caller();
+    check_not_blacklisted(&[&author])?;
+    let property_id = get_proposal(proposal_id).map(|p| p.property_id).ok_or("Proposal not found".to_string())?;
+    if get_ownership(property_id, author) == 0 {
+        return Err("Only holders of the property can comment".to_string());
+    }
+    let text = text.trim().to_string();
+    if text.is_empty() {
+        return Err("Comment cannot be empty".to_string());
+    }
+    if text.len() > MAX_COMMENT_LEN {
+        return Err(format!("Comment cannot exceed {} bytes", MAX_COMMENT_LEN));
+    }
+    COMMENTS.with(|c| {
+        c.borrow_mut().entry(proposal_id).or_default().push(Comment { author, timestamp: now(), text });
+    });
+    record_property_event(EventType::ProposalCommented, property_id, author, format!("Commented on proposal {} for property {}", proposal_id, property_id));
+    Ok("Comment added".to_string())
+}
+
+#[query]
+pub fn get_comments(proposal_id: u64) -> Vec<Comment> {
+    COMMENTS.with(|c| c.borrow().get(&proposal_id).cloned().unwrap_or_default())
+}
+
 #[query]
 pub fn get_proposals(property_id: PropertyId) -> Vec<Proposal> {
     PROPOSALS.with(|props| {
@@ -617,48 +4576,252 @@ pub fn get_proposals(property_id: PropertyId) -> Vec<Proposal> {
     })
 }
 
+/// Get a page of proposals sorted by id, optionally filtered by property and status.
+/// `total` counts all proposals matching the filters.
 #[query]
-pub fn get_ownership_statement(user: Principal) -> Vec<OwnershipRecord> {
-    OWNERSHIP.with(|own| {
-        own.borrow()
-            .iter()
-            .filter(|((_, u), shares)| *u == user && **shares > 0)
-            .map(|((pid, _), shares)| {
-                let property_name = PROPERTIES.with(|props| props.borrow().get(pid).map(|p| p.name.clone()).unwrap_or_default());
-                OwnershipRecord {
-                    property_id: *pid,
-                    property_name,
-                    shares: *shares,
-                }
-            })
-            .collect()
+pub fn get_proposals_filtered(property_id: Option<PropertyId>, status: Option<ProposalStatus>, offset: u64, limit: u64) -> ProposalPage {
+    let limit = limit.min(MAX_PAGE_SIZE) as usize;
+    PROPOSALS.with(|props| {
+        let props = props.borrow();
+        let mut matching: Vec<&Proposal> = props
+            .values()
+            .filter(|p| property_id.is_none_or(|pid| p.property_id == pid))
+            .filter(|p| status.as_ref().is_none_or(|s| p.status == *s))
+            .collect();
+        matching.sort_by_key(|p| p.id);
+        ProposalPage {
+            total: matching.len() as u64,
+            proposals: matching.into_iter().skip(offset as usize).take(limit).cloned().collect(),
+        }
     })
 }
 
+#[query]
+pub fn get_ownership_statement(user: Principal) -> Vec<OwnershipRecord> {
+    let mut property_ids: Vec<PropertyId> = PROPERTIES_BY_HOLDER.with(|p| {
+        p.borrow().get(&user).map(|ids| ids.iter().cloned().collect()).unwrap_or_default()
+    });
+    property_ids.sort();
+    property_ids
+        .into_iter()
+        .map(|pid| {
+            let property_name = PROPERTIES.with(|props| props.borrow().get(&pid).map(|p| p.name.clone()).unwrap_or_default());
+            OwnershipRecord {
+                property_id: pid,
+                property_name,
+                shares: get_ownership(pid, user),
+            }
+        })
+        .collect()
+}
+
+/// A user's holdings and unclaimed income across all properties, valued at
+/// each property's latest recorded valuation.
+#[query]
+pub fn get_portfolio(user: Principal) -> Portfolio {
+    let mut property_ids: HashSet<PropertyId> = PROPERTIES_BY_HOLDER.with(|p| p.borrow().get(&user).cloned().unwrap_or_default());
+    UNCLAIMED_INCOME.with(|ui| {
+        property_ids.extend(ui.borrow().keys().filter(|(_, holder)| *holder == user).map(|(pid, _)| *pid));
+    });
+    let mut property_ids: Vec<PropertyId> = property_ids.into_iter().collect();
+    property_ids.sort();
+    let mut portfolio = Portfolio { positions: Vec::new(), total_unclaimed_income: 0, total_estimated_value: 0 };
+    for pid in property_ids {
+        let property = get_property(pid);
+        let shares = get_ownership(pid, user);
+        let unclaimed_income = get_unclaimed_income(pid, user);
+        let latest_valuation = VALUATIONS.with(|v| v.borrow().get(&pid).and_then(|history| history.last().map(|(_, valuation)| *valuation)));
+        let estimated_value = match (&property, latest_valuation) {
+            (Some(p), Some(valuation)) if p.total_shares > 0 => proportional_share(valuation, shares, p.total_shares).unwrap_or(0),
+            _ => 0,
+        };
+        portfolio.total_unclaimed_income = portfolio.total_unclaimed_income.saturating_add(unclaimed_income);
+        portfolio.total_estimated_value = portfolio.total_estimated_value.saturating_add(estimated_value);
+        portfolio.positions.push(PortfolioPosition {
+            property_id: pid,
+            property_name: property.map(|p| p.name).unwrap_or_default(),
+            shares,
+            unclaimed_income,
+            estimated_value,
+        });
+    }
+    portfolio
+}
+
+#[query]
+pub fn get_my_ownership_statement() -> Vec<OwnershipRecord> {
+    get_ownership_statement(caller())
+}
+
+#[query]
+pub fn get_my_listings() -> Vec<Listing> {
+    let caller_principal = caller();
+    MARKETPLACE.with(|mp| mp.borrow().iter().filter(|l| l.seller == caller_principal).cloned().collect())
+}
+
+/// Covers both UNCLAIMED_INCOME and the holder index: settled income can remain
+/// for properties the user no longer holds, while pending income is only
+/// tracked against current holdings.
 #[query]
 pub fn get_rental_income_statement(user: Principal) -> Vec<RentalIncomeRecord> {
+    let mut property_ids: HashSet<PropertyId> = PROPERTIES_BY_HOLDER.with(|p| p.borrow().get(&user).cloned().unwrap_or_default());
     UNCLAIMED_INCOME.with(|ui| {
-        ui.borrow()
+        property_ids.extend(ui.borrow().keys().filter(|(_, u)| *u == user).map(|(pid, _)| *pid));
+    });
+    let mut property_ids: Vec<PropertyId> = property_ids.into_iter().collect();
+    property_ids.sort();
+    property_ids
+        .into_iter()
+        .map(|pid| (pid, get_unclaimed_income(pid, user)))
+        .filter(|(_, income)| *income > 0)
+        .map(|(pid, income)| {
+            let property_name = PROPERTIES.with(|props| props.borrow().get(&pid).map(|p| p.name.clone()).unwrap_or_default());
+            RentalIncomeRecord {
+                property_id: pid,
+                property_name,
+                income,
+            }
+        })
+        .collect()
+}
+
+/// Canister-wide business snapshot, computed by scanning state.
+#[query]
+pub fn get_statistics() -> Statistics {
+    let (property_ids, total_shares_issued) = PROPERTIES.with(|props| {
+        let props = props.borrow();
+        let issued = props.values().fold(0u64, |acc, p| acc.saturating_add(p.total_shares.saturating_sub(p.shares_available)));
+        (props.keys().cloned().collect::<Vec<PropertyId>>(), issued)
+    });
+    let settled = UNCLAIMED_INCOME.with(|ui| ui.borrow().values().fold(0u64, |acc, v| acc.saturating_add(*v)));
+    let pending = property_ids.iter().fold(0u64, |acc, pid| acc.saturating_add(pending_income_for_property(*pid)));
+    Statistics {
+        total_properties: property_ids.len() as u64,
+        total_shares_issued,
+        total_holders: PROPERTIES_BY_HOLDER.with(|p| p.borrow().len() as u64),
+        total_income_deposited: RENTAL_INCOME.with(|ri| ri.borrow().values().fold(0u64, |acc, v| acc.saturating_add(*v))),
+        total_unclaimed_income: settled.saturating_add(pending),
+        open_proposals: PROPOSALS.with(|props| props.borrow().values().filter(|p| p.status == ProposalStatus::Open).count() as u64),
+        marketplace_listings: MARKETPLACE.with(|mp| mp.borrow().len() as u64),
+    }
+}
+
+#[query]
+pub fn get_cycle_balance() -> Result<u64, String> {
+    if get_role(&caller()) != Role::Admin {
+        return Err("Only admin can view the cycle balance".to_string());
+    }
+    Ok(canister_balance())
+}
+
+/// Admin sets the cycle balance below which `cycles_status` reports unhealthy.
+#[update]
+pub fn set_low_cycle_threshold(threshold: u64) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can set the cycle threshold".to_string());
+    }
+    LOW_CYCLE_THRESHOLD.with(|t| *t.borrow_mut() = threshold);
+    record_event(EventType::CycleThresholdSet, caller_principal, format!("Set low cycle threshold to {}", threshold));
+    Ok("Cycle threshold updated".to_string())
+}
+
+#[query]
+pub fn cycles_status() -> Result<CycleStatus, String> {
+    let balance = get_cycle_balance()?;
+    let threshold = LOW_CYCLE_THRESHOLD.with(|t| *t.borrow());
+    Ok(CycleStatus { balance, threshold, healthy: balance >= threshold })
+}
+
+/// Admin subscribes a canister to push notifications for the given event
+/// types, replacing any earlier subscription. The canister must expose
+/// `on_rwa_event : (Event) -> ()`; delivery is one-way and best-effort.
+#[update]
+pub fn register_notifier(canister_id: Principal, events: Vec<EventType>) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can register notifiers".to_string());
+    }
+    if events.is_empty() {
+        return Err("Notifier must subscribe to at least one event type".to_string());
+    }
+    let mut kinds: Vec<EventType> = Vec::new();
+    for kind in events {
+        if !kinds.contains(&kind) {
+            kinds.push(kind);
+        }
+    }
+    let count = kinds.len();
+    NOTIFIERS.with(|n| n.borrow_mut().insert(canister_id, kinds));
+    record_event(EventType::NotifierRegistered, caller_principal, format!("Registered notifier {} for {} event types", canister_id, count));
+    Ok("Notifier registered".to_string())
+}
+
+#[update]
+pub fn unregister_notifier(canister_id: Principal) -> Result<String, String> {
+    check_not_paused()?;
+    let caller_principal = caller();
+    if get_role(&caller_principal) != Role::Admin {
+        return Err("Only admin can remove notifiers".to_string());
+    }
+    if NOTIFIERS.with(|n| n.borrow_mut().remove(&canister_id)).is_none() {
+        return Err("Notifier not found".to_string());
+    }
+    record_event(EventType::NotifierRemoved, caller_principal, format!("Removed notifier {}", canister_id));
+    Ok("Notifier removed".to_string())
+}
+
+#[query]
+pub fn get_notifiers() -> Result<Vec<(Principal, Vec<EventType>)>, String> {
+    if get_role(&caller()) != Role::Admin {
+        return Err("Only admin can view notifiers".to_string());
+    }
+    Ok(NOTIFIERS.with(|n| n.borrow().iter().map(|(canister, kinds)| (*canister, kinds.clone())).collect()))
+}
+
+/// Admin-only page of the audit log, oldest first.
+#[query]
+pub fn get_events(offset: u64, limit: u64) -> Result<Vec<Event>, String> {
+    if get_role(&caller()) != Role::Admin {
+        return Err("Only admin can view the audit log".to_string());
+    }
+    let limit = limit.min(MAX_PAGE_SIZE) as usize;
+    Ok(EVENTS.with(|events| events.borrow().iter().skip(offset as usize).take(limit).cloned().collect()))
+}
+
+/// Most recent activity for a property (issuances, trades, transfers, income,
+/// governance and admin changes), newest first, at most MAX_PAGE_SIZE items.
+#[query]
+pub fn get_property_activity(property_id: PropertyId, limit: u64) -> Vec<ActivityItem> {
+    let limit = limit.min(MAX_PAGE_SIZE) as usize;
+    EVENTS.with(|events| {
+        events
+            .borrow()
             .iter()
-            .filter(|((_, u), _)| *u == user)
-            .map(|((pid, _), income)| {
-                let property_name = PROPERTIES.with(|props| props.borrow().get(pid).map(|p| p.name.clone()).unwrap_or_default());
-                RentalIncomeRecord {
-                    property_id: *pid,
-                    property_name,
-                    income: *income,
-                }
-            })
+            .rev()
+            .filter(|e| e.property_id == Some(property_id))
+            .take(limit)
+            .map(|e| ActivityItem { timestamp: e.timestamp, event_type: e.event_type.clone(), actor: e.actor, details: e.details.clone() })
             .collect()
     })
 }
 
+/// Admin-only tail of the audit log: the last `n` events (at most
+/// MAX_PAGE_SIZE), oldest first.
 #[query]
-pub fn get_recent_events(n: u64) -> Vec<Event> {
-    EVENTS.with(|events| {
+pub fn get_recent_events(n: u64) -> Result<Vec<Event>, String> {
+    if get_role(&caller()) != Role::Admin {
+        return Err("Only admin can view the audit log".to_string());
+    }
+    let n = n.min(MAX_PAGE_SIZE) as usize;
+    Ok(EVENTS.with(|events| {
         let events = events.borrow();
-        let len = events.len();
-        let start = if len > n as usize { len - n as usize } else { 0 };
+        let start = events.len().saturating_sub(n);
         events[start..].to_vec()
-    })
+    }))
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,152 @@
+use super::*;
+
+#[test]
+fn audit_log_is_admin_only() {
+    setup();
+    new_property(100);
+    set_caller(user(9));
+    assert_eq!(get_events(0, 10).err(), Some("Only admin can view the audit log".to_string()));
+    assert_eq!(get_recent_events(10).err(), Some("Only admin can view the audit log".to_string()));
+
+    set_caller(admin());
+    let recent = get_recent_events(1).unwrap();
+    assert_eq!(recent.len(), 1);
+    assert!(recent[0].event_type == EventType::PropertyRegistered);
+    assert_eq!(get_events(0, 10).unwrap().len(), 2);
+}
+
+#[test]
+fn operations_are_logged_in_order_with_their_actors() {
+    setup();
+    let alice = holder(2);
+    let bob = holder(3);
+    let logged_before = get_events(0, 100).unwrap().len();
+    let property_id = new_property(100);
+    issue(property_id, alice, 40);
+    set_caller(alice);
+    transfer_shares(property_id, alice, bob, 10).unwrap();
+    set_caller(admin());
+    deposit_rental_income(property_id, 100).unwrap();
+    set_caller(bob);
+    claim_income(property_id).unwrap();
+
+    set_caller(admin());
+    let events = get_events(0, 100).unwrap();
+    let logged: Vec<(EventType, Principal)> = events[logged_before..].iter().map(|e| (e.event_type.clone(), e.actor)).collect();
+    assert!(
+        logged
+            == vec![
+                (EventType::PropertyRegistered, admin()),
+                (EventType::SharesIssued, admin()),
+                (EventType::SharesTransferred, alice),
+                (EventType::RentalIncomeDeposited, admin()),
+                (EventType::RentalIncomeClaimed, bob),
+            ]
+    );
+    assert!(events.windows(2).all(|pair| pair[0].id < pair[1].id && pair[0].timestamp <= pair[1].timestamp));
+    assert!(events[logged_before..].iter().all(|e| e.property_id == Some(property_id)));
+}
+
+#[test]
+fn cycles_turn_unhealthy_below_the_threshold() {
+    setup();
+    env::set_canister_balance(5_000);
+    set_low_cycle_threshold(1_000).unwrap();
+    let status = cycles_status().unwrap();
+    assert_eq!((status.balance, status.threshold, status.healthy), (5_000, 1_000, true));
+    env::set_canister_balance(1_000);
+    assert!(cycles_status().unwrap().healthy);
+    env::set_canister_balance(999);
+    assert!(!cycles_status().unwrap().healthy);
+    assert_eq!(get_cycle_balance(), Ok(999));
+
+    set_caller(user(2));
+    assert_eq!(get_cycle_balance(), Err("Only admin can view the cycle balance".to_string()));
+    assert!(cycles_status().is_err());
+    assert_eq!(set_low_cycle_threshold(0), Err("Only admin can set the cycle threshold".to_string()));
+}
+
+#[test]
+fn statistics_count_across_the_canister() {
+    setup();
+    let (alice, bob, carol) = (holder(2), holder(3), holder(4));
+    let first = new_property(100);
+    let second = new_property(1_000);
+    new_property(10);
+    issue(first, alice, 40);
+    issue(first, bob, 10);
+    issue(second, alice, 200);
+    issue(second, carol, 100);
+    set_caller(admin());
+    deposit_rental_income(first, 100).unwrap();
+    deposit_rental_income(second, 1_000).unwrap();
+    set_caller(alice);
+    claim_income(first).unwrap();
+    submit_proposal(first, "Repaint".to_string(), None, 60).unwrap();
+    let cancelled = submit_proposal(second, "Sell".to_string(), None, 60).unwrap().id;
+    cancel_proposal(cancelled).unwrap();
+    list(second, carol, 50, 3);
+    list(first, bob, 5, 3);
+
+    let stats = get_statistics();
+    assert_eq!((stats.total_properties, stats.total_shares_issued, stats.total_holders), (3, 350, 3));
+    // alice claimed her 40 from the first property; bob's 10 and the second's 200 + 100 remain
+    assert_eq!((stats.total_income_deposited, stats.total_unclaimed_income), (1_100, 10 + 200 + 100));
+    assert_eq!((stats.open_proposals, stats.marketplace_listings), (1, 2));
+}
+
+#[test]
+fn subscribers_are_notified_of_income_deposits() {
+    setup();
+    let alice = holder(2);
+    let property_id = new_property(100);
+    issue(property_id, alice, 50);
+    let (income_watcher, vote_watcher) = (user(50), user(51));
+    set_caller(admin());
+    assert_eq!(register_notifier(income_watcher, vec![]), Err("Notifier must subscribe to at least one event type".to_string()));
+    register_notifier(income_watcher, vec![EventType::RentalIncomeDeposited, EventType::RentalIncomeDeposited]).unwrap();
+    register_notifier(vote_watcher, vec![EventType::ProposalVoted]).unwrap();
+    deposit_rental_income(property_id, 100).unwrap();
+
+    let sent = env::notifications();
+    assert_eq!(sent.len(), 1);
+    let (canister, method, event) = &sent[0];
+    assert_eq!((*canister, method.as_str()), (income_watcher, "on_rwa_event"));
+    assert!(event.event_type == EventType::RentalIncomeDeposited);
+    assert_eq!((event.actor, event.property_id), (admin(), Some(property_id)));
+    assert_eq!(event.details, format!("Deposited rental income 100 for property {} (50 to reserve)", property_id));
+    let logged = get_events(0, MAX_PAGE_SIZE).unwrap().pop().unwrap();
+    assert_eq!((logged.id, logged.timestamp), (event.id, event.timestamp));
+
+    set_caller(alice);
+    assert_eq!(register_notifier(alice, vec![EventType::ProposalVoted]), Err("Only admin can register notifiers".to_string()));
+}
+
+#[test]
+fn property_activity_is_newest_first_and_filtered() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    let other = new_property(100);
+    env::advance_secs(1);
+    issue(property_id, alice, 50);
+    issue(other, alice, 50);
+    env::advance_secs(1);
+    fund(bob, 100);
+    let listing_id = list(property_id, alice, 10, 2);
+    set_caller(bob);
+    buy_shares(listing_id, bob, 10).unwrap();
+    env::advance_secs(1);
+    set_caller(admin());
+    deposit_rental_income(property_id, 100).unwrap();
+    set_caller(alice);
+    submit_proposal(property_id, "Repaint".to_string(), None, 60).unwrap();
+
+    let activity = get_property_activity(property_id, 10);
+    let types: Vec<EventType> = activity.iter().map(|a| a.event_type.clone()).collect();
+    assert!(types == vec![EventType::ProposalSubmitted, EventType::RentalIncomeDeposited, EventType::SharesBought, EventType::SharesListedForSale, EventType::SharesIssued, EventType::PropertyRegistered]);
+    assert!(activity.windows(2).all(|pair| pair[0].timestamp >= pair[1].timestamp));
+    assert_eq!(activity[2].actor, bob);
+    assert_eq!(get_property_activity(property_id, 2).len(), 2);
+    assert_eq!(get_property_activity(other, 10).len(), 2);
+}
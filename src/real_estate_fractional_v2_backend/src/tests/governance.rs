@@ -0,0 +1,404 @@
+use super::*;
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Property with 1000 shares split 600/400 between two holders.
+fn two_holder_property() -> (PropertyId, Principal, Principal) {
+    setup();
+    let alice = holder(2);
+    let bob = holder(3);
+    let property_id = new_property(1_000);
+    issue(property_id, alice, 600);
+    issue(property_id, bob, 400);
+    (property_id, alice, bob)
+}
+
+fn propose(property_id: PropertyId, proposer: Principal, action: Option<ProposalAction>) -> u64 {
+    set_caller(proposer);
+    submit_proposal(property_id, "Test proposal".to_string(), action, DAY).unwrap().id
+}
+
+fn vote(proposal_id: u64, voter: Principal, choice: bool) {
+    set_caller(voter);
+    vote_on_proposal(proposal_id, choice).unwrap();
+}
+
+#[test]
+fn votes_after_the_deadline_are_rejected() {
+    let (property_id, alice, bob) = two_holder_property();
+    let id = propose(property_id, alice, None);
+    vote(id, alice, true);
+    env::advance_secs(DAY + 1);
+    set_caller(bob);
+    assert_eq!(vote_on_proposal(id, false), Err("Voting period ended".to_string()));
+}
+
+#[test]
+fn execution_waits_for_the_deadline() {
+    let (property_id, alice, _) = two_holder_property();
+    let id = propose(property_id, alice, Some(ProposalAction::UpdateStatus(PropertyStatus::Maintenance)));
+    vote(id, alice, true);
+    assert_eq!(execute_proposal(id), Err("Voting period not ended".to_string()));
+    assert_eq!(get_property(property_id).unwrap().status, PropertyStatus::Active);
+
+    env::advance_secs(DAY + 1);
+    assert_eq!(execute_proposal(id), Ok("Proposal approved and executed".to_string()));
+    assert_eq!(get_property(property_id).unwrap().status, PropertyStatus::Maintenance);
+    assert!(get_proposal(id).unwrap().status == ProposalStatus::Executed);
+}
+
+#[test]
+fn proposals_without_quorum_fail_and_close_as_rejected() {
+    let (property_id, alice, _) = two_holder_property();
+    set_caller(admin());
+    set_quorum_bps(7_000).unwrap();
+    let id = propose(property_id, alice, None);
+    vote(id, alice, true);
+    env::advance_secs(DAY + 1);
+    assert_eq!(execute_proposal(id), Err("Quorum not reached".to_string()));
+    assert!(get_proposal(id).unwrap().status == ProposalStatus::Rejected);
+    assert_eq!(execute_proposal(id), Err("Proposal already finalized".to_string()));
+}
+
+#[test]
+fn voting_then_delegating_cannot_count_shares_twice() {
+    let (property_id, alice, bob) = two_holder_property();
+    let delegate = user(9);
+    let id = propose(property_id, alice, None);
+    vote(id, bob, true);
+    set_caller(bob);
+    assert_eq!(delegate_vote(property_id, delegate), Err("Cannot delegate while your vote is counted on an open proposal".to_string()));
+    set_caller(delegate);
+    assert!(vote_on_proposal(id, true).is_err());
+    assert_eq!(get_proposal(id).unwrap().yes_votes, 400);
+}
+
+#[test]
+fn revoking_after_the_delegate_voted_cannot_count_shares_twice() {
+    let (property_id, alice, bob) = two_holder_property();
+    let delegate = user(9);
+    set_caller(bob);
+    delegate_vote(property_id, delegate).unwrap();
+    let id = propose(property_id, alice, None);
+    vote(id, delegate, true);
+    set_caller(bob);
+    assert_eq!(revoke_delegation(property_id), Err("Cannot revoke while your delegate's vote is counted on an open proposal".to_string()));
+    assert!(vote_on_proposal(id, true).is_err());
+    assert_eq!(get_proposal(id).unwrap().yes_votes, 400);
+
+    // Once voting closes the delegation can change again
+    env::advance_secs(DAY + 1);
+    assert_eq!(revoke_delegation(property_id), Ok("Delegation revoked".to_string()));
+}
+
+#[test]
+fn redelegating_after_the_delegate_voted_cannot_count_shares_twice() {
+    let (property_id, alice, bob) = two_holder_property();
+    let (first, second) = (user(8), user(9));
+    set_caller(bob);
+    delegate_vote(property_id, first).unwrap();
+    let id = propose(property_id, alice, None);
+    vote(id, first, true);
+    set_caller(bob);
+    assert_eq!(delegate_vote(property_id, second), Err("Cannot re-delegate while your delegate's vote is counted on an open proposal".to_string()));
+    assert_eq!(get_delegate(property_id, bob), Some(first));
+    set_caller(second);
+    assert!(vote_on_proposal(id, true).is_err());
+    assert_eq!(get_proposal(id).unwrap().yes_votes, 400);
+
+    env::advance_secs(DAY + 1);
+    set_caller(bob);
+    assert_eq!(delegate_vote(property_id, second), Ok("Vote delegated".to_string()));
+}
+
+#[test]
+fn snapshots_are_stored_apart_from_the_proposal() {
+    let (property_id, alice, bob) = two_holder_property();
+    let id = propose(property_id, alice, None);
+    assert_eq!(get_snapshot_shares(id, alice), 600);
+    assert_eq!(get_snapshot_shares(id, bob), 400);
+    assert_eq!(get_snapshot_shares(id, user(9)), 0);
+}
+
+#[test]
+fn quorum_is_measured_against_total_shares() {
+    let (property_id, alice, bob) = two_holder_property();
+    set_caller(admin());
+    set_quorum_bps(5_000).unwrap();
+    assert_eq!(get_quorum_bps(), 5_000);
+
+    let short = propose(property_id, bob, None);
+    vote(short, bob, true);
+    let met = propose(property_id, alice, None);
+    vote(met, alice, true);
+    env::advance_secs(DAY + 1);
+    assert_eq!(execute_proposal(short), Err("Quorum not reached".to_string()));
+    assert_eq!(execute_proposal(met), Ok("Proposal approved and executed".to_string()));
+}
+
+#[test]
+fn only_admins_set_the_quorum() {
+    let (_, alice, _) = two_holder_property();
+    set_caller(alice);
+    assert!(set_quorum_bps(0).is_err());
+    set_caller(admin());
+    assert!(set_quorum_bps(10_001).is_err());
+    assert_eq!(get_quorum_bps(), 1_000);
+}
+
+/// Passes a proposal carrying `action` with alice's majority and executes it.
+fn pass(property_id: PropertyId, alice: Principal, action: ProposalAction) -> Result<String, String> {
+    let id = propose(property_id, alice, Some(action));
+    vote(id, alice, true);
+    env::advance_secs(DAY + 1);
+    execute_proposal(id)
+}
+
+#[test]
+fn executed_proposals_apply_their_action() {
+    let (property_id, alice, bob) = two_holder_property();
+    let mut updated = metadata();
+    updated.city = "Dallas".to_string();
+    pass(property_id, alice, ProposalAction::UpdateMetadata(updated)).unwrap();
+    assert_eq!(get_property(property_id).unwrap().metadata.city, "Dallas");
+
+    set_caller(admin());
+    deposit_reserve(property_id, 500).unwrap();
+    pass(property_id, alice, ProposalAction::DistributeReserve(500)).unwrap();
+    assert_eq!(get_reserve(property_id), 0);
+    assert_eq!(get_unclaimed_income(property_id, alice), 300);
+    assert_eq!(get_unclaimed_income(property_id, bob), 200);
+}
+
+#[test]
+fn failed_actions_leave_the_proposal_open() {
+    let (property_id, alice, _) = two_holder_property();
+    let id = propose(property_id, alice, Some(ProposalAction::DistributeReserve(500)));
+    vote(id, alice, true);
+    env::advance_secs(DAY + 1);
+    assert_eq!(execute_proposal(id), Err("Insufficient reserve".to_string()));
+    assert!(get_proposal(id).unwrap().status == ProposalStatus::Open);
+    set_caller(admin());
+    deposit_reserve(property_id, 500).unwrap();
+    assert_eq!(execute_proposal(id), Ok("Proposal approved and executed".to_string()));
+}
+
+#[test]
+fn a_repeat_vote_replaces_the_earlier_one() {
+    let (property_id, alice, bob) = two_holder_property();
+    let id = propose(property_id, alice, None);
+    vote(id, alice, true);
+    vote(id, bob, true);
+    assert_eq!((get_proposal(id).unwrap().yes_votes, get_proposal(id).unwrap().no_votes), (1_000, 0));
+    vote(id, alice, false);
+    let proposal = get_proposal(id).unwrap();
+    assert_eq!((proposal.yes_votes, proposal.no_votes), (400, 600));
+    assert_eq!(proposal.votes.get(&alice), Some(&false));
+    // Voting the same way again changes nothing
+    vote(id, alice, false);
+    let proposal = get_proposal(id).unwrap();
+    assert_eq!((proposal.yes_votes, proposal.no_votes), (400, 600));
+}
+
+#[test]
+fn proposals_are_fetched_by_id() {
+    let (property_id, alice, _) = two_holder_property();
+    let id = propose(property_id, alice, None);
+    let proposal = get_proposal(id).unwrap();
+    assert_eq!((proposal.id, proposal.property_id), (id, property_id));
+    assert!(get_proposal(id + 1).is_none());
+}
+
+#[test]
+fn proposal_pages_filter_by_property_and_status() {
+    let (first, alice, _) = two_holder_property();
+    let second = new_property(100);
+    issue(second, alice, 100);
+    let a = propose(first, alice, None);
+    let b = propose(second, alice, None);
+    let c = propose(first, alice, None);
+    set_caller(alice);
+    cancel_proposal(b).unwrap();
+    let ids = |page: ProposalPage| (page.total, page.proposals.iter().map(|p| p.id).collect::<Vec<_>>());
+
+    assert_eq!(ids(get_proposals_filtered(None, None, 0, 10)), (3, vec![a, b, c]));
+    assert_eq!(ids(get_proposals_filtered(None, Some(ProposalStatus::Open), 0, 10)), (2, vec![a, c]));
+    assert_eq!(ids(get_proposals_filtered(Some(second), None, 0, 10)), (1, vec![b]));
+    assert_eq!(ids(get_proposals_filtered(Some(second), Some(ProposalStatus::Open), 0, 10)), (0, vec![]));
+    assert_eq!(ids(get_proposals_filtered(None, None, 1, 1)), (3, vec![b]));
+}
+
+#[test]
+fn holders_can_vote_to_pause_transfers() {
+    let (property_id, alice, bob) = two_holder_property();
+    fund(alice, 100);
+    let listing_id = list(property_id, bob, 10, 1);
+    pass(property_id, alice, ProposalAction::SetTransfersPaused(true)).unwrap();
+    assert!(get_property(property_id).unwrap().transfers_paused);
+    let paused = Err("Transfers are paused for this property".to_string());
+
+    set_caller(alice);
+    assert_eq!(transfer_shares(property_id, alice, bob, 10), paused);
+    assert_eq!(buy_shares(listing_id, alice, 10), paused);
+    pass(property_id, alice, ProposalAction::SetTransfersPaused(false)).unwrap();
+    set_caller(alice);
+    transfer_shares(property_id, alice, bob, 10).unwrap();
+    assert_eq!(get_ownership(property_id, bob), 410);
+}
+
+#[test]
+fn only_holders_comment_on_proposals() {
+    let (property_id, alice, bob) = two_holder_property();
+    let id = propose(property_id, alice, None);
+    set_caller(bob);
+    add_comment(id, "  Sounds good ".to_string()).unwrap();
+    set_caller(user(9));
+    assert_eq!(add_comment(id, "Spam".to_string()), Err("Only holders of the property can comment".to_string()));
+    set_caller(bob);
+    assert_eq!(add_comment(id, " ".to_string()), Err("Comment cannot be empty".to_string()));
+
+    let comments: Vec<(Principal, u64, String)> = get_comments(id).into_iter().map(|c| (c.author, c.timestamp, c.text)).collect();
+    assert_eq!(comments, vec![(bob, env::now(), "Sounds good".to_string())]);
+    assert!(get_comments(id + 1).is_empty());
+}
+
+#[test]
+fn sensitive_actions_need_a_supermajority() {
+    let (property_id, alice, bob) = two_holder_property();
+    let normal = propose(property_id, alice, Some(ProposalAction::SetTransfersPaused(true)));
+    let sensitive = propose(property_id, alice, Some(ProposalAction::UpdateStatus(PropertyStatus::Sold)));
+    for id in [normal, sensitive] {
+        vote(id, alice, true);
+        vote(id, bob, false);
+    }
+    assert_eq!(get_approval_threshold_bps(ProposalKind::SetTransfersPaused), 5000);
+    assert_eq!(get_approval_threshold_bps(ProposalKind::UpdateStatus), 6667);
+    env::advance_secs(DAY + 1);
+    // 60% yes
+    assert_eq!(execute_proposal(normal), Ok("Proposal approved and executed".to_string()));
+    assert_eq!(execute_proposal(sensitive), Ok("Proposal rejected".to_string()));
+    assert_eq!(get_property(property_id).unwrap().status, PropertyStatus::Active);
+
+    set_caller(admin());
+    assert_eq!(set_approval_threshold_bps(ProposalKind::Signal, 4999), Err("Approval threshold must be between 5000 and 10000 bps".to_string()));
+    set_approval_threshold_bps(ProposalKind::SetTransfersPaused, 7000).unwrap();
+    let raised = propose(property_id, alice, Some(ProposalAction::SetTransfersPaused(false)));
+    vote(raised, alice, true);
+    vote(raised, bob, false);
+    env::advance_secs(DAY + 1);
+    assert_eq!(execute_proposal(raised), Ok("Proposal rejected".to_string()));
+}
+
+#[test]
+fn proposers_and_admins_can_cancel_open_proposals() {
+    let (property_id, alice, bob) = two_holder_property();
+    let id = propose(property_id, alice, None);
+    set_caller(bob);
+    assert_eq!(cancel_proposal(id), Err("Only the proposer or an admin can cancel a proposal".to_string()));
+    set_caller(alice);
+    assert_eq!(cancel_proposal(id), Ok("Proposal cancelled".to_string()));
+    assert!(get_proposal(id).unwrap().status == ProposalStatus::Cancelled);
+    assert_eq!(cancel_proposal(id), Err("Only open proposals can be cancelled".to_string()));
+    set_caller(bob);
+    assert!(vote_on_proposal(id, true).is_err());
+    env::advance_secs(DAY + 1);
+    assert!(execute_proposal(id).is_err());
+    assert!(get_proposal(id).unwrap().status == ProposalStatus::Cancelled);
+
+    let other = propose(property_id, alice, None);
+    set_caller(admin());
+    cancel_proposal(other).unwrap();
+    assert_eq!(cancel_proposal(other + 1), Err("Proposal not found".to_string()));
+}
+
+#[test]
+fn proposing_needs_a_minimum_stake() {
+    setup();
+    let (small, enough) = (holder(2), holder(3));
+    let property_id = new_property(1_000);
+    issue(property_id, small, 9);
+    issue(property_id, enough, 10);
+    let insufficient = Some("Insufficient stake to propose".to_string());
+
+    // The default minimum is 1% of total shares
+    set_caller(small);
+    assert_eq!(submit_proposal(property_id, "Too small".to_string(), None, DAY).err(), insufficient);
+    set_caller(user(9));
+    assert_eq!(submit_proposal(property_id, "No shares".to_string(), None, DAY).err(), insufficient);
+    assert_eq!(submit_proposal(property_id + 1, "Nowhere".to_string(), None, DAY).err(), Some("Property not found".to_string()));
+    propose(property_id, enough, None);
+
+    set_caller(admin());
+    set_min_proposal_bps(200).unwrap();
+    set_caller(enough);
+    assert_eq!(submit_proposal(property_id, "Now too small".to_string(), None, DAY).err(), insufficient);
+}
+
+#[test]
+fn turnout_is_measured_against_total_shares() {
+    setup();
+    let (alice, bob, carol) = (holder(2), holder(3), holder(4));
+    let property_id = new_property(1_000);
+    issue(property_id, alice, 300);
+    issue(property_id, bob, 125);
+    issue(property_id, carol, 200);
+    let id = propose(property_id, alice, None);
+    vote(id, alice, true);
+    vote(id, bob, false);
+    let stats = get_proposal_stats(id).unwrap();
+    assert_eq!((stats.yes_votes, stats.no_votes, stats.participating_shares, stats.total_shares), (300, 125, 425, 1_000));
+    // 425 of 1000 shares voted, unissued shares included
+    assert_eq!((stats.turnout_bps, stats.voter_count), (4_250, 2));
+    vote(id, carol, true);
+    let stats = get_proposal_stats(id).unwrap();
+    assert_eq!((stats.turnout_bps, stats.voter_count), (6_250, 3));
+    assert!(get_proposal_stats(id + 1).is_none());
+}
+
+#[test]
+fn holders_can_rename_their_property() {
+    let (property_id, alice, _) = two_holder_property();
+    pass(property_id, alice, ProposalAction::Rename("Harbor View".to_string())).unwrap();
+    assert_eq!(get_property(property_id).unwrap().name, "Harbor View");
+    assert_eq!(pass(property_id, alice, ProposalAction::Rename(" ".to_string())), Err("Property name cannot be empty".to_string()));
+}
+
+#[test]
+fn vote_breakdown_lists_heaviest_voters_first() {
+    setup();
+    let (alice, bob, carol) = (holder(2), holder(3), holder(4));
+    let property_id = new_property(1_000);
+    issue(property_id, alice, 100);
+    issue(property_id, bob, 500);
+    issue(property_id, carol, 250);
+    let id = propose(property_id, alice, None);
+    vote(id, alice, true);
+    vote(id, bob, false);
+    vote(id, carol, true);
+    // Weights come from the snapshot, not later holdings
+    set_caller(bob);
+    transfer_shares(property_id, bob, alice, 400).unwrap();
+
+    let breakdown: Vec<(Principal, bool, u64)> = get_proposal_votes(id).iter().map(|v| (v.voter, v.choice, v.weight)).collect();
+    assert_eq!(breakdown, vec![(bob, false, 500), (carol, true, 250), (alice, true, 100)]);
+    let total: u64 = breakdown.iter().map(|(_, _, weight)| weight).sum();
+    let proposal = get_proposal(id).unwrap();
+    assert_eq!(total, proposal.yes_votes + proposal.no_votes);
+    assert!(get_proposal_votes(id + 1).is_empty());
+}
+
+#[test]
+fn executing_twice_is_told_apart_from_an_unknown_id() {
+    let (property_id, alice, _) = two_holder_property();
+    let id = propose(property_id, alice, None);
+    vote(id, alice, true);
+    env::advance_secs(DAY + 1);
+    assert_eq!(execute_proposal(id), Ok("Proposal approved and executed".to_string()));
+    assert_eq!(execute_proposal(id), Err("Proposal already finalized".to_string()));
+    assert_eq!(execute_proposal(id + 1), Err("Proposal not found".to_string()));
+
+    let cancelled = propose(property_id, alice, None);
+    set_caller(alice);
+    cancel_proposal(cancelled).unwrap();
+    assert_eq!(execute_proposal(cancelled), Err("Proposal already finalized".to_string()));
+}
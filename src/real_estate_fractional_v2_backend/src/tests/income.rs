@@ -0,0 +1,621 @@
+use super::*;
+
+fn deposit(property_id: PropertyId, amount: u64) {
+    set_caller(admin());
+    deposit_rental_income(property_id, amount).unwrap();
+}
+
+fn claim(property_id: PropertyId, holder: Principal) -> u64 {
+    set_caller(holder);
+    claim_income(property_id).unwrap()
+}
+
+/// Deposited income is either distributed to holders or sits in the reserve.
+fn assert_income_accounted(property_id: PropertyId) {
+    let deposited = get_property_income_summary(property_id).total_deposited;
+    assert_eq!(deposited, get_distributed_income(property_id) + get_reserve(property_id));
+}
+
+#[test]
+fn accumulator_matches_an_eager_split_across_deposits_and_transfers() {
+    setup();
+    let alice = holder(2);
+    let bob = holder(3);
+    let property_id = new_property(1_000);
+    issue(property_id, alice, 333);
+    issue(property_id, bob, 500);
+    // The eager model: every deposit immediately credits each holder
+    // `amount * shares / total`, kept exact as a numerator over 1_000.
+    let mut owed = [0u64; 2];
+    let mut shares = [333u64, 500];
+    for (amount, moved) in [(10, 0), (7, 100), (1, 0), (25, 250), (3, 0)] {
+        if moved > 0 {
+            set_caller(bob);
+            transfer_shares(property_id, bob, alice, moved).unwrap();
+            shares = [shares[0] + moved, shares[1] - moved];
+        }
+        deposit(property_id, amount);
+        for (owed, shares) in owed.iter_mut().zip(shares) {
+            *owed += amount * shares;
+        }
+        assert_eq!(get_unclaimed_income(property_id, alice), owed[0] / 1_000);
+        assert_eq!(get_unclaimed_income(property_id, bob), owed[1] / 1_000);
+        assert_income_accounted(property_id);
+    }
+    assert_eq!(claim(property_id, alice), owed[0] / 1_000);
+    assert_eq!(claim(property_id, bob), owed[1] / 1_000);
+    assert!(get_distributed_income(property_id) >= owed[0] / 1_000 + owed[1] / 1_000);
+}
+
+#[test]
+fn sub_unit_accrual_is_paid_once_it_adds_up() {
+    setup();
+    let alice = holder(2);
+    let bob = holder(3);
+    let property_id = new_property(2);
+    issue(property_id, alice, 1);
+    issue(property_id, bob, 1);
+    deposit(property_id, 1);
+    assert_eq!(claim(property_id, alice), 0);
+    assert_eq!(claim(property_id, bob), 0);
+    assert_eq!(get_distributed_income(property_id), 1);
+    assert_income_accounted(property_id);
+    deposit(property_id, 1);
+    assert_eq!(claim(property_id, alice), 1);
+    assert_eq!(claim(property_id, bob), 1);
+    assert_eq!(get_distributed_income(property_id), 2);
+}
+
+#[test]
+fn unissued_share_fractions_are_not_paid_twice() {
+    setup();
+    let alice = holder(2);
+    let bob = holder(3);
+    let property_id = new_property(4);
+    issue(property_id, alice, 1);
+    issue(property_id, bob, 1);
+    // Each deposit leaves the holders half a unit between them
+    for _ in 0..4 {
+        deposit(property_id, 1);
+        assert_income_accounted(property_id);
+    }
+    assert_eq!(claim(property_id, alice), 1);
+    assert_eq!(claim(property_id, bob), 1);
+    assert_eq!(get_distributed_income(property_id), 2);
+    assert_eq!(get_reserve(property_id), 2);
+}
+
+/// 1000 shares split into 200 preferred taking 60% of income and 800 common.
+fn two_class_property() -> PropertyId {
+    let property_id = new_property(1_000);
+    let classes = vec![
+        ShareClass { name: "Preferred".to_string(), total: 200, income_weight_bps: 6_000 },
+        ShareClass { name: "Common".to_string(), total: 800, income_weight_bps: 4_000 },
+    ];
+    set_share_classes(property_id, classes).unwrap();
+    property_id
+}
+
+#[test]
+fn income_splits_by_class_weight_then_within_each_class() {
+    setup();
+    let alice = holder(2);
+    let bob = holder(3);
+    let carol = holder(4);
+    let property_id = two_class_property();
+    issue_class_shares(property_id, 0, alice, 200).unwrap();
+    issue_class_shares(property_id, 1, bob, 600).unwrap();
+    issue_class_shares(property_id, 1, carol, 200).unwrap();
+    deposit(property_id, 10_000);
+    assert_eq!(get_unclaimed_income(property_id, alice), 6_000);
+    assert_eq!(get_unclaimed_income(property_id, bob), 3_000);
+    assert_eq!(get_unclaimed_income(property_id, carol), 1_000);
+    assert_eq!(get_distributed_income(property_id), 10_000);
+}
+
+#[test]
+fn class_transfers_are_logged_and_charged_the_transfer_fee() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    let property_id = two_class_property();
+    issue_class_shares(property_id, 1, alice, 400).unwrap();
+    set_transfer_fee_bps(500).unwrap();
+
+    set_caller(alice);
+    assert_eq!(transfer_class_shares(property_id, 1, bob, 100), Ok("Shares transferred".to_string()));
+    let record = get_transfer(0).unwrap();
+    assert_eq!((record.from, record.to, record.amount, record.fee, record.class_index), (alice, bob, 100, 5, Some(1)));
+    assert_eq!(get_class_ownership(property_id, 1, bob), 95);
+    assert_eq!(get_ownership(property_id, bob), 95);
+    // The withheld fee goes back to the class's unissued pool
+    assert_eq!(get_property(property_id).unwrap().shares_available, 1_000 - 400 + 5);
+    set_caller(admin());
+    issue_class_shares(property_id, 1, alice, 405).unwrap();
+    assert!(issue_class_shares(property_id, 1, alice, 1).is_err());
+
+    // Classed properties can't use the generic transfer path
+    set_caller(alice);
+    assert_eq!(transfer_shares(property_id, alice, bob, 10), Err("Property has share classes; use the class-aware endpoints".to_string()));
+}
+
+#[test]
+fn unissued_class_shares_send_their_cut_to_the_reserve() {
+    setup();
+    let alice = holder(2);
+    let bob = holder(3);
+    let property_id = two_class_property();
+    issue_class_shares(property_id, 0, alice, 100).unwrap();
+    issue_class_shares(property_id, 1, bob, 800).unwrap();
+    deposit(property_id, 10_000);
+    assert_eq!(get_unclaimed_income(property_id, alice), 3_000);
+    assert_eq!(get_unclaimed_income(property_id, bob), 4_000);
+    assert_eq!(get_reserve(property_id), 3_000);
+    // Moving class shares keeps the issued count, so the split is unchanged
+    set_caller(bob);
+    transfer_class_shares(property_id, 1, alice, 400).unwrap();
+    deposit(property_id, 10_000);
+    assert_eq!(get_unclaimed_income(property_id, alice), 3_000 + 3_000 + 2_000);
+    assert_eq!(get_unclaimed_income(property_id, bob), 4_000 + 2_000);
+    assert_eq!(get_reserve(property_id), 6_000);
+    assert_income_accounted(property_id);
+}
+
+/// 10 shares with one and two issued, then a deposit of 15: alice is owed
+/// 1.5, bob 3.0 and unissued shares 10.5, leaving one unit of dust.
+fn rounding_case(mode: RoundingMode) -> (PropertyId, Principal, Principal) {
+    setup();
+    let alice = holder(2);
+    let bob = holder(3);
+    let property_id = new_property(10);
+    issue(property_id, alice, 1);
+    issue(property_id, bob, 2);
+    set_caller(admin());
+    set_rounding_mode(mode).unwrap();
+    deposit(property_id, 15);
+    assert_income_accounted_with_carry(property_id);
+    (property_id, alice, bob)
+}
+
+fn assert_income_accounted_with_carry(property_id: PropertyId) {
+    let deposited = get_property_income_summary(property_id).total_deposited;
+    assert_eq!(deposited, get_distributed_income(property_id) + get_reserve(property_id) + carried_income(property_id));
+}
+
+#[test]
+fn floor_rounds_down_and_carries_the_dust() {
+    let (property_id, alice, bob) = rounding_case(RoundingMode::Floor);
+    assert_eq!(get_unclaimed_income(property_id, alice), 1);
+    assert_eq!(get_unclaimed_income(property_id, bob), 3);
+    assert_eq!(get_reserve(property_id), 10);
+    assert_eq!(carried_income(property_id), 1);
+}
+
+#[test]
+fn reserve_remainder_rounds_down_and_reserves_the_dust() {
+    let (property_id, alice, bob) = rounding_case(RoundingMode::ReserveRemainder);
+    assert_eq!(get_unclaimed_income(property_id, alice), 1);
+    assert_eq!(get_unclaimed_income(property_id, bob), 3);
+    assert_eq!(get_reserve(property_id), 11);
+    assert_eq!(carried_income(property_id), 0);
+}
+
+#[test]
+fn half_up_rounds_to_nearest_from_the_deposits_dust() {
+    let (property_id, alice, bob) = rounding_case(RoundingMode::HalfUp);
+    assert_eq!(get_unclaimed_income(property_id, alice), 2);
+    assert_eq!(get_unclaimed_income(property_id, bob), 3);
+    assert_eq!(get_reserve(property_id), 10);
+    assert_eq!(get_distributed_income(property_id), 5);
+}
+
+#[test]
+fn preview_matches_each_rounding_mode() {
+    for (mode, alice_share, remainder) in [(RoundingMode::Floor, 1, 11), (RoundingMode::ReserveRemainder, 1, 11), (RoundingMode::HalfUp, 2, 10)] {
+        setup();
+        let alice = holder(2);
+        let bob = holder(3);
+        let property_id = new_property(10);
+        issue(property_id, alice, 1);
+        issue(property_id, bob, 2);
+        set_caller(admin());
+        set_rounding_mode(mode).unwrap();
+        let preview = preview_income_distribution(property_id, 15).unwrap();
+        assert_eq!(preview.allocations, vec![(alice, alice_share), (bob, 3)], "{:?}", mode);
+        assert_eq!(preview.remainder, remainder, "{:?}", mode);
+    }
+}
+
+#[test]
+fn half_up_never_draws_on_the_reserve() {
+    setup();
+    let alice = holder(2);
+    let bob = holder(3);
+    let property_id = new_property(2);
+    issue(property_id, alice, 1);
+    issue(property_id, bob, 1);
+    set_caller(admin());
+    set_rounding_mode(RoundingMode::HalfUp).unwrap();
+    deposit_reserve(property_id, 100).unwrap();
+    // Both halves round up only if something pays for the second unit
+    deposit(property_id, 1);
+    assert_eq!(claim(property_id, alice) + claim(property_id, bob), 0);
+    assert_eq!(get_reserve(property_id), 100);
+    deposit(property_id, 1);
+    assert_eq!(claim(property_id, alice) + claim(property_id, bob), 2);
+    assert_eq!(get_reserve(property_id), 100);
+}
+
+#[test]
+fn half_up_does_not_depend_on_when_holders_settle() {
+    setup();
+    let alice = holder(2);
+    set_caller(admin());
+    set_rounding_mode(RoundingMode::HalfUp).unwrap();
+    let eager = new_property(10);
+    let lazy = new_property(10);
+    issue(eager, alice, 1);
+    issue(lazy, alice, 1);
+    let mut claimed = 0;
+    for amount in [15, 4, 15, 7] {
+        deposit(eager, amount);
+        deposit(lazy, amount);
+        claimed += claim(eager, alice);
+    }
+    assert_eq!(claim(lazy, alice), claimed);
+}
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// 1000 shares with 250 issued to alice and 250 to bob.
+fn windowed_property() -> (PropertyId, Principal, Principal) {
+    setup();
+    let alice = holder(2);
+    let bob = holder(3);
+    let property_id = new_property(1_000);
+    issue(property_id, alice, 250);
+    issue(property_id, bob, 250);
+    (property_id, alice, bob)
+}
+
+fn set_window(property_id: PropertyId, days: Option<u64>) {
+    set_caller(admin());
+    set_claim_window(property_id, days.map(|days| days * DAY)).unwrap();
+}
+
+#[test]
+fn income_can_be_claimed_before_its_window_ends() {
+    let (property_id, alice, _) = windowed_property();
+    set_window(property_id, Some(30));
+    deposit(property_id, 1_000);
+    env::advance_secs(29 * DAY);
+    assert_eq!(claim(property_id, alice), 250);
+}
+
+#[test]
+fn sweeping_moves_expired_income_to_the_reserve() {
+    let (property_id, alice, bob) = windowed_property();
+    set_window(property_id, Some(30));
+    deposit(property_id, 1_000);
+    assert_eq!(get_reserve(property_id), 500);
+    env::advance_secs(31 * DAY);
+    assert_eq!(get_unclaimed_income(property_id, alice), 0);
+    set_caller(admin());
+    assert_eq!(sweep_expired_income(property_id), Ok(500));
+    assert_eq!(get_reserve(property_id), 1_000);
+    assert_eq!(get_distributed_income(property_id), 0);
+    set_caller(alice);
+    assert_eq!(claim_income(property_id), Ok(0));
+    set_caller(bob);
+    assert_eq!(claim_income(property_id), Ok(0));
+}
+
+#[test]
+fn claims_after_expiry_are_rejected() {
+    let (property_id, alice, _) = windowed_property();
+    set_window(property_id, Some(30));
+    deposit(property_id, 1_000);
+    env::advance_secs(31 * DAY);
+    set_caller(alice);
+    assert_eq!(claim_income(property_id), Err("Unclaimed income has expired".to_string()));
+    assert_eq!(get_reserve(property_id), 750);
+}
+
+#[test]
+fn income_from_before_the_window_never_expires() {
+    let (property_id, alice, bob) = windowed_property();
+    deposit(property_id, 400);
+    set_window(property_id, Some(30));
+    deposit(property_id, 1_000);
+    // Settling both deposits together must still tell them apart
+    set_caller(bob);
+    transfer_shares(property_id, bob, alice, 50).unwrap();
+    env::advance_secs(31 * DAY);
+    assert_eq!(get_unclaimed_income(property_id, alice), 100);
+    assert_eq!(get_unclaimed_income(property_id, bob), 100);
+    set_caller(admin());
+    assert_eq!(sweep_expired_income(property_id), Ok(500));
+    assert_eq!(claim(property_id, alice), 100);
+    assert_eq!(claim(property_id, bob), 100);
+    assert_income_accounted(property_id);
+}
+
+#[test]
+fn income_after_the_window_is_removed_never_expires() {
+    let (property_id, alice, _) = windowed_property();
+    set_window(property_id, Some(30));
+    deposit(property_id, 1_000);
+    set_window(property_id, None);
+    deposit(property_id, 400);
+    env::advance_secs(31 * DAY);
+    assert_eq!(get_unclaimed_income(property_id, alice), 100);
+    deposit(property_id, 400);
+    assert_eq!(claim(property_id, alice), 200);
+}
+
+#[test]
+fn large_deposits_and_holdings_do_not_wrap() {
+    setup();
+    let alice = holder(2);
+    let bob = holder(3);
+    let half = u64::MAX / 2;
+    let property_id = new_property(half);
+    issue(property_id, alice, half / 4 * 3);
+    issue(property_id, bob, half - half / 4 * 3);
+    deposit(property_id, half);
+    let alice_income = get_unclaimed_income(property_id, alice);
+    let bob_income = get_unclaimed_income(property_id, bob);
+    // Exact three quarters and one quarter, give or take rounding
+    assert!(alice_income.abs_diff(half / 4 * 3) <= 1, "{}", alice_income);
+    assert!(bob_income.abs_diff(half - half / 4 * 3) <= 1, "{}", bob_income);
+    assert!(alice_income + bob_income <= half);
+    set_caller(admin());
+    assert_eq!(deposit_rental_income(property_id, u64::MAX), Err("Rental income total overflow".to_string()));
+}
+
+#[test]
+fn claims_are_recorded_with_their_time() {
+    let (property_id, alice, _) = windowed_property();
+    deposit(property_id, 1_000);
+    let first_claim_at = now();
+    assert_eq!(claim(property_id, alice), 250);
+    env::advance_secs(60);
+    // Nothing new to claim the second time
+    assert_eq!(claim(property_id, alice), 0);
+    deposit(property_id, 400);
+    assert_eq!(claim(property_id, alice), 100);
+    assert_eq!(get_claimed_income(property_id, alice), 350);
+    let history = get_claim_history(alice);
+    assert_eq!(history.iter().map(|record| (record.property_id, record.amount)).collect::<Vec<_>>(), vec![(property_id, 250), (property_id, 100)]);
+    assert_eq!(history[0].timestamp, first_claim_at);
+    assert_eq!(history[1].timestamp, first_claim_at + 60 * 1_000_000_000);
+}
+
+#[test]
+fn holders_can_only_claim_their_own_income() {
+    let (property_id, alice, bob) = windowed_property();
+    deposit(property_id, 1_000);
+    let outsider = user(9);
+    assert_eq!(claim(property_id, outsider), 0);
+    assert_eq!(claim(property_id, alice), 250);
+    // Alice's claim left bob's income untouched
+    assert_eq!(get_unclaimed_income(property_id, bob), 250);
+    assert_eq!(claim(property_id, bob), 250);
+}
+
+#[test]
+fn only_admins_and_managers_deposit_income() {
+    let (property_id, alice, _) = windowed_property();
+    set_caller(alice);
+    assert_eq!(deposit_rental_income(property_id, 100), Err("Unauthorized to deposit income".to_string()));
+    assert_eq!(get_property_income_summary(property_id).total_deposited, 0);
+    set_caller(admin());
+    assert_eq!(deposit_rental_income(property_id, 100), Ok("Rental income distributed".to_string()));
+    assert_eq!(get_property_income_summary(property_id).total_deposited, 100);
+}
+
+#[test]
+fn income_follows_the_holdings_at_each_deposit() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    issue(property_id, alice, 60);
+    issue(property_id, bob, 40);
+    deposit(property_id, 100);
+
+    set_caller(alice);
+    transfer_shares(property_id, alice, bob, 30).unwrap();
+    assert_eq!(get_income_accrual_basis(property_id, alice), 30);
+    assert_eq!(get_income_accrual_basis(property_id, bob), 70);
+    // The transfer does not move income already earned
+    assert_eq!(get_unclaimed_income(property_id, alice), 60);
+    assert_eq!(get_unclaimed_income(property_id, bob), 40);
+
+    deposit(property_id, 100);
+    assert_eq!(get_unclaimed_income(property_id, alice), 60 + 30);
+    assert_eq!(get_unclaimed_income(property_id, bob), 40 + 70);
+    assert_income_accounted(property_id);
+}
+
+#[test]
+fn income_splits_over_base_units_with_decimals() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    // 10.00 shares: alice holds 2.50, bob 7.50
+    let property_id = register_property("Decimal".to_string(), from_display_units("10", 2).unwrap(), metadata(), None, 2).unwrap().id;
+    issue(property_id, alice, from_display_units("2.5", 2).unwrap());
+    issue(property_id, bob, from_display_units("7.50", 2).unwrap());
+    assert_eq!(to_display_units(get_ownership(property_id, alice), 2), "2.50");
+    deposit(property_id, 1_000);
+    assert_eq!(get_unclaimed_income(property_id, alice), 250);
+    assert_eq!(get_unclaimed_income(property_id, bob), 750);
+
+    assert_eq!(from_display_units("1.234", 2), Err("Too many decimal places".to_string()));
+    assert_eq!(from_display_units("1.2x", 2), Err("Invalid amount".to_string()));
+    assert_eq!(to_display_units(5, 2), "0.05");
+}
+
+#[test]
+fn reserves_are_spent_only_from_what_was_deposited() {
+    setup();
+    let property_id = new_property(100);
+    deposit_reserve(property_id, 500).unwrap();
+    assert_eq!(get_reserve(property_id), 500);
+    assert_eq!(spend_reserve(property_id, 501, "Roof".to_string()), Err("Insufficient reserve".to_string()));
+    assert_eq!(spend_reserve(property_id, 200, "Roof repair".to_string()), Ok("Reserve spent".to_string()));
+    assert_eq!(get_reserve(property_id), 300);
+    let last = get_events(0, MAX_PAGE_SIZE).unwrap().pop().unwrap();
+    assert!(last.event_type == EventType::ReserveSpent);
+    assert!(last.details.ends_with(": Roof repair"));
+
+    assert_eq!(deposit_reserve(property_id + 1, 1), Err("Property not found".to_string()));
+    set_caller(user(2));
+    assert_eq!(deposit_reserve(property_id, 1), Err("Unauthorized to manage reserves".to_string()));
+    assert_eq!(spend_reserve(property_id, 1, "Mine".to_string()), Err("Unauthorized to manage reserves".to_string()));
+}
+
+#[test]
+fn preview_matches_the_actual_deposit() {
+    setup();
+    let (alice, bob, carol) = (holder(2), holder(3), holder(4));
+    let property_id = new_property(1_000);
+    issue(property_id, alice, 333);
+    issue(property_id, bob, 500);
+    issue(property_id, carol, 7);
+    set_caller(admin());
+    let preview = preview_income_distribution(property_id, 100).unwrap();
+    assert_eq!(preview.allocations, vec![(alice, 33), (bob, 50), (carol, 0)]);
+    // The 160 unissued shares' cut; holders keep their sub-unit fractions
+    assert_eq!(preview.remainder, 16);
+    assert_eq!(preview_income_distribution(property_id + 1, 100).err(), Some("Property not found or has no shares".to_string()));
+
+    deposit(property_id, 100);
+    for (holder, amount) in &preview.allocations {
+        assert_eq!(get_unclaimed_income(property_id, *holder), *amount);
+    }
+    assert_eq!(get_reserve(property_id), preview.remainder);
+}
+
+#[test]
+fn uneven_deposits_account_for_every_unit() {
+    setup();
+    let (alice, bob, carol) = (holder(2), holder(3), holder(4));
+    let property_id = new_property(4);
+    issue(property_id, alice, 1);
+    issue(property_id, bob, 1);
+    issue(property_id, carol, 2);
+    deposit(property_id, 101);
+    assert_income_accounted(property_id);
+    assert_eq!([alice, bob, carol].map(|h| get_unclaimed_income(property_id, h)), [25, 25, 50]);
+    // The leftover quarters and half add up to whole units with the next deposit
+    deposit(property_id, 3);
+    assert_income_accounted(property_id);
+    assert_eq!([alice, bob, carol].map(|h| claim(property_id, h)), [26, 26, 52]);
+    assert_eq!(get_reserve(property_id), 0);
+}
+
+#[test]
+fn income_summary_after_a_partial_claim() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    issue(property_id, alice, 60);
+    issue(property_id, bob, 40);
+    deposit(property_id, 1_000);
+    claim(property_id, alice);
+    let summary = get_property_income_summary(property_id);
+    assert_eq!((summary.total_deposited, summary.total_unclaimed, summary.total_claimed), (1_000, 400, 600));
+    let empty = get_property_income_summary(property_id + 1);
+    assert_eq!((empty.total_deposited, empty.total_unclaimed, empty.total_claimed), (0, 0, 0));
+}
+
+#[test]
+fn claim_all_sweeps_only_the_callers_income() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    let properties = [new_property(100), new_property(100), new_property(100)];
+    for (i, property_id) in properties.iter().enumerate() {
+        issue(*property_id, alice, 50);
+        issue(*property_id, bob, 50);
+        deposit(*property_id, 100 * (i as u64 + 1));
+    }
+    set_caller(alice);
+    assert_eq!(claim_all_income(), Ok(vec![(properties[0], 50), (properties[1], 100), (properties[2], 150)]));
+    for property_id in properties {
+        assert_eq!(get_unclaimed_income(property_id, alice), 0);
+        assert_eq!(get_unclaimed_income(property_id, bob), get_property_income_summary(property_id).total_deposited / 2);
+    }
+    assert_eq!(claim_all_income(), Ok(vec![]));
+}
+
+#[test]
+fn scheduled_income_is_distributed_when_the_timer_fires() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    issue(property_id, alice, 25);
+    issue(property_id, bob, 75);
+    set_caller(admin());
+    assert_eq!(schedule_recurring_income(property_id, 0, 60), Err("Amount and interval must be positive".to_string()));
+    schedule_recurring_income(property_id, 400, 30 * DAY).unwrap();
+    assert_eq!(get_recurring_income(property_id).unwrap().amount, 400);
+    env::fire_timers();
+    assert_eq!(get_unclaimed_income(property_id, alice), 100);
+    assert_eq!(get_unclaimed_income(property_id, bob), 300);
+    env::fire_timers();
+    assert_eq!(get_property_income_summary(property_id).total_deposited, 800);
+
+    set_caller(admin());
+    cancel_recurring_income(property_id).unwrap();
+    env::fire_timers();
+    assert_eq!(get_property_income_summary(property_id).total_deposited, 800);
+    assert_eq!(cancel_recurring_income(property_id), Err("No recurring income scheduled".to_string()));
+}
+
+#[test]
+fn portfolio_totals_every_position() {
+    setup();
+    let alice = holder(2);
+    let first = new_property(100);
+    let second = new_property(1_000);
+    issue(first, alice, 40);
+    issue(second, alice, 250);
+    deposit(first, 500);
+    set_caller(admin());
+    record_valuation(first, 10_000).unwrap();
+    record_valuation(first, 20_000).unwrap();
+
+    let portfolio = get_portfolio(alice);
+    let positions: Vec<(PropertyId, u64, u64, u64)> = portfolio.positions.iter().map(|p| (p.property_id, p.shares, p.unclaimed_income, p.estimated_value)).collect();
+    // The second property was never valued
+    assert_eq!(positions, vec![(first, 40, 200, 8_000), (second, 250, 0, 0)]);
+    assert_eq!((portfolio.total_unclaimed_income, portfolio.total_estimated_value), (200, 8_000));
+    assert!(get_portfolio(user(9)).positions.is_empty());
+}
+
+#[test]
+fn every_ownership_change_settles_income_first() {
+    setup();
+    let (alice, bob, carol) = (holder(2), holder(3), holder(4));
+    let property_id = new_property(100);
+    issue(property_id, alice, 40);
+    issue(property_id, bob, 40);
+    deposit(property_id, 100);
+
+    set_caller(alice);
+    transfer_shares(property_id, alice, bob, 10).unwrap();
+    fund(carol, 100);
+    let listing_id = list(property_id, bob, 10, 1);
+    set_caller(carol);
+    buy_shares(listing_id, carol, 10).unwrap();
+    issue(property_id, carol, 10);
+    set_caller(alice);
+    burn_shares(property_id, 10, false).unwrap();
+    // alice 20, bob 40, carol 20, 20 unissued
+    deposit(property_id, 200);
+
+    assert_eq!(claim(property_id, alice), 40 + 40);
+    assert_eq!(claim(property_id, bob), 40 + 80);
+    assert_eq!(claim(property_id, carol), 40);
+    assert_eq!(get_reserve(property_id), 20 + 40);
+    assert_income_accounted(property_id);
+}
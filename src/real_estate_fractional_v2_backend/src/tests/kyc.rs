@@ -0,0 +1,175 @@
+use super::*;
+
+#[test]
+fn verified_buyers_can_buy_and_unverified_ones_cannot() {
+    setup();
+    let seller = holder(2);
+    let verified = holder(3);
+    let unverified = user(4);
+    let property_id = new_property(100);
+    issue(property_id, seller, 50);
+    fund(verified, 100);
+    fund(unverified, 100);
+    let listing_id = list(property_id, seller, 20, 1);
+
+    set_caller(unverified);
+    assert_eq!(buy_shares(listing_id, unverified, 10), Err("Recipient is not KYC verified".to_string()));
+    set_caller(verified);
+    assert_eq!(buy_shares(listing_id, verified, 10), Ok("Shares bought successfully".to_string()));
+    assert_eq!(get_ownership(property_id, verified), 10);
+    assert_eq!(get_ownership(property_id, unverified), 0);
+}
+
+#[test]
+fn unverified_principals_cannot_receive_shares() {
+    setup();
+    let alice = holder(2);
+    let unverified = user(4);
+    let property_id = new_property(100);
+    issue(property_id, alice, 50);
+    set_caller(admin());
+    assert_eq!(issue_shares(property_id, unverified, 10, None), Err("Recipient is not KYC verified".to_string()));
+    set_caller(alice);
+    assert_eq!(transfer_shares(property_id, alice, unverified, 10), Err("Recipient is not KYC verified".to_string()));
+}
+
+#[test]
+fn strict_kyc_can_be_turned_off() {
+    setup();
+    let alice = holder(2);
+    let unverified = user(4);
+    let property_id = new_property(100);
+    issue(property_id, alice, 50);
+    set_caller(user(5));
+    assert!(set_strict_kyc(false).is_err());
+    set_caller(admin());
+    set_strict_kyc(false).unwrap();
+    assert!(!is_strict_kyc_enabled());
+    set_caller(alice);
+    transfer_shares(property_id, alice, unverified, 10).unwrap();
+    assert_eq!(get_ownership(property_id, unverified), 10);
+}
+
+#[test]
+fn blacklisted_principals_can_neither_receive_nor_move_shares() {
+    setup();
+    let (alice, bob, carol) = (holder(2), holder(3), holder(4));
+    let property_id = new_property(100);
+    issue(property_id, alice, 20);
+    issue(property_id, bob, 20);
+    issue(property_id, carol, 20);
+    fund(alice, 100);
+    let listing_id = list(property_id, carol, 10, 1);
+    set_caller(admin());
+    deposit_rental_income(property_id, 100).unwrap();
+    set_blacklist(alice, true).unwrap();
+    assert!(is_blacklisted(&alice));
+    let blacklisted = Err("Principal is blacklisted".to_string());
+
+    assert_eq!(issue_shares(property_id, alice, 5, None), blacklisted);
+    set_caller(bob);
+    assert_eq!(transfer_shares(property_id, bob, alice, 5), blacklisted);
+    set_caller(alice);
+    assert_eq!(transfer_shares(property_id, alice, bob, 5), blacklisted);
+    assert_eq!(list_shares_for_sale(property_id, 5, 1, None, None), blacklisted);
+    assert_eq!(buy_shares(listing_id, alice, 5), blacklisted);
+    assert_eq!(claim_income(property_id), Err("Principal is blacklisted".to_string()));
+    assert_eq!(withdraw_funds(50), blacklisted);
+    assert_eq!(get_ownership(property_id, alice), 20);
+    assert_eq!(get_balance(alice), 100);
+
+    set_caller(admin());
+    set_blacklist(alice, false).unwrap();
+    set_caller(alice);
+    transfer_shares(property_id, alice, bob, 5).unwrap();
+    assert_eq!(claim_income(property_id), Ok(20));
+}
+
+#[test]
+fn blacklisted_holders_cannot_redeem_sale_proceeds() {
+    setup();
+    let alice = holder(2);
+    let property_id = new_property(100);
+    issue(property_id, alice, 50);
+    set_caller(admin());
+    update_property_status(property_id, PropertyStatus::Sold).unwrap();
+    set_sale_proceeds(property_id, 1_000).unwrap();
+    set_blacklist(alice, true).unwrap();
+
+    set_caller(alice);
+    assert_eq!(redeem_shares(property_id), Err("Principal is blacklisted".to_string()));
+    assert_eq!(get_ownership(property_id, alice), 50);
+    assert_eq!(get_balance(alice), 0);
+
+    set_caller(admin());
+    set_blacklist(alice, false).unwrap();
+    set_caller(alice);
+    assert_eq!(redeem_shares(property_id), Ok(500));
+}
+
+#[test]
+fn kyc_lapses_at_its_expiry() {
+    setup();
+    let alice = user(2);
+    let property_id = new_property(100);
+    let valid_until = env::now() + 60 * 1_000_000_000;
+    set_kyc_status(alice, true, Some(valid_until)).unwrap();
+    assert_eq!(get_kyc_expiry(alice), Some(valid_until));
+    assert!(is_kyc_verified(&alice));
+    issue_shares(property_id, alice, 10, None).unwrap();
+
+    env::advance_secs(60);
+    assert!(!is_kyc_verified(&alice));
+    assert_eq!(issue_shares(property_id, alice, 10, None), Err("Recipient is not KYC verified".to_string()));
+    // Grants without an expiry never lapse; revoking clears the record
+    set_kyc_status(alice, true, None).unwrap();
+    assert_eq!(get_kyc_expiry(alice), Some(u64::MAX));
+    set_kyc_status(alice, false, None).unwrap();
+    assert_eq!(get_kyc_expiry(alice), None);
+}
+
+#[test]
+fn batch_kyc_updates_apply_each_entry() {
+    setup();
+    let (alice, bob, carol) = (user(2), user(3), user(4));
+    set_kyc_status(bob, true, None).unwrap();
+    assert_eq!(set_kyc_status_batch(vec![(alice, true), (bob, false), (carol, false)]), Ok(2));
+    assert!(is_kyc_verified(&alice));
+    assert!(!is_kyc_verified(&bob));
+    assert!(!is_kyc_verified(&carol));
+    assert_eq!(set_kyc_status_batch(vec![(alice, true), (carol, true)]), Ok(1));
+    assert!(is_kyc_verified(&carol));
+
+    set_caller(alice);
+    assert_eq!(set_kyc_status_batch(vec![(bob, true)]), Err("Only admin can set KYC status".to_string()));
+    assert!(!is_kyc_verified(&bob));
+}
+
+#[test]
+fn private_offerings_admit_only_whitelisted_recipients() {
+    setup();
+    let (insider, outsider) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    // Public until the first whitelist entry
+    issue(property_id, outsider, 10);
+    add_to_whitelist(property_id, insider).unwrap();
+    let rejected = Err("Recipient is not whitelisted for this property".to_string());
+
+    assert_eq!(issue_shares(property_id, outsider, 5, None), rejected);
+    issue(property_id, insider, 20);
+    set_caller(insider);
+    assert_eq!(transfer_shares(property_id, insider, outsider, 5), rejected);
+    fund(outsider, 100);
+    let listing_id = list(property_id, insider, 5, 1);
+    set_caller(outsider);
+    assert_eq!(buy_shares(listing_id, outsider, 5), rejected);
+    // Holders who are not listed can still sell to those who are
+    transfer_shares(property_id, outsider, insider, 5).unwrap();
+
+    set_caller(admin());
+    remove_from_whitelist(property_id, insider).unwrap();
+    assert_eq!(issue_shares(property_id, insider, 1, None), rejected);
+    clear_whitelist(property_id).unwrap();
+    issue(property_id, outsider, 5);
+    assert_eq!(get_ownership(property_id, outsider), 10);
+}
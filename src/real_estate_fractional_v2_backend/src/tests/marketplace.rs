@@ -0,0 +1,407 @@
+use super::*;
+
+#[test]
+fn listings_are_always_for_the_callers_shares() {
+    setup();
+    let victim = holder(2);
+    let thief = holder(3);
+    let property_id = new_property(100);
+    issue(property_id, victim, 50);
+
+    // The thief holds nothing, so it cannot list anything, let alone the victim's shares
+    set_caller(thief);
+    assert_eq!(list_shares_for_sale(property_id, 50, 0, None, None), Err("Not enough shares to list".to_string()));
+
+    let listing_id = list(property_id, victim, 10, 5);
+    let listing = get_marketplace_listings().into_iter().find(|l| l.listing_id == listing_id).unwrap();
+    assert_eq!(listing.seller, victim);
+}
+
+#[test]
+fn only_the_seller_can_cancel_a_listing() {
+    setup();
+    let seller = holder(2);
+    let other = holder(3);
+    let property_id = new_property(100);
+    issue(property_id, seller, 50);
+    fund(other, 1_000);
+    let listing_id = list(property_id, seller, 10, 5);
+
+    set_caller(other);
+    assert!(cancel_listing(listing_id).is_err());
+    assert_eq!(get_marketplace_listings().len(), 1);
+
+    set_caller(seller);
+    assert_eq!(cancel_listing(listing_id), Ok("Listing cancelled".to_string()));
+    assert!(get_marketplace_listings().is_empty());
+    assert_eq!(get_balance(other), 1_000);
+}
+
+#[test]
+fn listed_shares_cannot_be_transferred_away() {
+    setup();
+    let seller = holder(2);
+    let friend = holder(3);
+    let property_id = new_property(100);
+    issue(property_id, seller, 50);
+    list(property_id, seller, 40, 5);
+
+    set_caller(seller);
+    assert_eq!(transfer_shares(property_id, seller, friend, 20), Err("Not enough unlisted shares to transfer".to_string()));
+    assert_eq!(transfer_shares(property_id, seller, friend, 10), Ok("Shares transferred".to_string()));
+    assert_eq!(get_listed_shares(property_id, seller), 40);
+    assert_eq!(get_ownership(property_id, seller), 40);
+}
+
+#[test]
+fn forced_transfers_shrink_listings_they_leave_unbacked() {
+    setup();
+    let seller = holder(2);
+    let recipient = holder(3);
+    let property_id = new_property(100);
+    issue(property_id, seller, 50);
+    let older = list(property_id, seller, 20, 5);
+    let newer = list(property_id, seller, 20, 6);
+
+    set_caller(admin());
+    admin_force_transfer(property_id, seller, recipient, 25, "Court order".to_string()).unwrap();
+    let listings = get_marketplace_listings();
+    assert_eq!(listings.len(), 2);
+    assert_eq!(listings.iter().find(|l| l.listing_id == older).unwrap().amount, 20);
+    assert_eq!(listings.iter().find(|l| l.listing_id == newer).unwrap().amount, 5);
+
+    admin_force_transfer(property_id, seller, recipient, 20, "Court order".to_string()).unwrap();
+    let listings = get_marketplace_listings();
+    assert_eq!(listings.len(), 1);
+    assert_eq!(listings[0].listing_id, older);
+    assert_eq!(listings[0].amount, 5);
+    assert_eq!(get_listed_shares(property_id, seller), get_ownership(property_id, seller));
+}
+
+#[test]
+fn dry_run_reports_a_paused_canister() {
+    setup();
+    let seller = holder(2);
+    let buyer = holder(3);
+    let property_id = new_property(100);
+    issue(property_id, seller, 50);
+    fund(buyer, 1_000);
+    let listing_id = list(property_id, seller, 10, 5);
+    assert_eq!(can_buy_shares(listing_id, buyer, 10), Ok(()));
+
+    set_caller(admin());
+    set_paused(true).unwrap();
+    assert_eq!(can_buy_shares(listing_id, buyer, 10), Err(RwaError::Paused));
+    set_caller(buyer);
+    assert_eq!(buy_shares(listing_id, buyer, 10), Err("Canister is paused".to_string()));
+}
+
+#[test]
+fn dry_run_predicts_each_failure_reason() {
+    setup();
+    let seller = holder(2);
+    let buyer = holder(3);
+    let property_id = new_property(100);
+    issue(property_id, seller, 50);
+    let listing_id = list(property_id, seller, 10, 5);
+
+    assert_eq!(can_buy_shares(listing_id, buyer, 10), Err(RwaError::InsufficientFunds));
+    fund(buyer, 50);
+    assert_eq!(can_buy_shares(listing_id, buyer, 10), Ok(()));
+    assert_eq!(can_buy_shares(listing_id, buyer, 11), Err(RwaError::ListingUnavailable));
+    assert_eq!(can_buy_shares(999, buyer, 1), Err(RwaError::ListingUnavailable));
+    assert_eq!(can_buy_shares(listing_id, seller, 1), Err(RwaError::OwnListing));
+    assert_eq!(can_buy_shares(listing_id, user(9), 1), Err(RwaError::NotKycVerified));
+
+    set_caller(admin());
+    set_blacklist(buyer, true).unwrap();
+    assert_eq!(can_buy_shares(listing_id, buyer, 1), Err(RwaError::Blacklisted));
+    set_blacklist(buyer, false).unwrap();
+
+    add_to_whitelist(property_id, seller).unwrap();
+    assert_eq!(can_buy_shares(listing_id, buyer, 1), Err(RwaError::NotWhitelisted));
+    clear_whitelist(property_id).unwrap();
+
+    set_property_frozen(property_id, true).unwrap();
+    assert_eq!(can_buy_shares(listing_id, buyer, 1), Err(RwaError::PropertyFrozen));
+    set_property_frozen(property_id, false).unwrap();
+
+    apply_transfers_paused(property_id, true, admin()).unwrap();
+    assert_eq!(can_buy_shares(listing_id, buyer, 1), Err(RwaError::TransfersPaused));
+    apply_transfers_paused(property_id, false, admin()).unwrap();
+
+    let ownership_capped = register_property("Capped".to_string(), 100, metadata(), Some(1_000), 0).unwrap().id;
+    issue(ownership_capped, seller, 10);
+    let capped_listing = list(ownership_capped, seller, 10, 1);
+    assert_eq!(can_buy_shares(capped_listing, buyer, 10), Ok(()));
+    issue(ownership_capped, buyer, 5);
+    assert_eq!(can_buy_shares(capped_listing, buyer, 10), Err(RwaError::OwnershipCapExceeded));
+
+    set_caller(admin());
+    issue_shares(property_id, seller, 10, Some(60)).unwrap();
+    let until = get_lockup(property_id, seller);
+    assert_eq!(can_buy_shares(listing_id, buyer, 1), Err(RwaError::SharesLocked { until }));
+    assert_eq!(get_balance(buyer), 50);
+    assert_eq!(get_ownership(property_id, buyer), 0);
+}
+
+#[test]
+fn purchases_target_the_exact_listing() {
+    setup();
+    let seller = holder(2);
+    let buyer = holder(3);
+    let property_id = new_property(100);
+    issue(property_id, seller, 50);
+    fund(buyer, 1_000);
+    let cheap = list(property_id, seller, 10, 5);
+    let dear = list(property_id, seller, 10, 20);
+    assert_ne!(cheap, dear);
+
+    set_caller(buyer);
+    buy_shares(dear, buyer, 4).unwrap();
+    assert_eq!(get_balance(buyer), 1_000 - 80);
+    let listings = get_marketplace_listings();
+    assert_eq!(listings.iter().find(|l| l.listing_id == cheap).unwrap().amount, 10);
+    assert_eq!(listings.iter().find(|l| l.listing_id == dear).unwrap().amount, 6);
+    assert_eq!(buy_shares(99, buyer, 1), Err("Listing not found or insufficient shares".to_string()));
+}
+
+#[test]
+fn listing_pages_stop_at_the_end() {
+    setup();
+    let seller = holder(2);
+    let property_id = new_property(1_000);
+    issue(property_id, seller, 500);
+    set_caller(admin());
+    set_max_listings_per_seller(200).unwrap();
+    let ids: Vec<u64> = (0..120).map(|_| list(property_id, seller, 1, 1)).collect();
+
+    let page = get_marketplace_listings_paged(0, 1_000);
+    assert_eq!(page.total, 120);
+    assert_eq!(page.listings.len(), 100);
+    let page = get_marketplace_listings_paged(110, 50);
+    assert_eq!(page.listings.iter().map(|l| l.listing_id).collect::<Vec<_>>(), ids[110..].to_vec());
+    let page = get_marketplace_listings_paged(500, 10);
+    assert!(page.listings.is_empty());
+    assert_eq!(page.total, 120);
+}
+
+#[test]
+fn purchases_move_funds_before_shares() {
+    setup();
+    let seller = holder(2);
+    let buyer = holder(3);
+    let property_id = new_property(100);
+    issue(property_id, seller, 50);
+    fund(buyer, 100);
+    let listing_id = list(property_id, seller, 20, 7);
+
+    set_caller(buyer);
+    assert_eq!(buy_shares(listing_id, buyer, 15), Err("Insufficient funds".to_string()));
+    assert_eq!((get_balance(buyer), get_balance(seller)), (100, 0));
+    assert_eq!((get_ownership(property_id, buyer), get_ownership(property_id, seller)), (0, 50));
+
+    assert_eq!(buy_shares(listing_id, buyer, 10), Ok("Shares bought successfully".to_string()));
+    assert_eq!((get_balance(buyer), get_balance(seller)), (30, 70));
+    assert_eq!((get_ownership(property_id, buyer), get_ownership(property_id, seller)), (10, 40));
+
+    assert_eq!(withdraw_funds(31), Err("Insufficient funds".to_string()));
+    withdraw_funds(30).unwrap();
+    assert_eq!(get_balance(buyer), 0);
+}
+
+#[test]
+fn market_fees_accrue_to_the_treasury() {
+    setup();
+    let seller = holder(2);
+    let buyer = holder(3);
+    let property_id = new_property(100);
+    issue(property_id, seller, 50);
+    fund(buyer, 10_000);
+    set_caller(seller);
+    assert_eq!(set_market_fee_bps(250), Err("Only admin can set the market fee".to_string()));
+    set_caller(admin());
+    assert_eq!(set_market_fee_bps(10_001), Err("Fee cannot exceed 10000 bps".to_string()));
+    set_market_fee_bps(250).unwrap();
+    let listing_id = list(property_id, seller, 40, 100);
+
+    set_caller(buyer);
+    buy_shares(listing_id, buyer, 40).unwrap();
+    // 2.5% of a 4_000 trade
+    assert_eq!(get_balance(buyer), 6_000);
+    assert_eq!(get_balance(seller), 3_900);
+    assert_eq!(get_treasury_balance(), Err("Only admin can view the treasury".to_string()));
+    set_caller(admin());
+    assert_eq!(get_treasury_balance(), Ok(100));
+}
+
+#[test]
+fn listings_must_be_priced_within_the_bounds() {
+    setup();
+    let seller = holder(2);
+    let property_id = new_property(100);
+    issue(property_id, seller, 50);
+    assert_eq!(set_price_bounds(property_id, 20, 10), Err("Minimum price cannot exceed maximum price".to_string()));
+    set_price_bounds(property_id, 10, 20).unwrap();
+
+    set_caller(seller);
+    assert_eq!(list_shares_for_sale(property_id, 5, 9, None, None), Err("Price 9 is below the minimum of 10 per share".to_string()));
+    assert_eq!(list_shares_for_sale(property_id, 5, 21, None, None), Err("Price 21 is above the maximum of 20 per share".to_string()));
+    assert!(get_marketplace_listings().is_empty());
+    list(property_id, seller, 5, 10);
+    list(property_id, seller, 5, 20);
+    assert_eq!(get_marketplace_listings().len(), 2);
+}
+
+#[test]
+fn best_price_buys_fill_the_cheapest_listings_first() {
+    setup();
+    let (alice, bob, buyer) = (holder(2), holder(3), holder(4));
+    let property_id = new_property(100);
+    issue(property_id, alice, 50);
+    issue(property_id, bob, 50);
+    fund(buyer, 1_000);
+    let dear = list(property_id, alice, 10, 9);
+    let cheap = list(property_id, bob, 5, 3);
+    let middle = list(property_id, alice, 5, 5);
+
+    set_caller(buyer);
+    let fill = buy_shares_best_price(property_id, buyer, 12).unwrap();
+    let breakdown: Vec<(u64, u64, u64)> = fill.fills.iter().map(|f| (f.listing_id, f.amount, f.cost)).collect();
+    assert_eq!(breakdown, vec![(cheap, 5, 15), (middle, 5, 25), (dear, 2, 18)]);
+    assert_eq!((fill.filled, fill.total_cost), (12, 58));
+    assert_eq!(get_balance(buyer), 1_000 - 58);
+    assert_eq!(get_ownership(property_id, buyer), 12);
+
+    // Liquidity runs out before the order is filled
+    let fill = buy_shares_best_price(property_id, buyer, 20).unwrap();
+    assert_eq!((fill.filled, fill.total_cost), (8, 72));
+    assert!(get_marketplace_listings().is_empty());
+}
+
+#[test]
+fn order_book_groups_listings_by_price() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    let other = new_property(100);
+    issue(property_id, alice, 50);
+    issue(property_id, bob, 50);
+    issue(other, alice, 50);
+    list(property_id, alice, 10, 7);
+    list(property_id, bob, 5, 3);
+    list(property_id, bob, 4, 7);
+    list(property_id, alice, 1, 3);
+    list(other, alice, 9, 1);
+    let book = get_order_book(property_id);
+    assert_eq!(book.property_id, property_id);
+    assert_eq!(book.levels, vec![(3, 6), (7, 14)]);
+    assert!(get_order_book(other + 1).levels.is_empty());
+}
+
+#[test]
+fn trades_are_logged_with_their_price_and_time() {
+    setup();
+    let (seller, buyer) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    issue(property_id, seller, 50);
+    fund(buyer, 1_000);
+    let first = list(property_id, seller, 10, 4);
+    let second = list(property_id, seller, 10, 6);
+    let start = env::now();
+
+    set_caller(buyer);
+    buy_shares(first, buyer, 3).unwrap();
+    env::advance_secs(10);
+    buy_shares(second, buyer, 2).unwrap();
+    let page = get_trade_history(property_id, 0, 10);
+    assert_eq!(page.total, 2);
+    let trades: Vec<(Principal, Principal, u64, u64, u64)> = page.trades.iter().map(|t| (t.buyer, t.seller, t.amount, t.price_per_share, t.timestamp)).collect();
+    assert_eq!(trades, vec![(buyer, seller, 3, 4, start), (buyer, seller, 2, 6, start + 10_000_000_000)]);
+    assert_eq!(get_trade_history(property_id, 1, 10).trades.len(), 1);
+    assert_eq!(get_trade_history(property_id + 1, 0, 10).total, 0);
+}
+
+#[test]
+fn last_price_and_vwap_follow_recent_trades() {
+    setup();
+    let (seller, buyer) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    issue(property_id, seller, 50);
+    fund(buyer, 1_000);
+    assert_eq!(get_last_price(property_id), None);
+    assert_eq!(get_vwap(property_id, 3_600), None);
+    let first = list(property_id, seller, 10, 10);
+    let second = list(property_id, seller, 10, 20);
+
+    set_caller(buyer);
+    buy_shares(first, buyer, 3).unwrap();
+    env::advance_secs(600);
+    buy_shares(second, buyer, 1).unwrap();
+    assert_eq!(get_last_price(property_id), Some(20));
+    // (3 * 10 + 1 * 20) / 4, rounded down
+    assert_eq!(get_vwap(property_id, 3_600), Some(12));
+    // Only the later trade falls in a five-minute window
+    assert_eq!(get_vwap(property_id, 300), Some(20));
+    env::advance_secs(3_600);
+    assert_eq!(get_vwap(property_id, 300), None);
+}
+
+#[test]
+fn holders_cannot_trade_with_themselves() {
+    setup();
+    let seller = holder(2);
+    let property_id = new_property(100);
+    issue(property_id, seller, 50);
+    fund(seller, 1_000);
+    let listing_id = list(property_id, seller, 10, 5);
+
+    set_caller(seller);
+    assert_eq!(buy_shares(listing_id, seller, 5), Err("Cannot buy your own listing".to_string()));
+    assert_eq!(can_buy_shares(listing_id, seller, 5), Err(RwaError::OwnListing));
+    assert_eq!(transfer_shares(property_id, seller, seller, 5), Err("Cannot transfer shares to yourself".to_string()));
+    assert_eq!(get_balance(seller), 1_000);
+    assert!(get_trade_history(property_id, 0, 10).trades.is_empty());
+}
+
+#[test]
+fn sellers_list_up_to_the_cap() {
+    setup();
+    let (seller, other) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    issue(property_id, seller, 50);
+    issue(property_id, other, 10);
+    assert_eq!(set_max_listings_per_seller(0), Err("Listing cap must be at least 1".to_string()));
+    set_max_listings_per_seller(3).unwrap();
+    let listing_ids: Vec<u64> = (0..3).map(|_| list(property_id, seller, 1, 1)).collect();
+
+    set_caller(seller);
+    assert_eq!(list_shares_for_sale(property_id, 1, 1, None, None), Err("Seller already has the maximum of 3 open listings".to_string()));
+    // The cap is per seller, and cancelling frees a slot
+    list(property_id, other, 1, 1);
+    set_caller(seller);
+    cancel_listing(listing_ids[0]).unwrap();
+    list(property_id, seller, 1, 1);
+    assert_eq!(get_my_listings().len(), 3);
+    assert_eq!(set_max_listings_per_seller(5), Err("Only admin can set the listing cap".to_string()));
+}
+
+#[test]
+fn listing_currency_round_trips_and_defaults() {
+    setup();
+    let alice = holder(2);
+    let property_id = new_property(100);
+    issue(property_id, alice, 50);
+    set_caller(alice);
+    list_shares_for_sale(property_id, 10, 250, Some(" ckusdc ".to_string()), Some(6)).unwrap();
+    list_shares_for_sale(property_id, 10, 3, None, None).unwrap();
+    assert!(list_shares_for_sale(property_id, 10, 3, Some("US-D".to_string()), None).is_err());
+    assert!(list_shares_for_sale(property_id, 10, 3, None, Some(MAX_DECIMALS + 1)).is_err());
+
+    let listings = get_marketplace_listings();
+    assert_eq!(listings.len(), 2);
+    let quoted = listings.iter().find(|l| l.price_per_share == 250).unwrap();
+    assert_eq!((quoted.currency.as_str(), quoted.price_decimals), ("CKUSDC", 6));
+    let defaulted = listings.iter().find(|l| l.price_per_share == 3).unwrap();
+    assert_eq!((defaulted.currency.as_str(), defaulted.price_decimals), (DEFAULT_CURRENCY, DEFAULT_PRICE_DECIMALS));
+}
@@ -0,0 +1,176 @@
+//! Unit tests. Endpoints run as plain functions; `env` stands in for the
+//! system API so each test picks the caller and the clock. Tests run on
+//! separate threads, so every test starts from fresh canister state.
+
+use super::*;
+
+mod audit;
+mod governance;
+mod income;
+mod kyc;
+mod marketplace;
+mod properties;
+mod redemption;
+mod roles;
+mod shares;
+mod upgrade;
+
+pub(crate) mod env {
+    use super::super::{Event, TimerId, NOTIFY_METHOD};
+    use candid::Principal;
+    use std::cell::RefCell;
+    use std::time::Duration;
+
+    // 2023-11-14, so lockups and deadlines never start at zero
+    pub const START_NS: u64 = 1_700_000_000_000_000_000;
+
+    thread_local! {
+        static CALLER: RefCell<Principal> = const { RefCell::new(Principal::anonymous()) };
+        static TIME: RefCell<u64> = const { RefCell::new(START_NS) };
+        static CONTROLLERS: RefCell<Vec<Principal>> = const { RefCell::new(Vec::new()) };
+        static CERTIFIED_DATA: RefCell<Vec<u8>> = const { RefCell::new(Vec::new()) };
+        static NOTIFICATIONS: RefCell<Vec<(Principal, String, Event)>> = const { RefCell::new(Vec::new()) };
+        static CYCLES: RefCell<u64> = const { RefCell::new(0) };
+        static TIMERS: RefCell<Vec<Box<dyn FnMut()>>> = RefCell::new(Vec::new());
+    }
+
+    pub fn caller() -> Principal {
+        CALLER.with(|c| *c.borrow())
+    }
+
+    pub fn set_caller(principal: Principal) {
+        CALLER.with(|c| *c.borrow_mut() = principal);
+    }
+
+    pub fn now() -> u64 {
+        TIME.with(|t| *t.borrow())
+    }
+
+    pub fn advance_secs(secs: u64) {
+        TIME.with(|t| *t.borrow_mut() += secs * 1_000_000_000);
+    }
+
+    pub fn is_controller(principal: &Principal) -> bool {
+        CONTROLLERS.with(|c| c.borrow().contains(principal))
+    }
+
+    pub fn add_controller(principal: Principal) {
+        CONTROLLERS.with(|c| c.borrow_mut().push(principal));
+    }
+
+    pub fn canister_balance() -> u64 {
+        CYCLES.with(|c| *c.borrow())
+    }
+
+    pub fn set_canister_balance(cycles: u64) {
+        CYCLES.with(|c| *c.borrow_mut() = cycles);
+    }
+
+    pub fn set_certified_data(data: &[u8]) {
+        CERTIFIED_DATA.with(|c| *c.borrow_mut() = data.to_vec());
+    }
+
+    pub fn certified_data() -> Vec<u8> {
+        CERTIFIED_DATA.with(|c| c.borrow().clone())
+    }
+
+    pub fn data_certificate() -> Option<Vec<u8>> {
+        Some(b"test-certificate".to_vec())
+    }
+
+    pub fn notify_event(canister: Principal, event: &Event) {
+        NOTIFICATIONS.with(|n| n.borrow_mut().push((canister, NOTIFY_METHOD.to_string(), event.clone())));
+    }
+
+    /// Notifications sent so far, as (canister, method, payload).
+    pub fn notifications() -> Vec<(Principal, String, Event)> {
+        NOTIFICATIONS.with(|n| n.borrow().clone())
+    }
+
+    /// Timers only fire when a test calls `fire_timers`. Cleared timers are
+    /// kept, so their callbacks must tolerate running after cancellation.
+    pub fn set_timer_interval(_interval: Duration, func: impl FnMut() + 'static) -> TimerId {
+        TIMERS.with(|t| t.borrow_mut().push(Box::new(func)));
+        TimerId::default()
+    }
+
+    /// Runs every interval timer's callback once, as if each interval elapsed.
+    pub fn fire_timers() {
+        let mut timers = TIMERS.with(|t| t.take());
+        for func in timers.iter_mut() {
+            func();
+        }
+        TIMERS.with(|t| {
+            let added = t.replace(timers);
+            t.borrow_mut().extend(added);
+        });
+    }
+
+    pub fn clear_timer(_timer_id: TimerId) {}
+}
+
+use env::set_caller;
+
+pub fn user(n: u8) -> Principal {
+    Principal::from_slice(&[n, 0xAA])
+}
+
+pub fn admin() -> Principal {
+    user(1)
+}
+
+/// Deploys with `admin()` as the initial admin and strict KYC on, and leaves
+/// the admin as the caller.
+pub fn setup() {
+    let deployer = user(0);
+    env::add_controller(deployer);
+    set_caller(deployer);
+    init(admin(), true);
+    set_caller(admin());
+}
+
+pub fn metadata() -> PropertyMetadata {
+    PropertyMetadata {
+        location: "1 Main St".to_string(),
+        description: "Test property".to_string(),
+        tags: Vec::new(),
+        country: "US".to_string(),
+        city: "Austin".to_string(),
+        lat: None,
+        lng: None,
+    }
+}
+
+/// Registers a property as the admin and returns its id.
+pub fn new_property(total_shares: u64) -> PropertyId {
+    set_caller(admin());
+    let name = format!("Property {}", NEXT_PROPERTY_ID.with(|id| *id.borrow()));
+    register_property(name, total_shares, metadata(), None, 0).unwrap().id
+}
+
+/// A KYC-verified user.
+pub fn holder(n: u8) -> Principal {
+    let principal = user(n);
+    set_caller(admin());
+    set_kyc_status(principal, true, None).unwrap();
+    principal
+}
+
+/// Credits internal funds as the admin.
+pub fn fund(user: Principal, amount: u64) {
+    set_caller(admin());
+    deposit_funds(user, amount).unwrap();
+}
+
+/// Issues shares as the admin.
+pub fn issue(property_id: PropertyId, to: Principal, amount: u64) {
+    set_caller(admin());
+    issue_shares(property_id, to, amount, None).unwrap();
+}
+
+/// Lists `amount` of the seller's shares at `price`, returning the listing id.
+pub fn list(property_id: PropertyId, seller: Principal, amount: u64, price: u64) -> u64 {
+    set_caller(seller);
+    list_shares_for_sale(property_id, amount, price, None, None).unwrap();
+    NEXT_LISTING_ID.with(|id| *id.borrow()) - 1
+}
@@ -0,0 +1,298 @@
+use super::*;
+
+#[test]
+fn only_admins_and_managers_update_status_and_metadata() {
+    setup();
+    let property_id = new_property(100);
+    // The caller is taken from the call itself, so naming an admin gets nowhere
+    set_caller(user(2));
+    assert_eq!(update_property_status(property_id, PropertyStatus::Maintenance), Err("Only admin or manager can update property status".to_string()));
+    assert_eq!(update_property_metadata(property_id, metadata()), Err("Only admin or manager can update property metadata".to_string()));
+    assert!(get_property(property_id).unwrap().status == PropertyStatus::Active);
+
+    set_caller(admin());
+    update_property_status(property_id, PropertyStatus::Maintenance).unwrap();
+    assert!(get_property(property_id).unwrap().status == PropertyStatus::Maintenance);
+}
+
+fn page_ids(page: &PropertyPage) -> Vec<PropertyId> {
+    page.properties.iter().map(|p| p.id).collect()
+}
+
+#[test]
+fn property_pages_filter_by_status_in_id_order() {
+    setup();
+    let ids: Vec<PropertyId> = (0..5).map(|_| new_property(10)).collect();
+    update_property_status(ids[1], PropertyStatus::Maintenance).unwrap();
+    update_property_status(ids[3], PropertyStatus::Maintenance).unwrap();
+
+    let active = get_all_properties(0, 10, Some(PropertyStatus::Active), None);
+    assert_eq!(page_ids(&active), vec![ids[0], ids[2], ids[4]]);
+    assert_eq!(active.total, 3);
+    let maintenance = get_all_properties(0, 10, Some(PropertyStatus::Maintenance), None);
+    assert_eq!(page_ids(&maintenance), vec![ids[1], ids[3]]);
+
+    let first = get_all_properties(1, 2, None, None);
+    assert_eq!(page_ids(&first), vec![ids[1], ids[2]]);
+    assert_eq!(page_ids(&get_all_properties(1, 2, None, None)), page_ids(&first));
+    assert_eq!(first.total, 5);
+}
+
+#[test]
+fn valuations_are_kept_in_the_order_recorded() {
+    setup();
+    let property_id = new_property(1_000);
+    let start = env::now();
+    for valuation in [500_000, 450_000, 520_000] {
+        record_valuation(property_id, valuation).unwrap();
+        env::advance_secs(60);
+    }
+    let history: Vec<(u64, u64, u64)> = get_valuation_history(property_id).iter().map(|v| (v.timestamp, v.valuation, v.price_per_share)).collect();
+    let minute = 60 * 1_000_000_000;
+    assert_eq!(history, vec![(start, 500_000, 500), (start + minute, 450_000, 450), (start + 2 * minute, 520_000, 520)]);
+
+    assert_eq!(record_valuation(property_id + 1, 1), Err("Property not found".to_string()));
+    set_caller(user(2));
+    assert_eq!(record_valuation(property_id, 1), Err("Only admin can record valuations".to_string()));
+    assert_eq!(get_valuation_history(property_id).len(), 3);
+}
+
+#[test]
+fn only_never_issued_properties_are_archived() {
+    setup();
+    let alice = holder(2);
+    let clean = new_property(100);
+    let held = new_property(100);
+    issue(held, alice, 10);
+
+    set_caller(alice);
+    assert_eq!(archive_property(clean), Err("Only admin can archive properties".to_string()));
+    set_caller(admin());
+    assert_eq!(archive_property(held), Err("Cannot archive a property with issued shares".to_string()));
+    archive_property(clean).unwrap();
+    assert!(get_property(clean).is_none());
+    assert_eq!(archive_property(clean), Err("Property not found".to_string()));
+    let listed: Vec<PropertyId> = get_all_properties(0, 10, None, None).properties.iter().map(|p| p.id).collect();
+    assert_eq!(listed, vec![held]);
+}
+
+#[test]
+fn archiving_keeps_history_and_clears_configuration() {
+    setup();
+    let alice = holder(2);
+    let property_id = new_property(100);
+    record_valuation(property_id, 10_000).unwrap();
+    add_document(property_id, "Deed".to_string(), "ipfs://deed".to_string(), [7; 32]).unwrap();
+    add_to_whitelist(property_id, alice).unwrap();
+    set_claim_window(property_id, Some(60)).unwrap();
+    set_price_bounds(property_id, 1, 10).unwrap();
+    schedule_recurring_income(property_id, 100, 60).unwrap();
+    set_caller(alice);
+    delegate_vote(property_id, user(9)).unwrap();
+
+    set_caller(admin());
+    archive_property(property_id).unwrap();
+    assert_eq!(get_valuation_history(property_id).len(), 1);
+    assert_eq!(get_valuation_history(property_id)[0].price_per_share, 100);
+    assert_eq!(get_documents(property_id).len(), 1);
+    assert!(get_whitelist(property_id).is_none());
+    assert_eq!(get_claim_window(property_id), None);
+    assert!(get_recurring_income(property_id).is_none());
+    assert_eq!(get_delegate(property_id, alice), None);
+}
+
+#[test]
+fn token_metadata_describes_the_property() {
+    setup();
+    let property_id = register_property("Harbor Lofts".to_string(), 5_000, metadata(), None, 2).unwrap().id;
+    let entries: Vec<(String, String)> = icrc1_metadata(property_id)
+        .into_iter()
+        .map(|(key, value)| {
+            let value = match value {
+                MetadataValue::Nat(n) => n.to_string(),
+                MetadataValue::Text(t) => t,
+                MetadataValue::Int(_) | MetadataValue::Blob(_) => panic!("unexpected value for {}", key),
+            };
+            (key, value)
+        })
+        .collect();
+    let expected = [("icrc1:name", "Harbor Lofts".to_string()), ("icrc1:symbol", format!("RWA{}", property_id)), ("icrc1:decimals", "2".to_string()), ("icrc1:total_supply", "5_000".to_string())];
+    assert_eq!(entries, expected.map(|(key, value)| (key.to_string(), value)));
+    assert!(icrc1_metadata(property_id + 1).is_empty());
+}
+
+#[test]
+fn registration_rejects_zero_shares_and_blank_names() {
+    setup();
+    assert_eq!(register_property("Empty".to_string(), 0, metadata(), None, 0).err(), Some("Total shares must be greater than zero".to_string()));
+    assert_eq!(register_property("".to_string(), 100, metadata(), None, 0).err(), Some("Property name cannot be empty".to_string()));
+    assert_eq!(register_property("   ".to_string(), 100, metadata(), None, 0).err(), Some("Property name cannot be empty".to_string()));
+    assert!(get_all_properties(0, 10, None, None).properties.is_empty());
+    let property = register_property("  Elm Court ".to_string(), 100, metadata(), None, 0).unwrap();
+    assert_eq!(property.name, "Elm Court");
+}
+
+/// Registers a property as the admin with the given tags and city.
+fn property_in(name: &str, tags: &[&str], city: &str) -> PropertyId {
+    let mut metadata = metadata();
+    metadata.tags = tags.iter().map(|t| t.to_string()).collect();
+    metadata.city = city.to_string();
+    set_caller(admin());
+    register_property(name.to_string(), 100, metadata, None, 0).unwrap().id
+}
+
+#[test]
+fn property_pages_filter_by_tag() {
+    setup();
+    let loft = property_in("Loft", &["Residential", "urban"], "Austin");
+    let mall = property_in("Mall", &["commercial"], "Austin");
+    let duplex = property_in("Duplex", &[" residential "], "Austin");
+    assert_eq!(get_property(loft).unwrap().metadata.tags, vec!["residential", "urban"]);
+
+    let residential = get_all_properties(0, 10, None, Some("residential".to_string()));
+    assert_eq!(page_ids(&residential), vec![loft, duplex]);
+    assert_eq!(residential.total, 2);
+    assert_eq!(page_ids(&get_all_properties(0, 10, None, Some("Commercial".to_string()))), vec![mall]);
+    assert!(get_all_properties(0, 10, None, Some("reit".to_string())).properties.is_empty());
+    assert_eq!(get_all_tags(), vec!["commercial", "residential", "urban"]);
+}
+
+#[test]
+fn city_search_ignores_case_and_whitespace() {
+    setup();
+    let loft = property_in("Loft", &[], "Austin");
+    property_in("Tower", &[], "Dallas");
+    let duplex = property_in("Duplex", &[], "  austin ");
+    let found: Vec<PropertyId> = search_properties_by_city("AUSTIN".to_string()).iter().map(|p| p.id).collect();
+    assert_eq!(found, vec![loft, duplex]);
+    assert_eq!(get_property(duplex).unwrap().metadata.city, "austin");
+    assert!(search_properties_by_city("Houston".to_string()).is_empty());
+
+    let mut out_of_range = metadata();
+    out_of_range.lat = Some(91.0);
+    assert_eq!(register_property("Nowhere".to_string(), 100, out_of_range, None, 0).err(), Some("Latitude must be between -90 and 90".to_string()));
+}
+
+#[test]
+fn admins_rename_properties_to_unused_names() {
+    setup();
+    let property_id = new_property(100);
+    let other = register_property("Oak House".to_string(), 100, metadata(), None, 0).unwrap().id;
+    assert_eq!(rename_property(property_id, "  ".to_string()), Err("Property name cannot be empty".to_string()));
+    assert_eq!(rename_property(property_id, "oak house".to_string()), Err("A property named oak house already exists".to_string()));
+    assert_eq!(rename_property(property_id, " Maple Flats ".to_string()), Ok("Property renamed".to_string()));
+    assert_eq!(get_property(property_id).unwrap().name, "Maple Flats");
+    assert_eq!(get_property_by_name("maple flats".to_string()).unwrap().id, property_id);
+    rename_property(other, "OAK HOUSE".to_string()).unwrap();
+    set_caller(user(2));
+    assert_eq!(rename_property(property_id, "Mine".to_string()), Err("Only admin can rename properties".to_string()));
+}
+
+/// Root hash of a witness, computed the way an IC client verifies one.
+fn reconstruct(tree: &HashTree) -> [u8; 32] {
+    match tree {
+        HashTree::Empty => sha256(&[&domain_sep("ic-hashtree-empty")]),
+        HashTree::Fork(left, right) => fork_hash(&reconstruct(left), &reconstruct(right)),
+        HashTree::Labeled(label, subtree) => labeled_hash(label, &reconstruct(subtree)),
+        HashTree::Leaf(value) => leaf_hash(value),
+        HashTree::Pruned(hash) => hash.as_slice().try_into().unwrap(),
+    }
+}
+
+/// The leaf a witness reveals for a property under the "properties" label.
+fn revealed_leaf(tree: &HashTree, property_id: PropertyId) -> Option<Vec<u8>> {
+    match tree {
+        HashTree::Fork(left, right) => revealed_leaf(left, property_id).or_else(|| revealed_leaf(right, property_id)),
+        HashTree::Labeled(label, subtree) if label.as_slice() == CERTIFIED_LABEL => revealed_leaf(subtree, property_id),
+        HashTree::Labeled(label, subtree) if *label == property_id.to_be_bytes() => match subtree.as_ref() {
+            HashTree::Leaf(value) => Some(value.clone()),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[test]
+fn certified_properties_verify_against_the_certified_data() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    let ids: Vec<PropertyId> = (0..3).map(|_| new_property(100)).collect();
+    issue(ids[1], alice, 40);
+    issue(ids[1], bob, 10);
+
+    let response = get_property_certified(ids[1]).unwrap();
+    assert_eq!(reconstruct(&response.witness).to_vec(), env::certified_data());
+    assert_eq!(response.holders, vec![(alice, 40), (bob, 10)]);
+    let leaf = revealed_leaf(&response.witness, ids[1]).unwrap();
+    assert_eq!(leaf, certified_digest(&response.property, &response.holders).to_vec());
+    assert!(revealed_leaf(&response.witness, ids[0]).is_none());
+
+    // Any change to the holdings moves the certified root
+    set_caller(alice);
+    transfer_shares(ids[1], alice, bob, 5).unwrap();
+    assert_ne!(reconstruct(&response.witness).to_vec(), env::certified_data());
+    let fresh = get_property_certified(ids[1]).unwrap();
+    assert_eq!(reconstruct(&fresh.witness).to_vec(), env::certified_data());
+    assert_eq!(get_property_certified(99).err(), Some("Property not found".to_string()));
+}
+
+#[test]
+fn admins_attach_documents_in_order() {
+    setup();
+    let property_id = new_property(100);
+    add_document(property_id, "Title deed".to_string(), "ipfs://deed".to_string(), [1; 32]).unwrap();
+    add_document(property_id, " Prospectus ".to_string(), "https://example.com/prospectus.pdf".to_string(), [2; 32]).unwrap();
+    let documents: Vec<(String, String, [u8; 32])> = get_documents(property_id).into_iter().map(|d| (d.name, d.uri, d.sha256)).collect();
+    assert_eq!(documents, vec![
+        ("Title deed".to_string(), "ipfs://deed".to_string(), [1; 32]),
+        ("Prospectus".to_string(), "https://example.com/prospectus.pdf".to_string(), [2; 32]),
+    ]);
+    assert_eq!(add_document(property_id, "".to_string(), "ipfs://x".to_string(), [0; 32]), Err("Document name and URI cannot be empty".to_string()));
+    assert_eq!(add_document(property_id + 1, "Deed".to_string(), "ipfs://x".to_string(), [0; 32]), Err("Property not found".to_string()));
+
+    set_caller(user(2));
+    assert_eq!(add_document(property_id, "Fake".to_string(), "ipfs://fake".to_string(), [0; 32]), Err("Only admin can add documents".to_string()));
+    assert_eq!(get_documents(property_id).len(), 2);
+}
+
+#[test]
+fn properties_are_found_by_unique_name() {
+    setup();
+    let property_id = register_property("Birch Row".to_string(), 100, metadata(), None, 0).unwrap().id;
+    assert_eq!(get_property_by_name(" birch ROW ".to_string()).unwrap().id, property_id);
+    assert!(get_property_by_name("Cedar Row".to_string()).is_none());
+    assert_eq!(register_property("BIRCH ROW".to_string(), 100, metadata(), None, 0).err(), Some("A property named BIRCH ROW already exists".to_string()));
+    assert_eq!(get_all_properties(0, 10, None, None).total, 1);
+}
+
+#[test]
+fn sold_is_a_terminal_status() {
+    setup();
+    let property_id = new_property(100);
+    assert_eq!(update_property_status(property_id, PropertyStatus::Maintenance), Ok("Property status updated".to_string()));
+    update_property_status(property_id, PropertyStatus::Active).unwrap();
+    update_property_status(property_id, PropertyStatus::Sold).unwrap();
+    let invalid = Err("Invalid status transition".to_string());
+    assert_eq!(update_property_status(property_id, PropertyStatus::Active), invalid);
+    assert_eq!(update_property_status(property_id, PropertyStatus::Maintenance), invalid);
+    assert_eq!(update_property_status(property_id, PropertyStatus::Sold), invalid);
+    assert!(get_property(property_id).unwrap().status == PropertyStatus::Sold);
+}
+
+#[test]
+fn batches_register_all_or_nothing() {
+    setup();
+    let entry = |name: &str, total: u64| (name.to_string(), total, metadata());
+    assert_eq!(register_properties(vec![entry("A", 10), entry("B", 0), entry("C", 10)]).err(), Some("Entry 1: Total shares must be greater than zero".to_string()));
+    assert_eq!(register_properties(vec![entry("A", 10), entry("a ", 10)]).err(), Some("Entry 1: Duplicate property name a in batch".to_string()));
+    assert!(get_all_properties(0, 10, None, None).properties.is_empty());
+
+    let created = register_properties(vec![entry("A", 10), entry("B", 20), entry("C", 30)]).unwrap();
+    let created: Vec<(PropertyId, String, u64)> = created.into_iter().map(|p| (p.id, p.name, p.total_shares)).collect();
+    assert_eq!(created, vec![(1, "A".to_string(), 10), (2, "B".to_string(), 20), (3, "C".to_string(), 30)]);
+    assert_eq!(get_property_by_name("b".to_string()).unwrap().id, 2);
+    assert_eq!(register_properties(vec![entry("D", 10), entry("A", 10)]).err(), Some("Entry 1: A property named A already exists".to_string()));
+
+    set_caller(user(2));
+    assert_eq!(register_properties(vec![entry("E", 10)]).err(), Some("Only admin can register properties".to_string()));
+}
@@ -0,0 +1,105 @@
+use super::*;
+
+/// 1000-share property with 600 and 300 issued to two holders, sold for 10_000.
+fn sold_property() -> (PropertyId, Principal, Principal) {
+    setup();
+    let alice = holder(2);
+    let bob = holder(3);
+    let property_id = new_property(1_000);
+    issue(property_id, alice, 600);
+    issue(property_id, bob, 300);
+    set_caller(admin());
+    update_property_status(property_id, PropertyStatus::Sold).unwrap();
+    set_sale_proceeds(property_id, 10_000).unwrap();
+    (property_id, alice, bob)
+}
+
+#[test]
+fn holders_redeem_their_proportional_share() {
+    let (property_id, alice, bob) = sold_property();
+    set_caller(alice);
+    assert_eq!(redeem_shares(property_id), Ok(6_000));
+    set_caller(bob);
+    assert_eq!(redeem_shares(property_id), Ok(3_000));
+    assert_eq!(get_balance(alice), 6_000);
+    assert_eq!(get_balance(bob), 3_000);
+    assert_eq!(get_ownership(property_id, alice), 0);
+    assert_eq!(redeem_shares(property_id), Err("No shares to redeem".to_string()));
+}
+
+#[test]
+fn redemption_requires_a_sold_property() {
+    setup();
+    let alice = holder(2);
+    let property_id = new_property(100);
+    issue(property_id, alice, 10);
+    set_caller(alice);
+    assert_eq!(redeem_shares(property_id), Err("Property is not sold".to_string()));
+}
+
+#[test]
+fn sold_properties_cannot_issue_or_burn_shares_again() {
+    let (property_id, alice, bob) = sold_property();
+    set_caller(alice);
+    redeem_shares(property_id).unwrap();
+
+    // Redeemed and unissued shares stay out of circulation
+    set_caller(admin());
+    assert_eq!(issue_shares(property_id, alice, 600, None), Err("Property is sold".to_string()));
+    assert_eq!(batch_issue_shares(property_id, vec![(alice, 100)]), Err("Property is sold".to_string()));
+    assert_eq!(set_share_classes(property_id, Vec::new()), Err("Property is sold".to_string()));
+    set_caller(bob);
+    assert_eq!(burn_shares(property_id, 100, false), Err("Property is sold".to_string()));
+
+    assert_eq!(redeem_shares(property_id), Ok(3_000));
+    assert_eq!(get_balance(alice) + get_balance(bob), 9_000);
+}
+
+#[test]
+fn buybacks_fill_until_exhausted_or_closed() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    issue(property_id, alice, 30);
+    issue(property_id, bob, 30);
+    // Market fees would normally fill the treasury
+    TREASURY.with(|t| *t.borrow_mut() = 1_000);
+    set_caller(admin());
+    assert_eq!(open_buyback(property_id, 10, 101), Err("Insufficient treasury funds for buyback".to_string()));
+    open_buyback(property_id, 10, 50).unwrap();
+    assert_eq!(get_treasury_balance(), Ok(500));
+
+    set_caller(alice);
+    assert_eq!(sell_to_buyback(property_id, 20), Ok(200));
+    assert_eq!(get_buyback(property_id).unwrap().remaining, 30);
+    set_caller(bob);
+    assert_eq!(sell_to_buyback(property_id, 31), Err("Buyback only accepts 30 more shares".to_string()));
+    assert_eq!(sell_to_buyback(property_id, 30), Ok(300));
+    set_caller(alice);
+    assert_eq!(sell_to_buyback(property_id, 1), Err("Buyback only accepts 0 more shares".to_string()));
+    assert_eq!((get_balance(alice), get_balance(bob)), (200, 300));
+    assert_eq!((get_ownership(property_id, alice), get_ownership(property_id, bob)), (10, 0));
+    assert_eq!(get_property(property_id).unwrap().shares_available, 90);
+
+    set_caller(admin());
+    close_buyback(property_id).unwrap();
+    set_caller(alice);
+    assert_eq!(sell_to_buyback(property_id, 1), Err("No open buyback for this property".to_string()));
+}
+
+#[test]
+fn closing_a_partly_filled_buyback_refunds_the_treasury() {
+    setup();
+    let alice = holder(2);
+    let property_id = new_property(100);
+    issue(property_id, alice, 30);
+    TREASURY.with(|t| *t.borrow_mut() = 1_000);
+    set_caller(admin());
+    open_buyback(property_id, 10, 50).unwrap();
+    set_caller(alice);
+    sell_to_buyback(property_id, 5).unwrap();
+    set_caller(admin());
+    close_buyback(property_id).unwrap();
+    assert_eq!(get_treasury_balance(), Ok(1_000 - 50));
+    assert!(get_buyback(property_id).is_none());
+}
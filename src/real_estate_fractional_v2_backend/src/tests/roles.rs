@@ -0,0 +1,163 @@
+use super::*;
+
+#[test]
+fn admin_applies_only_once_accepted() {
+    setup();
+    let alice = user(2);
+    assert_eq!(set_role(alice, Role::Admin), Err("Admin must be granted with propose_role and accept_role".to_string()));
+    add_admin(alice).unwrap();
+    assert_eq!(get_role(&alice), Role::User);
+    assert!(get_pending_role(alice).is_some_and(|pending| pending.role == Role::Admin));
+    set_caller(alice);
+    accept_role().unwrap();
+    assert_eq!(get_role(&alice), Role::Admin);
+    assert!(get_admins().contains(&alice));
+    assert!(get_pending_role(alice).is_none());
+}
+
+#[test]
+fn only_the_proposed_principal_can_accept() {
+    setup();
+    let alice = user(2);
+    propose_role(alice, Role::Manager).unwrap();
+    set_caller(user(3));
+    assert_eq!(accept_role(), Err("No pending role for caller".to_string()));
+    assert_eq!(get_role(&alice), Role::User);
+}
+
+#[test]
+fn unaccepted_proposals_expire() {
+    setup();
+    let alice = user(2);
+    add_admin(alice).unwrap();
+    env::advance_secs(ROLE_HANDOVER_WINDOW_SECS + 1);
+    set_caller(alice);
+    assert_eq!(accept_role(), Err("Role proposal has expired".to_string()));
+    assert_eq!(get_role(&alice), Role::User);
+    assert_eq!(accept_role(), Err("No pending role for caller".to_string()));
+}
+
+#[test]
+fn the_last_admin_cannot_be_handed_away() {
+    setup();
+    assert_eq!(propose_role(admin(), Role::User), Err("Cannot remove last admin".to_string()));
+    assert_eq!(remove_admin(admin()), Err("Cannot remove last admin".to_string()));
+    // With a second admin in place the first one can step down
+    add_admin(user(2)).unwrap();
+    set_caller(user(2));
+    accept_role().unwrap();
+    remove_admin(admin()).unwrap();
+    assert_eq!(get_role(&admin()), Role::User);
+}
+
+#[test]
+fn only_admins_can_propose_roles() {
+    setup();
+    set_caller(user(2));
+    assert_eq!(add_admin(user(2)), Err("Only admin can add admins".to_string()));
+    assert_eq!(propose_role(user(2), Role::Admin), Err("Only admin can propose roles".to_string()));
+}
+
+fn manager(n: u8) -> Principal {
+    let principal = user(n);
+    set_caller(admin());
+    set_role(principal, Role::Manager).unwrap();
+    principal
+}
+
+#[test]
+fn managers_run_properties_but_not_roles_or_kyc() {
+    setup();
+    let alice = holder(2);
+    let property_id = new_property(100);
+    issue(property_id, alice, 10);
+    let bob = manager(3);
+    set_caller(bob);
+    assert_eq!(deposit_rental_income(property_id, 100), Ok("Rental income distributed".to_string()));
+    update_property_metadata(property_id, metadata()).unwrap();
+    update_property_status(property_id, PropertyStatus::Maintenance).unwrap();
+    assert_eq!(set_role(user(4), Role::Manager), Err("Only admin can set roles".to_string()));
+    assert!(set_kyc_status(user(4), true, None).is_err());
+    assert_eq!(get_role(&user(4)), Role::User);
+}
+
+#[test]
+fn admins_are_added_and_removed_in_step_with_roles() {
+    setup();
+    let alice = user(2);
+    assert_eq!(get_admins(), vec![admin()]);
+    add_admin(alice).unwrap();
+    set_caller(alice);
+    accept_role().unwrap();
+    assert_eq!(get_admins(), vec![admin(), alice]);
+
+    assert_eq!(remove_admin(user(3)), Err("User is not an admin".to_string()));
+    remove_admin(admin()).unwrap();
+    assert_eq!(get_admins(), vec![alice]);
+    assert_eq!(get_role(&admin()), Role::User);
+    assert_eq!(remove_admin(alice), Err("Cannot remove last admin".to_string()));
+    assert_eq!(get_admins(), vec![alice]);
+}
+
+#[test]
+fn only_a_controller_can_bootstrap_the_admin() {
+    // No init: the canister starts with only the placeholder admin
+    let controller = user(0);
+    env::add_controller(controller);
+    set_caller(user(2));
+    assert_eq!(bootstrap_admin(user(2)), Err("Only a controller can bootstrap the admin".to_string()));
+    assert_eq!(get_role(&user(2)), Role::User);
+
+    set_caller(controller);
+    assert_eq!(bootstrap_admin(admin()), Ok("Admin bootstrapped".to_string()));
+    assert_eq!(get_admins(), vec![admin()]);
+    assert_eq!(bootstrap_admin(user(2)), Err("Admin already bootstrapped".to_string()));
+}
+
+#[test]
+fn init_installs_the_admin_and_config() {
+    let deployer = user(0);
+    set_caller(deployer);
+    init(admin(), false);
+    assert_eq!(get_role(&admin()), Role::Admin);
+    assert_eq!(get_admins(), vec![admin()]);
+    assert_eq!(get_role(&Principal::anonymous()), Role::User);
+    assert_eq!(get_deployer(), Some(deployer));
+    assert!(!is_strict_kyc_enabled());
+    env::add_controller(deployer);
+    assert_eq!(bootstrap_admin(user(2)), Err("Admin already bootstrapped".to_string()));
+}
+
+#[test]
+fn pausing_blocks_updates_until_an_admin_unpauses() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    issue(property_id, alice, 50);
+    fund(bob, 100);
+    let listing_id = list(property_id, alice, 10, 2);
+
+    set_caller(alice);
+    assert_eq!(set_paused(true), Err("Only admin can pause the canister".to_string()));
+    assert!(!is_paused());
+    set_caller(admin());
+    set_paused(true).unwrap();
+    assert!(is_paused());
+
+    let paused = Err("Canister is paused".to_string());
+    set_caller(alice);
+    assert_eq!(transfer_shares(property_id, alice, bob, 5), paused);
+    set_caller(bob);
+    assert_eq!(buy_shares(listing_id, bob, 10), paused);
+    assert!(get_property(property_id).is_some());
+    assert_eq!(icrc1_balance_of(property_id, alice), 50);
+    assert_eq!(set_paused(false), Err("Only admin can pause the canister".to_string()));
+
+    set_caller(admin());
+    set_paused(false).unwrap();
+    set_caller(alice);
+    transfer_shares(property_id, alice, bob, 5).unwrap();
+    set_caller(bob);
+    buy_shares(listing_id, bob, 10).unwrap();
+    assert_eq!(icrc1_balance_of(property_id, bob), 15);
+}
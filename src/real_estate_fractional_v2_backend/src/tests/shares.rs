@@ -0,0 +1,452 @@
+use super::*;
+
+#[test]
+fn only_authorized_callers_register_and_issue() {
+    setup();
+    let alice = holder(2);
+    set_caller(alice);
+    assert_eq!(register_property("Mine".to_string(), 100, metadata(), None, 0).err(), Some("Only admin can register properties".to_string()));
+    let property_id = new_property(100);
+    set_caller(alice);
+    assert_eq!(issue_shares(property_id, alice, 10, None), Err("Only admin or manager can issue shares".to_string()));
+    assert_eq!(get_ownership(property_id, alice), 0);
+    set_caller(admin());
+    assert_eq!(issue_shares(property_id, alice, 10, None), Ok("Shares issued".to_string()));
+}
+
+#[test]
+fn issued_shares_match_holdings() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    assert!(verify_property_integrity(property_id));
+    issue(property_id, alice, 30);
+    issue(property_id, bob, 20);
+    assert!(verify_property_integrity(property_id));
+    assert_eq!(issue_shares(property_id, bob, 51, None), Err("Not enough shares or property not found".to_string()));
+    set_caller(alice);
+    transfer_shares(property_id, alice, bob, 10).unwrap();
+    assert!(verify_property_integrity(property_id));
+    // A holding the property's counters don't account for breaks the invariant
+    OWNERSHIP.with(|own| *own.borrow_mut().get_mut(&(property_id, bob)).unwrap() += 1);
+    assert!(!verify_property_integrity(property_id));
+    assert!(!verify_property_integrity(property_id + 1));
+}
+
+#[test]
+fn locked_shares_move_only_after_the_lockup() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    issue_shares(property_id, alice, 10, Some(3_600)).unwrap();
+    let until = get_lockup(property_id, alice);
+    assert_eq!(until, env::now() + 3_600 * 1_000_000_000);
+    let locked = Err(format!("Shares are locked until {}", until));
+
+    set_caller(alice);
+    assert_eq!(transfer_shares(property_id, alice, bob, 5), locked);
+    assert_eq!(list_shares_for_sale(property_id, 5, 1, None, None), locked);
+
+    env::advance_secs(3_599);
+    assert_eq!(transfer_shares(property_id, alice, bob, 5), locked);
+    env::advance_secs(1);
+    assert_eq!(transfer_shares(property_id, alice, bob, 5), Ok("Shares transferred".to_string()));
+    assert_eq!(get_ownership(property_id, bob), 5);
+}
+
+#[test]
+fn holdings_stop_at_the_ownership_cap() {
+    setup();
+    let (alice, bob, carol) = (holder(2), holder(3), holder(4));
+    let property_id = register_property("Capped".to_string(), 100, metadata(), Some(1_000), 0).unwrap().id;
+    let over_cap = Err("Exceeds ownership cap".to_string());
+    assert_eq!(issue_shares(property_id, alice, 11, None), over_cap);
+    issue(property_id, alice, 10);
+    assert_eq!(issue_shares(property_id, alice, 1, None), over_cap);
+    issue(property_id, bob, 10);
+    issue(property_id, carol, 5);
+
+    set_caller(carol);
+    assert_eq!(transfer_shares(property_id, carol, alice, 1), over_cap);
+    set_caller(bob);
+    transfer_shares(property_id, bob, carol, 5).unwrap();
+    assert_eq!(get_ownership(property_id, carol), 10);
+
+    fund(alice, 100);
+    let listing_id = list(property_id, carol, 5, 1);
+    set_caller(alice);
+    assert_eq!(buy_shares(listing_id, alice, 1), over_cap);
+    assert_eq!(can_buy_shares(listing_id, alice, 1), Err(RwaError::OwnershipCapExceeded));
+    assert_eq!(get_ownership(property_id, alice), 10);
+    assert_eq!(get_balance(alice), 100);
+}
+
+#[test]
+fn self_views_show_only_the_callers_data() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    let first = new_property(100);
+    let second = new_property(100);
+    issue(first, alice, 30);
+    issue(second, alice, 20);
+    issue(first, bob, 10);
+    let listing_id = list(first, alice, 5, 3);
+    list(first, bob, 5, 3);
+
+    set_caller(alice);
+    let statement: Vec<(PropertyId, u64)> = get_my_ownership_statement().iter().map(|r| (r.property_id, r.shares)).collect();
+    assert_eq!(statement, vec![(first, 30), (second, 20)]);
+    let listings: Vec<u64> = get_my_listings().iter().map(|l| l.listing_id).collect();
+    assert_eq!(listings, vec![listing_id]);
+
+    set_caller(user(9));
+    assert!(get_my_ownership_statement().is_empty());
+    assert!(get_my_listings().is_empty());
+}
+
+#[test]
+fn frozen_properties_block_movement_and_claims_until_unfrozen() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    issue(property_id, alice, 50);
+    fund(bob, 100);
+    let listing_id = list(property_id, alice, 10, 1);
+    set_caller(admin());
+    set_property_frozen(property_id, true).unwrap();
+    // Income still accrues while frozen
+    deposit_rental_income(property_id, 100).unwrap();
+    let frozen = "Property is frozen".to_string();
+
+    set_caller(alice);
+    assert_eq!(transfer_shares(property_id, alice, bob, 5), Err(frozen.clone()));
+    assert_eq!(list_shares_for_sale(property_id, 5, 1, None, None), Err(frozen.clone()));
+    assert_eq!(claim_income(property_id), Err(frozen.clone()));
+    set_caller(bob);
+    assert_eq!(buy_shares(listing_id, bob, 5), Err(frozen));
+
+    set_caller(admin());
+    set_property_frozen(property_id, false).unwrap();
+    set_caller(bob);
+    buy_shares(listing_id, bob, 5).unwrap();
+    set_caller(alice);
+    transfer_shares(property_id, alice, bob, 5).unwrap();
+    assert_eq!(claim_income(property_id), Ok(50));
+    assert_eq!(get_ownership(property_id, bob), 10);
+}
+
+#[test]
+fn frozen_properties_block_redemption() {
+    setup();
+    let alice = holder(2);
+    let property_id = new_property(100);
+    issue(property_id, alice, 50);
+    set_caller(admin());
+    update_property_status(property_id, PropertyStatus::Sold).unwrap();
+    set_sale_proceeds(property_id, 1_000).unwrap();
+    set_property_frozen(property_id, true).unwrap();
+
+    set_caller(alice);
+    assert_eq!(redeem_shares(property_id), Err("Property is frozen".to_string()));
+    assert_eq!(get_ownership(property_id, alice), 50);
+    assert_eq!(get_balance(alice), 0);
+
+    set_caller(admin());
+    set_property_frozen(property_id, false).unwrap();
+    set_caller(alice);
+    assert_eq!(redeem_shares(property_id), Ok(500));
+}
+
+#[test]
+fn batch_issuance_is_all_or_nothing() {
+    setup();
+    let (alice, bob, carol) = (holder(2), holder(3), holder(4));
+    let property_id = new_property(100);
+    assert_eq!(batch_issue_shares(property_id, vec![(alice, 40), (bob, 40), (carol, 21)]), Err("Not enough shares available for batch".to_string()));
+    // One bad recipient fails the whole batch too
+    assert_eq!(batch_issue_shares(property_id, vec![(alice, 10), (user(9), 10)]), Err("Recipient is not KYC verified".to_string()));
+    assert_eq!(get_property(property_id).unwrap().shares_available, 100);
+    assert!(get_property_holders(property_id, 0, 10).is_empty());
+
+    assert_eq!(batch_issue_shares(property_id, vec![(alice, 40), (bob, 40), (alice, 5)]), Ok("Issued shares to 2 recipients".to_string()));
+    assert_eq!((get_ownership(property_id, alice), get_ownership(property_id, bob)), (45, 40));
+    assert_eq!(get_property(property_id).unwrap().shares_available, 15);
+    set_caller(alice);
+    assert_eq!(batch_issue_shares(property_id, vec![(carol, 1)]), Err("Only admin can batch issue shares".to_string()));
+}
+
+#[test]
+fn holders_are_listed_largest_first() {
+    setup();
+    let (alice, bob, carol) = (holder(2), holder(3), holder(4));
+    let property_id = new_property(100);
+    issue(property_id, alice, 10);
+    issue(property_id, bob, 50);
+    issue(property_id, carol, 30);
+    assert_eq!(get_property_holders(property_id, 0, 10), vec![(bob, 50), (carol, 30), (alice, 10)]);
+    assert_eq!(get_property_holders(property_id, 1, 1), vec![(carol, 30)]);
+    assert!(get_property_holders(property_id, 3, 10).is_empty());
+    assert!(get_property_holders(property_id + 1, 0, 10).is_empty());
+}
+
+/// Holder indexes as maintained, compared with a fresh scan of OWNERSHIP.
+fn assert_indexes_consistent() {
+    let maintained = (HOLDERS_BY_PROPERTY.with(|h| h.borrow().clone()), PROPERTIES_BY_HOLDER.with(|p| p.borrow().clone()));
+    rebuild_ownership_indexes();
+    let rebuilt = (HOLDERS_BY_PROPERTY.with(|h| h.borrow().clone()), PROPERTIES_BY_HOLDER.with(|p| p.borrow().clone()));
+    let non_empty = |(holders, properties): (HashMap<PropertyId, HashSet<Principal>>, HashMap<Principal, HashSet<PropertyId>>)| {
+        (holders.into_iter().filter(|(_, s)| !s.is_empty()).collect::<HashMap<_, _>>(), properties.into_iter().filter(|(_, s)| !s.is_empty()).collect::<HashMap<_, _>>())
+    };
+    assert_eq!(non_empty(maintained), non_empty(rebuilt));
+}
+
+#[test]
+fn holder_indexes_follow_issues_transfers_and_purchases() {
+    setup();
+    let (alice, bob, carol) = (holder(2), holder(3), holder(4));
+    let first = new_property(100);
+    let second = new_property(100);
+    issue(first, alice, 30);
+    issue(second, alice, 10);
+    assert_indexes_consistent();
+
+    set_caller(alice);
+    transfer_shares(first, alice, bob, 30).unwrap();
+    assert_indexes_consistent();
+    assert_eq!(get_property_holders(first, 0, 10), vec![(bob, 30)]);
+
+    fund(carol, 100);
+    let listing_id = list(second, alice, 10, 1);
+    set_caller(carol);
+    buy_shares(listing_id, carol, 10).unwrap();
+    assert_indexes_consistent();
+    assert!(get_ownership_statement(alice).is_empty());
+    let statement: Vec<PropertyId> = get_ownership_statement(carol).iter().map(|r| r.property_id).collect();
+    assert_eq!(statement, vec![second]);
+}
+
+#[test]
+fn supply_counts_follow_issuance() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    assert_eq!((get_total_shares(property_id), get_circulating_shares(property_id), get_holder_count(property_id)), (100, 0, 0));
+    issue(property_id, alice, 25);
+    issue(property_id, bob, 15);
+    assert_eq!((get_total_shares(property_id), get_circulating_shares(property_id), get_holder_count(property_id)), (100, 40, 2));
+    assert_eq!((get_total_shares(property_id + 1), get_circulating_shares(property_id + 1), get_holder_count(property_id + 1)), (0, 0, 0));
+}
+
+#[test]
+fn burns_retire_or_destroy_shares() {
+    setup();
+    let alice = holder(2);
+    let property_id = new_property(100);
+    issue(property_id, alice, 30);
+    // Permanent burns are admin-only, so the admin needs shares of its own
+    issue(property_id, holder(1), 20);
+
+    set_caller(alice);
+    assert_eq!(burn_shares(property_id, 31, false), Err("Not enough unlisted shares to burn".to_string()));
+    assert_eq!(burn_shares(property_id, 5, true), Err("Only admin can permanently burn shares".to_string()));
+    assert_eq!(burn_shares(property_id, 10, false), Ok("Shares burned".to_string()));
+    let property = get_property(property_id).unwrap();
+    assert_eq!((property.total_shares, property.shares_available), (100, 60));
+    assert_eq!(get_ownership(property_id, alice), 20);
+
+    set_caller(admin());
+    burn_shares(property_id, 20, true).unwrap();
+    let property = get_property(property_id).unwrap();
+    assert_eq!((property.total_shares, property.shares_available), (80, 60));
+    assert_eq!(get_ownership(property_id, admin()), 0);
+    assert!(verify_property_integrity(property_id));
+}
+
+#[test]
+fn icrc1_views_match_ownership_and_supply() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    issue(property_id, alice, 30);
+    set_caller(alice);
+    transfer_shares(property_id, alice, bob, 12).unwrap();
+    for owner in [alice, bob, user(9)] {
+        assert_eq!(icrc1_balance_of(property_id, owner), get_ownership(property_id, owner));
+    }
+    assert_eq!(icrc1_balance_of(property_id, bob), 12);
+    assert_eq!(icrc1_total_supply(property_id), get_total_shares(property_id));
+    assert_eq!(icrc1_total_supply(property_id), 100);
+}
+
+#[test]
+fn transfers_get_increasing_indexes_and_keep_their_memo() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    issue(property_id, alice, 30);
+    set_caller(alice);
+    let first = transfer_shares_v2(property_id, alice, bob, 5, Some(b"invoice 7".to_vec())).unwrap();
+    let second = transfer_shares_v2(property_id, alice, bob, 3, None).unwrap();
+    assert_eq!(second, first + 1);
+    let record = get_transfer(first).unwrap();
+    assert_eq!((record.tx_index, record.property_id, record.from, record.to, record.amount), (first, property_id, alice, bob, 5));
+    assert_eq!(record.timestamp, env::now());
+    assert_eq!(record.memo, Some(b"invoice 7".to_vec()));
+    assert_eq!(get_transfer(second).unwrap().memo, None);
+    assert!(get_transfer(second + 1).is_none());
+    assert_eq!(transfer_shares_v2(property_id, alice, bob, 1, Some(vec![0; MAX_MEMO_LEN + 1])), Err("Memo cannot exceed 32 bytes".to_string()));
+}
+
+#[test]
+fn spenders_move_shares_within_their_allowance() {
+    setup();
+    let (owner, spender, to) = (holder(2), holder(3), holder(4));
+    let property_id = new_property(100);
+    issue(property_id, owner, 10);
+    set_caller(owner);
+    assert_eq!(approve_shares(property_id, owner, 5), Err("Cannot approve yourself".to_string()));
+    approve_shares(property_id, spender, 20).unwrap();
+
+    set_caller(spender);
+    assert_eq!(transfer_from(property_id, owner, to, 21), Err("Insufficient allowance".to_string()));
+    transfer_from(property_id, owner, to, 6).unwrap();
+    assert_eq!(get_allowance(property_id, owner, spender), 14);
+    assert_eq!((get_ownership(property_id, owner), get_ownership(property_id, to)), (4, 6));
+    // The owner's balance limits the spend as well as the allowance
+    assert_eq!(transfer_from(property_id, owner, to, 5), Err("Not enough unlisted shares to transfer".to_string()));
+    assert_eq!(get_allowance(property_id, owner, spender), 14);
+    set_caller(to);
+    assert_eq!(transfer_from(property_id, owner, to, 1), Err("Insufficient allowance".to_string()));
+}
+
+#[test]
+fn issuing_the_last_share_marks_the_property_fully_subscribed() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    issue(property_id, alice, 99);
+    assert!(!is_fully_subscribed(property_id));
+    assert_eq!(get_property(property_id).unwrap().fully_subscribed_at, None);
+    env::advance_secs(5);
+    issue(property_id, bob, 1);
+    assert!(is_fully_subscribed(property_id));
+    assert_eq!(get_property(property_id).unwrap().fully_subscribed_at, Some(env::now()));
+    set_caller(admin());
+    let events = get_events(0, MAX_PAGE_SIZE).unwrap();
+    assert_eq!(events.iter().filter(|e| e.event_type == EventType::PropertyFullySubscribed).count(), 1);
+    assert!(!is_fully_subscribed(property_id + 1));
+}
+
+#[test]
+fn cancelled_offerings_reset_every_balance() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    issue_shares(property_id, alice, 30, Some(3_600)).unwrap();
+    issue(property_id, bob, 20);
+    list(property_id, bob, 5, 2);
+
+    set_caller(alice);
+    assert_eq!(cancel_offering(property_id), Err("Only admin can cancel offerings".to_string()));
+    set_caller(admin());
+    assert_eq!(cancel_offering(property_id), Ok("Offering cancelled; reclaimed shares from 2 holders".to_string()));
+    assert_eq!((get_ownership(property_id, alice), get_ownership(property_id, bob)), (0, 0));
+    assert_eq!(get_property(property_id).unwrap().shares_available, 100);
+    assert!(get_property_holders(property_id, 0, 10).is_empty());
+    assert!(get_marketplace_listings().is_empty());
+    assert_eq!(get_lockup(property_id, alice), 0);
+    assert!(verify_property_integrity(property_id));
+
+    let full = new_property(10);
+    issue(full, alice, 10);
+    assert_eq!(cancel_offering(full), Err("Offering is no longer in its subscription phase".to_string()));
+}
+
+#[test]
+fn admins_force_transfers_past_freezes_pauses_and_lockups() {
+    setup();
+    let (lost, heir) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    issue_shares(property_id, lost, 30, Some(3_600)).unwrap();
+    set_property_frozen(property_id, true).unwrap();
+    // Holders pause transfers by proposal; set the flag directly here
+    PROPERTIES.with(|props| props.borrow_mut().get_mut(&property_id).unwrap().transfers_paused = true);
+
+    set_caller(heir);
+    assert_eq!(admin_force_transfer(property_id, lost, heir, 30, "Court order".to_string()), Err("Only admin can force transfers".to_string()));
+    set_caller(admin());
+    assert_eq!(admin_force_transfer(property_id, lost, heir, 30, " ".to_string()), Err("A reason is required for forced transfers".to_string()));
+    assert_eq!(admin_force_transfer(property_id, lost, heir, 31, "Court order".to_string()), Err("Not enough shares to transfer".to_string()));
+    assert_eq!(admin_force_transfer(property_id, lost, heir, 30, "Court order 12".to_string()), Ok("Shares force-transferred".to_string()));
+    assert_eq!((get_ownership(property_id, lost), get_ownership(property_id, heir)), (0, 30));
+
+    let last = get_events(0, MAX_PAGE_SIZE).unwrap().pop().unwrap();
+    assert!(last.event_type == EventType::SharesForceTransferred);
+    assert!(last.details.ends_with(": Court order 12"));
+}
+
+#[test]
+fn forced_transfers_are_logged_and_respect_the_whitelist() {
+    setup();
+    let (lost, heir, outsider) = (holder(2), holder(3), holder(4));
+    let property_id = new_property(100);
+    issue(property_id, lost, 30);
+    set_caller(admin());
+    add_to_whitelist(property_id, heir).unwrap();
+    assert_eq!(admin_force_transfer(property_id, lost, outsider, 10, "Court order".to_string()), Err("Recipient is not whitelisted for this property".to_string()));
+
+    let reason = "Probate order 2026-0041 issued by the county court".to_string();
+    admin_force_transfer(property_id, lost, heir, 10, reason.clone()).unwrap();
+    let record = get_transfer(0).unwrap();
+    assert_eq!((record.from, record.to, record.amount, record.fee), (lost, heir, 10, 0));
+    assert_eq!(record.memo, Some(reason.as_bytes()[..MAX_MEMO_LEN].to_vec()));
+    let last = get_events(0, MAX_PAGE_SIZE).unwrap().pop().unwrap();
+    assert!(last.details.ends_with(&reason));
+}
+
+#[test]
+fn past_balances_are_found_between_changes() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    let before = env::now() - 1;
+    issue(property_id, alice, 50);
+    let issued_at = env::now();
+    env::advance_secs(10);
+    set_caller(alice);
+    transfer_shares(property_id, alice, bob, 20).unwrap();
+    let moved_at = env::now();
+    env::advance_secs(10);
+    burn_shares(property_id, 5, false).unwrap();
+    let burned_at = env::now();
+
+    assert_eq!(get_balance_at(property_id, alice, before), 0);
+    assert_eq!(get_balance_at(property_id, alice, issued_at), 50);
+    assert_eq!(get_balance_at(property_id, alice, moved_at - 1), 50);
+    assert_eq!(get_balance_at(property_id, alice, moved_at), 30);
+    assert_eq!(get_balance_at(property_id, alice, burned_at - 1), 30);
+    assert_eq!(get_balance_at(property_id, alice, burned_at + 1_000), 25);
+    assert_eq!(get_balance_at(property_id, bob, moved_at - 1), 0);
+    assert_eq!(get_balance_at(property_id, bob, burned_at), 20);
+}
+
+#[test]
+fn transfer_fees_are_withheld_in_shares() {
+    setup();
+    let (alice, bob) = (holder(2), holder(3));
+    let property_id = new_property(100);
+    issue(property_id, alice, 80);
+    assert_eq!(get_transfer_fee_bps(), 0);
+    set_transfer_fee_bps(500).unwrap();
+
+    set_caller(alice);
+    let tx_index = transfer_shares_v2(property_id, alice, bob, 40, None).unwrap();
+    assert_eq!((get_ownership(property_id, alice), get_ownership(property_id, bob)), (40, 38));
+    assert_eq!(get_transfer(tx_index).unwrap().fee, 2);
+    assert_eq!(get_property(property_id).unwrap().shares_available, 20 + 2);
+    // 5% of 30 rounds down to 1
+    transfer_shares(property_id, alice, bob, 30).unwrap();
+    assert_eq!(get_ownership(property_id, bob), 38 + 29);
+    assert!(verify_property_integrity(property_id));
+    assert_eq!(set_transfer_fee_bps(0), Err("Only admin can set the transfer fee".to_string()));
+}
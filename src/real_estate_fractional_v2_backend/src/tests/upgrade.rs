@@ -0,0 +1,136 @@
+use super::*;
+use candid::{Decode, Encode};
+
+/// Encodes the snapshot `pre_upgrade` would write.
+fn snapshot_bytes() -> Vec<u8> {
+    Encode!(&VersionedState::V1(StableState::capture())).unwrap()
+}
+
+#[test]
+fn state_survives_save_and_restore() {
+    setup();
+    let alice = holder(2);
+    let property_id = new_property(1_000);
+    issue(property_id, alice, 250);
+    set_caller(admin());
+    deposit_rental_income(property_id, 1_000).unwrap();
+    let bytes = snapshot_bytes();
+
+    // A fresh thread has fresh thread_locals, like a freshly upgraded canister
+    std::thread::spawn(move || {
+        assert!(get_property(property_id).is_none());
+        restore_snapshot(Decode!(&bytes, VersionedState).unwrap());
+        let property = get_property(property_id).unwrap();
+        assert_eq!(property.total_shares, 1_000);
+        assert_eq!(property.shares_available, 750);
+        assert_eq!(get_ownership(property_id, alice), 250);
+        assert_eq!(get_unclaimed_income(property_id, alice), 250);
+        assert_eq!(get_role(&admin()), Role::Admin);
+        assert!(is_kyc_verified(&alice));
+        assert_eq!(get_property_holders(property_id, 0, 10), vec![(alice, 250)]);
+        assert_eq!(get_property_by_name(property.name).map(|p| p.id), Some(property_id));
+        assert!(verify_property_integrity(property_id));
+    })
+    .join()
+    .unwrap();
+}
+
+#[test]
+fn restored_counters_continue_where_they_left_off() {
+    setup();
+    let first = new_property(10);
+    let bytes = snapshot_bytes();
+    std::thread::spawn(move || {
+        restore_snapshot(Decode!(&bytes, VersionedState).unwrap());
+        env::set_caller(admin());
+        let second = register_property("Second".to_string(), 10, metadata(), None, 0).unwrap();
+        assert_eq!(second.id, first + 1);
+    })
+    .join()
+    .unwrap();
+}
+
+#[test]
+fn class_issued_counts_are_rebuilt_after_restore() {
+    setup();
+    let alice = holder(2);
+    let property_id = new_property(100);
+    let classes = vec![
+        ShareClass { name: "Preferred".to_string(), total: 40, income_weight_bps: 5_000 },
+        ShareClass { name: "Common".to_string(), total: 60, income_weight_bps: 5_000 },
+    ];
+    set_share_classes(property_id, classes).unwrap();
+    issue_class_shares(property_id, 0, alice, 40).unwrap();
+    issue_class_shares(property_id, 1, alice, 30).unwrap();
+    let bytes = snapshot_bytes();
+    std::thread::spawn(move || {
+        restore_snapshot(Decode!(&bytes, VersionedState).unwrap());
+        assert_eq!(class_issued(property_id, 0), 40);
+        assert_eq!(class_issued(property_id, 1), 30);
+        env::set_caller(admin());
+        deposit_rental_income(property_id, 1_200).unwrap();
+        // Preferred is fully issued; half of common's 600 belongs to unissued shares
+        assert_eq!(get_unclaimed_income(property_id, alice), 600 + 300);
+        assert_eq!(get_reserve(property_id), 300);
+    })
+    .join()
+    .unwrap();
+}
+
+/// The original V1 layout, without any of the fields added since.
+#[derive(CandidType)]
+struct OriginalState {
+    properties: HashMap<PropertyId, Property>,
+    ownership: HashMap<(PropertyId, Principal), u64>,
+    next_property_id: PropertyId,
+    rental_income: HashMap<PropertyId, u64>,
+    unclaimed_income: HashMap<(PropertyId, Principal), u64>,
+    marketplace: Vec<Listing>,
+    admins: Vec<Principal>,
+    roles: HashMap<Principal, Role>,
+    kyc: HashMap<Principal, u64>,
+    bootstrapped: bool,
+    proposals: HashMap<u64, Proposal>,
+    next_proposal_id: u64,
+    events: Vec<Event>,
+}
+
+#[derive(CandidType)]
+enum OriginalVersionedState {
+    V1(OriginalState),
+}
+
+#[test]
+fn snapshots_without_later_fields_still_restore() {
+    setup();
+    let alice = holder(2);
+    let property_id = new_property(100);
+    issue(property_id, alice, 40);
+    let state = OriginalState {
+        properties: PROPERTIES.with(|s| s.borrow().clone()),
+        ownership: OWNERSHIP.with(|s| s.borrow().clone()),
+        next_property_id: NEXT_PROPERTY_ID.with(|s| *s.borrow()),
+        rental_income: HashMap::new(),
+        unclaimed_income: HashMap::new(),
+        marketplace: Vec::new(),
+        admins: ADMINS.with(|s| s.borrow().clone()),
+        roles: ROLES.with(|s| s.borrow().clone()),
+        kyc: KYC.with(|s| s.borrow().clone()),
+        bootstrapped: true,
+        proposals: HashMap::new(),
+        next_proposal_id: 1,
+        events: Vec::new(),
+    };
+    let bytes = Encode!(&OriginalVersionedState::V1(state)).unwrap();
+    std::thread::spawn(move || {
+        restore_snapshot(Decode!(&bytes, VersionedState).unwrap());
+        assert_eq!(get_ownership(property_id, alice), 40);
+        assert_eq!(get_property_holders(property_id, 0, 10), vec![(alice, 40)]);
+        // Settings missing from the snapshot keep their initial values
+        assert_eq!(get_quorum_bps(), 1_000);
+        assert!(is_strict_kyc_enabled());
+        assert!(get_transfer(0).is_none());
+    })
+    .join()
+    .unwrap();
+}